@@ -17,6 +17,7 @@ struct Suite {
 struct Test {
     id: u32,
     input: String,
+    #[serde(default)]
     output: String,
     name: String,
     enabled: Option<bool>,
@@ -52,14 +53,24 @@ fn write_test(test_file: &mut File, directory: &DirEntry) {
             }
             let test_name = format!("{}_{}_{}", suite.name, test.name, test.id,);
 
-            write!(
-                test_file,
-                include_str!("./tests/spec_test_template"),
-                name = test_name,
-                path = directory.display(),
-                input = test.input,
-                output = test.output,
-            )
+            // specs with an `err` expect `rep` to fail with that message
+            match test.err {
+                Some(err) => write!(
+                    test_file,
+                    include_str!("./tests/spec_error_template"),
+                    name = test_name,
+                    input = test.input,
+                    err = err,
+                ),
+                None => write!(
+                    test_file,
+                    include_str!("./tests/spec_test_template"),
+                    name = test_name,
+                    path = directory.display(),
+                    input = test.input,
+                    output = test.output,
+                ),
+            }
             .unwrap();
         }
     }
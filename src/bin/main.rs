@@ -1,30 +1,46 @@
-use std::fs;
+use std::env;
+use std::path::PathBuf;
 
 use rustyline::error::ReadlineError;
 use rustyline::{DefaultEditor, Result};
 
 use clap::Parser;
 
-use flox::chunk::Chunk;
-use flox::compiler::{compile, Compiler};
-use flox::vm::{VMErr, VirtualMachine};
+use flox::vm::VMErr;
+use flox::Interpreter;
 
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
 struct Args {
     #[clap(short, long)]
     debug: bool,
+    /// Directory searched for modules, can be repeated. The directories in
+    /// FLOX_PATH are searched after these.
+    #[clap(short = 'I', long = "path")]
+    path: Vec<PathBuf>,
     file: Option<String>,
 }
 
-fn repl(debug: bool) -> Result<()> {
+fn interpreter(args: &Args) -> Interpreter {
+    let mut interp = Interpreter::new(args.debug);
+    let mut search_path = args.path.clone();
+    if let Some(flox_path) = env::var_os("FLOX_PATH") {
+        search_path.extend(env::split_paths(&flox_path));
+    }
+    interp.set_search_path(search_path);
+    interp
+}
+
+fn print_warnings(interp: &mut Interpreter) {
+    for warning in interp.take_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+fn repl(mut interp: Interpreter) -> Result<()> {
     let mut rl = DefaultEditor::new()?;
     let _ = rl.load_history(".flang-history").is_err();
     let prompt: String = "user> ".to_string();
-    let mut vm = VirtualMachine::new(debug);
-
-    let mut comp = Compiler::new(None);
-    let mut chunk = Chunk::new("test chunk");
 
     loop {
         let line = rl.readline(&prompt);
@@ -34,21 +50,22 @@ fn repl(debug: bool) -> Result<()> {
                 let _ = rl.add_history_entry(line.as_str());
                 rl.save_history(".flox-history").unwrap();
 
-                if let Err(err) = compile(&line, &mut chunk, &mut comp) {
-                    println!("{}", err);
-                    continue;
-                };
-                if debug {
-                    println!("{}", chunk);
-                }
-
-                match vm.run(&chunk) {
-                    Ok(v) => println!("{}", v),
-                    Err(VMErr::RuntimeError(s)) => {
+                let result = interp.eval(&line);
+                print_warnings(&mut interp);
+                match result.and_then(|v| interp.realize(&v)) {
+                    Ok(v) => println!("{}", v.readable()),
+                    Err(VMErr::CompileError(s)) => {
+                        println!("{}", s);
+                        continue;
+                    }
+                    Err(VMErr::RuntimeError(_, s)) => {
                         println!("Error: {}", s);
                         continue;
                     }
-                    _ => continue,
+                    Err(VMErr::Exception(v)) => {
+                        println!("Uncaught: {}", v.readable());
+                        continue;
+                    }
                 };
             }
             Err(err) => {
@@ -58,23 +75,19 @@ fn repl(debug: bool) -> Result<()> {
     }
 }
 
-fn run_file(filename: String, debug: bool) {
-    let source = fs::read_to_string(filename).unwrap();
-
-    let mut chunk = Chunk::new("test chunk");
-    let mut comp = Compiler::new(None);
-    compile(&source, &mut chunk, &mut comp).unwrap();
-    println!("{}", chunk);
-    let mut vm = VirtualMachine::new(debug);
-    println!("{:?}", vm.run(&chunk));
+fn run_file(filename: String, mut interp: Interpreter) {
+    let result = interp.eval_file(&PathBuf::from(filename));
+    print_warnings(&mut interp);
+    println!("{:?}", result.and_then(|v| interp.realize(&v)));
 }
 
 fn main() {
     let args = Args::parse();
-    if args.file.is_some() {
-        run_file(args.file.unwrap(), args.debug);
+    let interp = interpreter(&args);
+    if let Some(file) = args.file {
+        run_file(file, interp);
     } else {
-        match repl(args.debug) {
+        match repl(interp) {
             Ok(_) => {}
             Err(err) => match err {
                 ReadlineError::Eof => {}
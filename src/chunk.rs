@@ -2,6 +2,8 @@ use std::fmt;
 use std::fmt::Write as _;
 
 pub mod closure;
+pub mod generic;
+pub mod lazy;
 pub mod object;
 pub mod value;
 pub use value::Value;
@@ -20,6 +22,9 @@ macro_rules! constant {
     }};
 }
 
+/// The largest constant index, slot or count an instruction can hold.
+pub const MAX_INDEX: usize = u16::MAX as usize;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     OpRet,
@@ -31,6 +36,9 @@ pub enum OpCode {
     OpSub,
     OpMul,
     OpDiv,
+    OpQuot,
+    OpRem,
+    OpMod,
     OpNil,
     OpTrue,
     OpFalse,
@@ -54,6 +62,31 @@ pub enum OpCode {
     OpSetUpvalue,
     OpClosure,
     OpPrint,
+    OpPop,
+    OpGetGlobal,
+    OpSetGlobal,
+    OpVector,
+    OpMap,
+    OpTry,
+    OpEndTry,
+    OpThrow,
+    OpReset,
+    OpShift,
+    OpResume,
+    OpYield,
+    OpInstance,
+    OpIsInstance,
+    OpGetField,
+    OpSetField,
+    OpIsType,
+    OpLength,
+    OpNth,
+    OpHasKey,
+    OpGetKey,
+    OpNoMatch,
+    OpUnpack,
+    OpSeq,
+    OpNext,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -154,6 +187,46 @@ impl Chunk {
         self.code[idx] = Element::Constant(constant);
     }
 
+    /// Jump targets take two elements, so that chunks with several top-level
+    /// forms can grow past 256 elements.
+    pub fn write_jump_target(&mut self, target: usize, line: usize) {
+        self.write(Element::Constant((target >> 8 & 0xFF) as u8), line);
+        self.write(Element::Constant((target & 0xFF) as u8), line);
+    }
+
+    pub fn rewrite_jump_target(&mut self, idx: usize, target: usize) {
+        self.code[idx] = Element::Constant((target >> 8 & 0xFF) as u8);
+        self.code[idx + 1] = Element::Constant((target & 0xFF) as u8);
+    }
+
+    pub fn get_jump_target(&self, index: usize) -> usize {
+        (self.get_constant_index(index) << 8) + self.get_constant_index(index + 1)
+    }
+
+    /// Constant indices, local and upvalue slots, field slots and element
+    /// counts take two elements, so a chunk can use up to `MAX_INDEX + 1` of
+    /// each.
+    pub fn write_index(&mut self, index: usize, line: usize) {
+        self.write(Element::Constant((index >> 8 & 0xFF) as u8), line);
+        self.write(Element::Constant((index & 0xFF) as u8), line);
+    }
+
+    pub fn get_index(&self, at: usize) -> usize {
+        (self.get_constant_index(at) << 8) + self.get_constant_index(at + 1)
+    }
+
+    /// Writes an instruction pushing constant `idx`, `OpConstLong` once the
+    /// index no longer fits in a byte.
+    pub fn write_load_constant(&mut self, idx: usize, line: usize) {
+        if idx <= u8::MAX as usize {
+            self.write_opcode(OpCode::OpConst, line);
+            self.write_constant(idx as u8, line);
+        } else {
+            self.write_opcode(OpCode::OpConstLong, line);
+            self.write_constant_long(idx, line);
+        }
+    }
+
     pub fn write_constant_long(&mut self, constant: usize, line: usize) {
         self.write(Element::Constant((constant >> 16 & 0xFF) as u8), line);
         self.write(Element::Constant((constant >> 8 & 0xFF) as u8), line);
@@ -203,6 +276,10 @@ impl Chunk {
         self.constants.len() - 1
     }
 
+    pub fn constant_count(&self) -> usize {
+        self.constants.len()
+    }
+
     pub fn get_opcode(&self, index: usize) -> Option<&OpCode> {
         let op = self.code.get(index)?;
 
@@ -230,6 +307,12 @@ impl Chunk {
         (idx, &self.constants[idx])
     }
 
+    /// The constant whose index is written at `index` by `write_index`.
+    pub fn get_wide_constant(&self, index: usize) -> (usize, &Value) {
+        let idx: usize = self.get_index(index);
+        (idx, &self.constants[idx])
+    }
+
     pub fn get_constant_long(&self, index: usize) -> Option<&Value> {
         let c1 = self.code[index].get_constant()?;
         let c2 = self.code[index + 1].get_constant()?;
@@ -264,20 +347,53 @@ impl Chunk {
                 let value = self.get_constant_long(index + 1).unwrap();
                 (format!("{:?} '{}'\n", opcode, value), 4)
             }
-            OpCode::OpSetLocal | OpCode::OpSetUpvalue => {
+            OpCode::OpCall | OpCode::OpResume => {
                 let n = self.get_constant_index(index + 1);
                 (format!("{:?} {}\n", opcode, n), 2)
             }
-            OpCode::OpGetLocal | OpCode::OpGetUpvalue => {
-                let n = self.get_constant_index(index + 1);
-                (format!("{:?} {}\n", opcode, n), 2)
+            OpCode::OpSetLocal
+            | OpCode::OpSetUpvalue
+            | OpCode::OpGetLocal
+            | OpCode::OpGetUpvalue
+            | OpCode::OpVector
+            | OpCode::OpMap
+            | OpCode::OpNth => {
+                let n = self.get_index(index + 1);
+                (format!("{:?} {}\n", opcode, n), 3)
+            }
+            OpCode::OpInstance | OpCode::OpIsInstance => {
+                let (n, c) = self.get_wide_constant(index + 1);
+                (format!("{:?} {}:'{}'\n", opcode, n, c), 3)
+            }
+            OpCode::OpGetField | OpCode::OpSetField => {
+                let (n, c) = self.get_wide_constant(index + 1);
+                let slot = self.get_index(index + 3);
+                (format!("{:?} {}:'{}' {}\n", opcode, n, c, slot), 5)
             }
-            OpCode::OpJmpIfFalse | OpCode::OpJmp => {
-                let idx = self.get_constant_index(index + 1);
-                (format!("{:?}: {}\n", opcode, idx), 2)
+            OpCode::OpUnpack => {
+                let count = self.get_index(index + 1);
+                let rest = self.get_constant_index(index + 3);
+                (format!("{:?} {} {}\n", opcode, count, rest), 4)
+            }
+            OpCode::OpNext => {
+                let slot = self.get_index(index + 1);
+                let idx = self.get_jump_target(index + 3);
+                (format!("{:?} {}: {}\n", opcode, slot, idx), 5)
+            }
+            OpCode::OpGetGlobal
+            | OpCode::OpSetGlobal
+            | OpCode::OpIsType
+            | OpCode::OpHasKey
+            | OpCode::OpGetKey => {
+                let (n, c) = self.get_wide_constant(index + 1);
+                (format!("{:?} {}:'{}'\n", opcode, n, c), 3)
+            }
+            OpCode::OpJmpIfFalse | OpCode::OpJmp | OpCode::OpTry => {
+                let idx = self.get_jump_target(index + 1);
+                (format!("{:?}: {}\n", opcode, idx), 3)
             }
             OpCode::OpClosure => {
-                let (_, function) = self.get_constant(index + 1);
+                let (_, function) = self.get_wide_constant(index + 1);
                 let function = function.get_function().unwrap();
                 //let upvalues_fmt = 0..function.upvalue_count upvalues
                 //    .iter()
@@ -288,7 +404,7 @@ impl Chunk {
                 //    format!("{:?} {}:'{} \n{}\n", opcode, n, closure, upvalues_fmt),
                 //    dbg!(2 + 2 * upvalues.len()),
                 //)
-                (format!("{:?}\n", opcode), 2 + 2 * function.upvalue_count)
+                (format!("{:?}\n", opcode), 3 + 3 * function.upvalue_count)
             }
             _ => (format!("{:?}\n", opcode), 1),
         };
@@ -299,6 +415,7 @@ impl Chunk {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -366,7 +483,7 @@ mod tests {
     #[test]
     fn test_chunk_rewrite_constant() {
         let mut chunk = Chunk::new("test_chunk");
-        let idx = chunk.add_constant(Value::Number(2.0));
+        let idx = chunk.add_constant(Value::Float(2.0));
         chunk.write_constant(idx as u8, 0);
         assert_eq!(chunk.get_current_index().ok(), Some(0));
 
@@ -380,6 +497,14 @@ mod tests {
         chunk.get_opcode(0);
     }
 
+    #[test]
+    fn test_chunk_write_index() {
+        let mut chunk = Chunk::new("test_chunk");
+        chunk.write_index(300, 0);
+        assert_eq!(chunk.get_current_index().ok(), Some(1));
+        assert_eq!(chunk.get_index(0), 300);
+    }
+
     #[test]
     fn test_chunk_write_constant_long() {
         let mut chunk = Chunk::new("test_chunk");
@@ -1,14 +1,27 @@
 use crate::chunk::object::Function;
+use crate::chunk::value::Value;
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// A captured variable. Locals live in cells so that closures and the frame
+/// that declared them observe each other's writes.
+#[derive(Clone, Debug)]
 pub struct ObjUpvalue {
-    pub location: usize,
+    pub cell: Rc<RefCell<Value>>,
 }
 
+impl PartialEq for ObjUpvalue {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.cell, &other.cell)
+    }
+}
+
+impl Eq for ObjUpvalue {}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Closure {
-    pub function: Box<Function>,
+    pub function: Rc<Function>,
     pub upvalues: Vec<ObjUpvalue>,
 }
 
@@ -25,11 +38,13 @@ mod tests {
 
     fn fixture_closure() -> Closure {
         Closure {
-            function: Box::new(Function {
+            function: Rc::new(Function {
                 arity: 2,
                 chunk: Chunk::new("test_chunk"),
                 name: "test_closure".to_string(),
                 upvalue_count: 0,
+                rest: false,
+                keys: Vec::new(),
             }),
             upvalues: vec![],
         }
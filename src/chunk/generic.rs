@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+
+/// The built-in types a method can specialize on. `number` covers the
+/// integers, ratios and floats.
+pub const TYPES: &[&str] = &[
+    "nil",
+    "bool",
+    "integer",
+    "ratio",
+    "float",
+    "number",
+    "char",
+    "string",
+    "keyword",
+    "list",
+    "vector",
+    "map",
+    "function",
+    "error",
+    "coroutine",
+    "lazy-seq",
+];
+
+/// A method of a generic function. A specializer is nil for a parameter that
+/// takes anything, a keyword naming one of `TYPES` or a struct type.
+#[derive(Clone)]
+pub struct Method {
+    pub specializers: Vec<Value>,
+    pub function: Value,
+}
+
+impl Method {
+    pub fn applies(&self, args: &[Value]) -> bool {
+        self.specializers
+            .iter()
+            .zip(args)
+            .all(|(specializer, arg)| matches(specializer, arg))
+    }
+
+    /// Orders methods from the most specific, comparing the parameters from
+    /// left to right.
+    fn compare(&self, other: &Method) -> Ordering {
+        let ranks = self.specializers.iter().map(specificity);
+        ranks.cmp(other.specializers.iter().map(specificity))
+    }
+}
+
+pub struct Generic {
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub methods: RefCell<Vec<Method>>,
+}
+
+impl Generic {
+    pub fn new(name: &str, arity: usize) -> Generic {
+        Generic {
+            name: name.into(),
+            arity,
+            methods: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Adds a method, replacing the one with the same specializers.
+    pub fn add(&self, method: Method) {
+        let mut methods = self.methods.borrow_mut();
+        match methods
+            .iter_mut()
+            .find(|other| other.specializers == method.specializers)
+        {
+            Some(other) => *other = method,
+            None => methods.push(method),
+        }
+    }
+
+    /// The functions of the methods that apply to `args`, most specific
+    /// first. Ties keep the order the methods were defined in.
+    pub fn applicable(&self, args: &[Value]) -> Vec<Value> {
+        let mut methods: Vec<Method> = self
+            .methods
+            .borrow()
+            .iter()
+            .filter(|method| method.applies(args))
+            .cloned()
+            .collect();
+        methods.sort_by(Method::compare);
+        methods.into_iter().map(|method| method.function).collect()
+    }
+}
+
+impl fmt::Debug for Generic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(generic {})", self.name)
+    }
+}
+
+/// What `next-method` calls inside a method: the less specific methods that
+/// also apply to the arguments of the call.
+pub struct NextMethod {
+    pub name: Rc<str>,
+    pub methods: Vec<Value>,
+    pub args: Vec<Value>,
+}
+
+impl fmt::Debug for NextMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(next-method {})", self.name)
+    }
+}
+
+/// The built-in type of a value, as named in `TYPES`. Instances and struct
+/// types have none.
+pub fn type_name(value: &Value) -> Option<&'static str> {
+    Some(match value {
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::Int(_) => "integer",
+        Value::Float(_) => "float",
+        Value::Char(_) => "char",
+        Value::Obj(obj) => match &**obj {
+            Object::BigInt(_) => "integer",
+            Object::Ratio(_) => "ratio",
+            Object::Str(_) => "string",
+            Object::Keyword(_) => "keyword",
+            Object::List(_) => "list",
+            Object::Vector(_) => "vector",
+            Object::Map(_) => "map",
+            Object::Function(_)
+            | Object::Closure(_)
+            | Object::Native(_)
+            | Object::Continuation(_)
+            | Object::Generic(_)
+            | Object::NextMethod(_) => "function",
+            Object::Error(_) => "error",
+            Object::Coroutine(_) => "coroutine",
+            Object::LazySeq(_) => "lazy-seq",
+            Object::Struct(_) | Object::Instance(_) => return None,
+        },
+    })
+}
+
+/// Whether `specializer` is nil, a keyword in `TYPES` or a struct type.
+pub fn is_specializer(specializer: &Value) -> bool {
+    match specializer.get_keyword() {
+        Some(keyword) => TYPES.contains(&&**keyword),
+        None => specializer.is_nil() || specializer.get_struct().is_some(),
+    }
+}
+
+/// Whether `arg` has the type `specializer` stands for.
+pub fn matches(specializer: &Value, arg: &Value) -> bool {
+    if let Some(struct_type) = specializer.get_struct() {
+        return arg
+            .get_instance()
+            .is_some_and(|instance| instance.is_a(&struct_type));
+    }
+    match specializer.get_keyword().map(|keyword| &**keyword) {
+        Some("number") => arg.is_number(),
+        Some(name) => type_name(arg) == Some(name),
+        None => true,
+    }
+}
+
+fn specificity(specializer: &Value) -> u8 {
+    match specializer.get_keyword().map(|keyword| &**keyword) {
+        _ if specializer.is_nil() => 2,
+        Some("number") => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::object::intern;
+
+    fn keyword(name: &str) -> Value {
+        Value::Obj(Box::new(Object::Keyword(intern(name))))
+    }
+
+    fn method(specializers: Vec<Value>, id: i64) -> Method {
+        Method {
+            specializers,
+            function: Value::Int(id),
+        }
+    }
+
+    #[test]
+    fn test_most_specific_first() {
+        let generic = Generic::new("describe", 2);
+        generic.add(method(vec![Value::Nil, Value::Nil], 0));
+        generic.add(method(vec![keyword("number"), Value::Nil], 1));
+        generic.add(method(vec![keyword("integer"), Value::Nil], 2));
+        generic.add(method(vec![Value::Nil, keyword("integer")], 3));
+        let args = [Value::Int(1), Value::Int(2)];
+        let order = vec![Value::Int(2), Value::Int(1), Value::Int(3), Value::Int(0)];
+        assert_eq!(generic.applicable(&args), order);
+        assert_eq!(
+            generic.applicable(&[Value::Float(1.0), Value::Nil]),
+            vec![Value::Int(1), Value::Int(0)]
+        );
+    }
+
+    #[test]
+    fn test_redefine_method() {
+        let generic = Generic::new("describe", 1);
+        generic.add(method(vec![keyword("string")], 0));
+        generic.add(method(vec![keyword("string")], 1));
+        assert_eq!(generic.methods.borrow().len(), 1);
+        assert!(generic.applicable(&[Value::Int(1)]).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::chunk::value::Value;
+
+/// How to produce the first item of a lazy sequence and the sequence of the
+/// rest. The VM realizes a step at most once and then remembers it as `Cons`
+/// or `Empty`, so walking a sequence twice does not redo the work.
+#[derive(Clone)]
+pub enum Step {
+    Empty,
+    Cons(Value, Rc<LazySeq>),
+    /// `(lazy-seq body)`, `body` is a function of no arguments returning a
+    /// collection or nil.
+    Thunk(Value),
+    /// The items of a list, vector, map or string from `index` on.
+    Items(Rc<Vec<Value>>, usize),
+    /// Numbers from `start` by `step`, up to but not including `end`.
+    Range {
+        start: Value,
+        end: Option<Value>,
+        step: Value,
+    },
+    /// `x`, then `(f x)`, `(f (f x))` and so on. `applied` tells whether `f`
+    /// still has to be applied to `x` to get the first item.
+    Iterate {
+        f: Value,
+        x: Value,
+        applied: bool,
+    },
+    Repeat(Value, Option<usize>),
+    Take(usize, Rc<LazySeq>),
+    Drop(usize, Rc<LazySeq>),
+    Map(Value, Rc<LazySeq>),
+    Filter(Value, Rc<LazySeq>),
+}
+
+pub struct LazySeq {
+    pub step: RefCell<Step>,
+}
+
+impl LazySeq {
+    pub fn new(step: Step) -> Rc<LazySeq> {
+        Rc::new(LazySeq {
+            step: RefCell::new(step),
+        })
+    }
+}
+
+/// Realized sequences are chains of `Cons` cells, so dropping the first cell
+/// could drop the whole chain recursively and overflow the stack on long
+/// sequences. The cells that are not shared are unlinked in a loop instead.
+impl Drop for LazySeq {
+    fn drop(&mut self) {
+        let mut step = std::mem::replace(self.step.get_mut(), Step::Empty);
+        loop {
+            let next = match step {
+                Step::Cons(_, next)
+                | Step::Take(_, next)
+                | Step::Drop(_, next)
+                | Step::Map(_, next)
+                | Step::Filter(_, next) => next,
+                _ => break,
+            };
+            match Rc::try_unwrap(next) {
+                Ok(mut seq) => step = std::mem::replace(seq.step.get_mut(), Step::Empty),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for LazySeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(lazy-seq)")
+    }
+}
@@ -1,6 +1,17 @@
 use crate::chunk::closure::Closure;
+use crate::chunk::generic::{Generic, NextMethod};
+use crate::chunk::lazy::LazySeq;
+use crate::chunk::value::Value;
 use crate::chunk::Chunk;
+use crate::vm::{Continuation, Coroutine, VMErr, VirtualMachine};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct Function {
@@ -8,6 +19,13 @@ pub struct Function {
     pub chunk: Chunk,
     pub upvalue_count: usize,
     pub arity: usize,
+    /// Whether the function has a `& rest` parameter, which takes the local
+    /// slot after the positional ones and receives a list of the other
+    /// arguments.
+    pub rest: bool,
+    /// The `&key` parameters, which take the local slots after the
+    /// positional ones.
+    pub keys: Vec<Rc<str>>,
 }
 
 impl fmt::Debug for Function {
@@ -16,11 +34,211 @@ impl fmt::Debug for Function {
     }
 }
 
+/// Natives get their arguments mutably so that they can take them out
+/// instead of cloning them.
+pub type NativeFn = fn(&mut VirtualMachine, &mut [Value]) -> Result<Value, VMErr>;
+
+/// A function implemented in Rust. `arity` is `None` for variadic functions.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Option<usize>,
+    pub function: NativeFn,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(native {})", self.name)
+    }
+}
+
+/// A structured error value, built with `error` or from a runtime error of
+/// the VM when it is caught. `kind` tells errors apart: `type-error`,
+/// `arity-error`, `division-by-zero`, `undefined-symbol`, `runtime-error`,
+/// `compile-error`, `assertion-failed` or whatever `error` was given.
+/// `payload` is a map with details.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorObject {
+    pub kind: Rc<str>,
+    pub message: Rc<str>,
+    pub payload: Value,
+}
+
+impl ErrorObject {
+    pub fn new(kind: &str, message: &str, payload: HashMap<Value, Value>) -> ErrorObject {
+        ErrorObject {
+            kind: intern(kind),
+            message: message.into(),
+            payload: Value::Obj(Box::new(Object::Map(Rc::new(payload)))),
+        }
+    }
+}
+
+/// A record type defined with `defstruct`. Instances are only instances of
+/// the exact type they were made with, redefining a struct makes a new type.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct StructType {
+    pub name: Rc<str>,
+    pub fields: Vec<Rc<str>>,
+}
+
+impl StructType {
+    pub fn constructor(&self) -> String {
+        format!("make-{}", self.name)
+    }
+
+    pub fn predicate(&self) -> String {
+        format!("{}?", self.name)
+    }
+
+    pub fn getter(&self, slot: usize) -> String {
+        format!("{}-{}", self.name, self.fields[slot])
+    }
+
+    pub fn setter(&self, slot: usize) -> String {
+        format!("set-{}-{}!", self.name, self.fields[slot])
+    }
+}
+
+/// A record made by a struct constructor. The compiler resolves field names
+/// to slots, copies of an instance share its slots.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub struct_type: Rc<StructType>,
+    pub slots: Rc<RefCell<Vec<Value>>>,
+}
+
+impl Instance {
+    pub fn new(struct_type: Rc<StructType>, slots: Vec<Value>) -> Instance {
+        Instance {
+            struct_type,
+            slots: Rc::new(RefCell::new(slots)),
+        }
+    }
+
+    pub fn is_a(&self, struct_type: &Rc<StructType>) -> bool {
+        Rc::ptr_eq(&self.struct_type, struct_type)
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_a(&other.struct_type)
+            && (Rc::ptr_eq(&self.slots, &other.slots)
+                || *self.slots.borrow() == *other.slots.borrow())
+    }
+}
+
+impl Eq for Instance {}
+
+impl Hash for Instance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.struct_type.name.hash(state);
+        self.slots.borrow().hash(state);
+    }
+}
+
+thread_local! {
+    static STRINGS: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// Returns the shared copy of `s`, so that equal string literals and names
+/// point at the same allocation.
+pub fn intern(s: &str) -> Rc<str> {
+    STRINGS.with(|strings| {
+        let mut strings = strings.borrow_mut();
+        match strings.get(s) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Rc<str> = Rc::from(s);
+                strings.insert(interned.clone());
+                interned
+            }
+        }
+    })
+}
+
 #[derive(Debug, Clone)]
 pub enum Object {
-    Str(String),
-    Function(Box<Function>),
+    BigInt(BigInt),
+    Ratio(BigRational),
+    /// Strings are immutable, so copies share the same buffer.
+    Str(Rc<str>),
+    Function(Rc<Function>),
     Closure(Box<Closure>),
+    Native(NativeFunction),
+    /// Collections are shared between copies and copied on write, with
+    /// `Rc::make_mut`, so passing them around does not copy their items.
+    List(Rc<Vec<Value>>),
+    Vector(Rc<Vec<Value>>),
+    Map(Rc<HashMap<Value, Value>>),
+    Error(ErrorObject),
+    Continuation(Rc<Continuation>),
+    Coroutine(Rc<RefCell<Coroutine>>),
+    LazySeq(Rc<LazySeq>),
+    Struct(Rc<StructType>),
+    Instance(Instance),
+    /// `:name`, names are interned so keywords compare by pointer.
+    Keyword(Rc<str>),
+    Generic(Rc<Generic>),
+    NextMethod(Rc<NextMethod>),
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::BigInt(i1), Object::BigInt(i2)) => i1 == i2,
+            (Object::Ratio(r1), Object::Ratio(r2)) => r1 == r2,
+            (Object::Str(s1), Object::Str(s2)) => Rc::ptr_eq(s1, s2) || s1 == s2,
+            (Object::Function(f1), Object::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (Object::Closure(c1), Object::Closure(c2)) => {
+                Rc::ptr_eq(&c1.function, &c2.function) && c1.upvalues == c2.upvalues
+            }
+            (Object::Native(n1), Object::Native(n2)) => n1.name == n2.name,
+            (Object::List(l1), Object::List(l2)) => l1 == l2,
+            (Object::Vector(v1), Object::Vector(v2)) => v1 == v2,
+            (Object::Map(m1), Object::Map(m2)) => m1 == m2,
+            (Object::Error(e1), Object::Error(e2)) => e1 == e2,
+            (Object::Continuation(k1), Object::Continuation(k2)) => Rc::ptr_eq(k1, k2),
+            (Object::Coroutine(c1), Object::Coroutine(c2)) => Rc::ptr_eq(c1, c2),
+            (Object::LazySeq(s1), Object::LazySeq(s2)) => Rc::ptr_eq(s1, s2),
+            (Object::Struct(t1), Object::Struct(t2)) => Rc::ptr_eq(t1, t2),
+            (Object::Instance(i1), Object::Instance(i2)) => i1 == i2,
+            (Object::Keyword(k1), Object::Keyword(k2)) => Rc::ptr_eq(k1, k2) || k1 == k2,
+            (Object::Generic(g1), Object::Generic(g2)) => Rc::ptr_eq(g1, g2),
+            (Object::NextMethod(n1), Object::NextMethod(n2)) => Rc::ptr_eq(n1, n2),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Object {}
+
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Object::BigInt(i) => i.hash(state),
+            Object::Ratio(r) => r.hash(state),
+            Object::Str(s) => s.hash(state),
+            Object::Function(f) => f.name.hash(state),
+            Object::Closure(c) => c.function.name.hash(state),
+            Object::Native(n) => n.name.hash(state),
+            Object::List(l) => l.hash(state),
+            Object::Vector(v) => v.hash(state),
+            // entry order is unspecified, so only the size contributes
+            Object::Map(m) => m.len().hash(state),
+            Object::Error(e) => e.hash(state),
+            Object::Continuation(k) => Rc::as_ptr(k).hash(state),
+            Object::Coroutine(c) => Rc::as_ptr(c).hash(state),
+            Object::LazySeq(s) => Rc::as_ptr(s).hash(state),
+            Object::Struct(t) => t.name.hash(state),
+            Object::Instance(i) => i.hash(state),
+            Object::Keyword(k) => k.hash(state),
+            Object::Generic(g) => g.name.hash(state),
+            Object::NextMethod(n) => n.name.hash(state),
+        }
+    }
 }
 
 impl Object {
@@ -31,7 +249,7 @@ impl Object {
         }
     }
 
-    pub fn get_function(&self) -> Option<Box<Function>> {
+    pub fn get_function(&self) -> Option<Rc<Function>> {
         match self {
             Object::Function(f) => Some(f.clone()),
             _ => None,
@@ -45,6 +263,97 @@ impl Object {
         }
     }
 
+    pub fn get_native(&self) -> Option<&NativeFunction> {
+        match self {
+            Object::Native(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn get_list(&self) -> Option<&Vec<Value>> {
+        match self {
+            Object::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn get_vector(&self) -> Option<&Vec<Value>> {
+        match self {
+            Object::Vector(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn get_map(&self) -> Option<&HashMap<Value, Value>> {
+        match self {
+            Object::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn get_error(&self) -> Option<&ErrorObject> {
+        match self {
+            Object::Error(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub fn get_continuation(&self) -> Option<Rc<Continuation>> {
+        match self {
+            Object::Continuation(k) => Some(k.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_coroutine(&self) -> Option<Rc<RefCell<Coroutine>>> {
+        match self {
+            Object::Coroutine(c) => Some(c.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_lazy_seq(&self) -> Option<Rc<LazySeq>> {
+        match self {
+            Object::LazySeq(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_struct(&self) -> Option<Rc<StructType>> {
+        match self {
+            Object::Struct(t) => Some(t.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_instance(&self) -> Option<&Instance> {
+        match self {
+            Object::Instance(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn get_keyword(&self) -> Option<&Rc<str>> {
+        match self {
+            Object::Keyword(k) => Some(k),
+            _ => None,
+        }
+    }
+
+    pub fn get_generic(&self) -> Option<Rc<Generic>> {
+        match self {
+            Object::Generic(g) => Some(g.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_next_method(&self) -> Option<Rc<NextMethod>> {
+        match self {
+            Object::NextMethod(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
     pub fn is_function(&self) -> bool {
         matches!(self, Object::Function(_))
     }
@@ -55,17 +364,20 @@ impl Object {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::chunk::Chunk;
 
     fn fixture_closure() -> Closure {
         Closure {
-            function: Box::new(Function {
+            function: Rc::new(Function {
                 arity: 2,
                 chunk: Chunk::new("test_chunk"),
                 name: "test_closure".to_string(),
                 upvalue_count: 2,
+                rest: false,
+                keys: Vec::new(),
             }),
             upvalues: vec![],
         }
@@ -73,11 +385,19 @@ mod tests {
 
     #[test]
     fn test_object_get_str_with_value() {
-        let object = Object::Str("some string".to_string());
+        let object = Object::Str(Rc::from("some string"));
 
         assert_eq!(object.get_str(), Some("some string"))
     }
 
+    #[test]
+    fn test_intern() {
+        let s1 = intern("ola");
+        let s2 = intern(&String::from("ola"));
+        assert!(Rc::ptr_eq(&s1, &s2));
+        assert_eq!(Object::Str(s1), Object::Str(Rc::from("ola")));
+    }
+
     #[test]
     fn test_object_get_str_without_value() {
         let object = Object::Closure(Box::new(fixture_closure()));
@@ -97,7 +417,7 @@ mod tests {
 
     #[test]
     fn test_object_get_function_without_value() {
-        let object = Object::Str("some string".to_string());
+        let object = Object::Str(Rc::from("some string"));
 
         assert_eq!(object.get_closure(), None)
     }
@@ -105,7 +425,7 @@ mod tests {
     #[test]
     fn test_object_is_function() {
         let function = Object::Closure(Box::new(fixture_closure()));
-        let string = Object::Str("some string".to_string());
+        let string = Object::Str(Rc::from("some string"));
 
         assert_eq!(function.is_closure(), true);
         assert_eq!(string.is_closure(), false);
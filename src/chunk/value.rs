@@ -1,22 +1,117 @@
+use itertools::Itertools;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Sub};
+use std::rc::Rc;
 
 use crate::chunk::closure::Closure;
-use crate::chunk::object::{Function, Object};
-
+use crate::chunk::generic::{Generic, NextMethod};
+use crate::chunk::lazy::LazySeq;
+use crate::chunk::object::{ErrorObject, Function, Instance, NativeFunction, Object, StructType};
+use crate::vm::{Continuation, Coroutine};
+
+/// A flox value.
+///
+/// Numbers are either exact (integers, bignums and rationals) or inexact
+/// (floats). Arithmetic on exact numbers stays exact: integers that overflow
+/// an `i64` are promoted to an `Object::BigInt`, and dividing integers that do
+/// not divide evenly produces an `Object::Ratio`. Results are always
+/// normalized, so a bignum that fits an `i64` becomes an `Int` again and a
+/// ratio with a denominator of one becomes an integer. Anything involving a
+/// float produces a float.
+///
+/// Integers print without a fractional part, rationals print as `1/3` and
+/// floats always print with a fractional part, so `(+ 1 1)` prints `2` and
+/// `(+ 1.0 1)` prints `2.0`.
 #[derive(Clone)]
 pub enum Value {
-    Number(f64),
+    Int(i64),
+    Float(f64),
+    Char(char),
     Bool(bool),
     Nil,
     Obj(Box<Object>),
 }
 
 impl Value {
+    /// Returns any number as a float, rounding exact numbers if needed.
     pub fn get_number(&self) -> Option<f64> {
         match self {
-            Value::Number(f) => Some(*f),
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Obj(obj) => match &**obj {
+                Object::BigInt(i) => i.to_f64(),
+                Object::Ratio(r) => r.to_f64(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Builds an integer value, using an `Int` whenever it fits.
+    pub fn from_bigint(i: BigInt) -> Value {
+        match i.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::Obj(Box::new(Object::BigInt(i))),
+        }
+    }
+
+    /// Builds a rational value, using an integer when the denominator is one.
+    pub fn from_ratio(r: BigRational) -> Value {
+        if r.is_integer() {
+            Value::from_bigint(r.to_integer())
+        } else {
+            Value::Obj(Box::new(Object::Ratio(r)))
+        }
+    }
+
+    /// Returns an exact integer (`Int` or bignum) as a `BigInt`.
+    pub fn get_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(i) => Some(BigInt::from(*i)),
+            Value::Obj(obj) => match &**obj {
+                Object::BigInt(i) => Some(i.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns any exact number as a `BigRational`.
+    pub fn get_ratio(&self) -> Option<BigRational> {
+        match self {
+            Value::Obj(obj) => match &**obj {
+                Object::Ratio(r) => Some(r.clone()),
+                _ => self.get_bigint().map(BigRational::from_integer),
+            },
+            _ => self.get_bigint().map(BigRational::from_integer),
+        }
+    }
+
+    pub fn get_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn get_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn get_char(&self) -> Option<char> {
+        match self {
+            Value::Char(c) => Some(*c),
             _ => None,
         }
     }
@@ -35,7 +130,7 @@ impl Value {
         }
     }
 
-    pub fn get_function(&self) -> Option<Box<Function>> {
+    pub fn get_function(&self) -> Option<Rc<Function>> {
         match self {
             Value::Obj(obj) => obj.get_function(),
             _ => None,
@@ -49,18 +144,141 @@ impl Value {
         }
     }
 
+    pub fn get_native(&self) -> Option<&NativeFunction> {
+        match self {
+            Value::Obj(obj) => obj.get_native(),
+            _ => None,
+        }
+    }
+
+    pub fn get_list(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Obj(obj) => obj.get_list(),
+            _ => None,
+        }
+    }
+
+    pub fn get_vector(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Obj(obj) => obj.get_vector(),
+            _ => None,
+        }
+    }
+
+    pub fn get_map(&self) -> Option<&HashMap<Value, Value>> {
+        match self {
+            Value::Obj(obj) => obj.get_map(),
+            _ => None,
+        }
+    }
+
+    pub fn get_continuation(&self) -> Option<Rc<Continuation>> {
+        match self {
+            Value::Obj(obj) => obj.get_continuation(),
+            _ => None,
+        }
+    }
+
+    pub fn get_coroutine(&self) -> Option<Rc<RefCell<Coroutine>>> {
+        match self {
+            Value::Obj(obj) => obj.get_coroutine(),
+            _ => None,
+        }
+    }
+
+    pub fn get_lazy_seq(&self) -> Option<Rc<LazySeq>> {
+        match self {
+            Value::Obj(obj) => obj.get_lazy_seq(),
+            _ => None,
+        }
+    }
+
+    pub fn get_struct(&self) -> Option<Rc<StructType>> {
+        match self {
+            Value::Obj(obj) => obj.get_struct(),
+            _ => None,
+        }
+    }
+
+    pub fn get_instance(&self) -> Option<&Instance> {
+        match self {
+            Value::Obj(obj) => obj.get_instance(),
+            _ => None,
+        }
+    }
+
+    pub fn get_keyword(&self) -> Option<&Rc<str>> {
+        match self {
+            Value::Obj(obj) => obj.get_keyword(),
+            _ => None,
+        }
+    }
+
+    pub fn get_generic(&self) -> Option<Rc<Generic>> {
+        match self {
+            Value::Obj(obj) => obj.get_generic(),
+            _ => None,
+        }
+    }
+
+    pub fn get_next_method(&self) -> Option<Rc<NextMethod>> {
+        match self {
+            Value::Obj(obj) => obj.get_next_method(),
+            _ => None,
+        }
+    }
+
+    pub fn get_error(&self) -> Option<&ErrorObject> {
+        match self {
+            Value::Obj(obj) => obj.get_error(),
+            _ => None,
+        }
+    }
+
     pub fn is_nil(&self) -> bool {
         matches!(self, Value::Nil)
     }
 
     pub fn is_number(&self) -> bool {
-        matches!(self, Value::Number(_))
+        self.number_rank().is_some()
+    }
+
+    /// True for integers, bignums and rationals.
+    pub fn is_exact(&self) -> bool {
+        matches!(self.number_rank(), Some(rank) if rank < 3)
+    }
+
+    /// Position of a number in the numeric tower, values of different rank are
+    /// promoted to the higher one before arithmetic.
+    fn number_rank(&self) -> Option<u8> {
+        match self {
+            Value::Int(_) => Some(0),
+            Value::Float(_) => Some(3),
+            Value::Obj(obj) => match &**obj {
+                Object::BigInt(_) => Some(1),
+                Object::Ratio(_) => Some(2),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Value::Int(_))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Value::Float(_))
     }
 
     pub fn is_bool(&self) -> bool {
         matches!(self, Value::Bool(_))
     }
 
+    pub fn is_char(&self) -> bool {
+        matches!(self, Value::Char(_))
+    }
+
     pub fn is_function(&self) -> bool {
         match self {
             Value::Obj(f) => f.is_function(),
@@ -76,38 +294,407 @@ impl Value {
     }
 }
 
+/// Nil, which natives leave behind when they take an argument out.
+impl Default for Value {
+    fn default() -> Value {
+        Value::Nil
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
+        if let Ok(ordering) = self.compare_numbers(other) {
+            return ordering == Some(Ordering::Equal);
+        }
         match (self, other) {
             (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
-            (Value::Number(b1), Value::Number(b2)) => b1 == b2,
-            _ => panic!(),
+            (Value::Char(c1), Value::Char(c2)) => c1 == c2,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Obj(o1), Value::Obj(o2)) => o1 == o2,
+            _ => false,
         }
     }
 }
 
 impl Eq for Value {}
 
-impl PartialOrd for Value {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Int(i) => (0, i).hash(state),
+            // a float is equal to the integer it converts to losslessly, so they
+            // must hash the same, this also makes 0.0 and -0.0 hash the same
+            Value::Float(f) if exact_int(*f).is_some() => (0, exact_int(*f).unwrap()).hash(state),
+            Value::Float(f) => (1, f.to_bits()).hash(state),
+            // bignums and rationals compare equal to floats through their float
+            // approximation, and are never equal to an `Int` since they are
+            // normalized
+            Value::Obj(_) if self.is_number() => {
+                (1, self.get_number().unwrap().to_bits()).hash(state)
+            }
+            Value::Bool(b) => (2, b).hash(state),
+            Value::Nil => 3.hash(state),
+            Value::Char(c) => (5, c).hash(state),
+            Value::Obj(obj) => {
+                4.hash(state);
+                obj.hash(state)
+            }
+        }
+    }
+}
+
+impl Value {
+    /// A total order over all values: values of different types are ordered by
+    /// type, values of the same type by their contents. Used wherever values
+    /// need a deterministic order, such as printing map entries.
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Nil => 0,
+                Value::Bool(_) => 1,
+                Value::Int(_) | Value::Float(_) => 2,
+                Value::Char(_) => 3,
+                Value::Obj(obj) => match &**obj {
+                    Object::BigInt(_) | Object::Ratio(_) => 2,
+                    Object::Str(_) => 4,
+                    Object::List(_) => 5,
+                    Object::Vector(_) => 6,
+                    Object::Map(_) => 7,
+                    Object::Function(_)
+                    | Object::Closure(_)
+                    | Object::Native(_)
+                    | Object::Continuation(_)
+                    | Object::Coroutine(_)
+                    | Object::LazySeq(_)
+                    | Object::Struct(_)
+                    | Object::Generic(_)
+                    | Object::NextMethod(_) => 8,
+                    Object::Error(_) => 9,
+                    Object::Instance(_) => 10,
+                    Object::Keyword(_) => 11,
+                },
+            }
+        }
+
+        fn cmp_seq(s1: &[Value], s2: &[Value]) -> Ordering {
+            s1.iter()
+                .zip(s2.iter())
+                .map(|(v1, v2)| v1.total_cmp(v2))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| s1.len().cmp(&s2.len()))
+        }
+
+        if let Ok(operands) = operands("compare", self, other) {
+            return match operands {
+                Operands::Int(i1, i2) => i1.cmp(&i2),
+                Operands::Big(i1, i2) => i1.cmp(&i2),
+                Operands::Ratio(r1, r2) => r1.cmp(&r2),
+                Operands::Float(f1, f2) => self
+                    .compare_numbers(other)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| f1.total_cmp(&f2)),
+            };
+        }
+
         match (self, other) {
-            (Value::Bool(b1), Value::Bool(b2)) => Some(b1.cmp(b2)),
-            (Value::Number(n1), Value::Number(n2)) => n1.partial_cmp(n2),
-            _ => panic!(),
+            (Value::Bool(b1), Value::Bool(b2)) => b1.cmp(b2),
+            (Value::Char(c1), Value::Char(c2)) => c1.cmp(c2),
+            (Value::Obj(o1), Value::Obj(o2)) => match (&**o1, &**o2) {
+                (Object::Str(s1), Object::Str(s2)) => s1.cmp(s2),
+                (Object::Keyword(k1), Object::Keyword(k2)) => k1.cmp(k2),
+                (Object::List(l1), Object::List(l2)) => cmp_seq(l1, l2),
+                (Object::Vector(v1), Object::Vector(v2)) => cmp_seq(v1, v2),
+                (Object::Map(m1), Object::Map(m2)) => {
+                    let e1: Vec<Value> = sorted_entries(m1)
+                        .flat_map(|(k, v)| [k.clone(), v.clone()])
+                        .collect();
+                    let e2: Vec<Value> = sorted_entries(m2)
+                        .flat_map(|(k, v)| [k.clone(), v.clone()])
+                        .collect();
+                    cmp_seq(&e1, &e2)
+                }
+                (Object::Instance(i1), Object::Instance(i2)) => i1
+                    .struct_type
+                    .name
+                    .cmp(&i2.struct_type.name)
+                    .then_with(|| cmp_seq(&i1.slots.borrow(), &i2.slots.borrow())),
+                _ => rank(self)
+                    .cmp(&rank(other))
+                    .then_with(|| format!("{:?}", self).cmp(&format!("{:?}", other))),
+            },
+            _ => rank(self).cmp(&rank(other)),
         }
     }
 }
 
+/// Orders an exact number and a float by the exact value of the float, rather
+/// than by rounding the exact number to a float, so that `=` never holds for
+/// numbers that differ.
+fn compare_exact(exact: &Value, f: f64) -> Option<Ordering> {
+    if !f.is_finite() {
+        return exact.get_number()?.partial_cmp(&f);
+    }
+    match exact {
+        // rounding is monotonic, so a difference after rounding is real
+        Value::Int(i) if *i as f64 != f => (*i as f64).partial_cmp(&f),
+        Value::Int(i) => Some(match exact_int(f) {
+            Some(j) => i.cmp(&j),
+            // the float rounded up to 2^63
+            None => Ordering::Less,
+        }),
+        _ => Some(exact.get_ratio()?.cmp(&BigRational::from_float(f)?)),
+    }
+}
+
+/// The `i64` equal to `f`, when there is one.
+fn exact_int(f: f64) -> Option<i64> {
+    // -2^63 and 2^63 are exact as floats, the integral floats between them
+    // convert losslessly
+    (f.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&f))
+        .then_some(f as i64)
+}
+
+/// Map entries ordered by key, so that maps print and iterate deterministically.
+pub fn sorted_entries(map: &HashMap<Value, Value>) -> impl Iterator<Item = (&Value, &Value)> {
+    map.iter().sorted_by(|(k1, _), (k2, _)| k1.total_cmp(k2))
+}
+
+/// Operands of a binary arithmetic operation after numeric promotion.
+enum Operands {
+    Int(i64, i64),
+    Big(BigInt, BigInt),
+    Ratio(BigRational, BigRational),
+    Float(f64, f64),
+}
+
+fn operands(op: &str, x: &Value, y: &Value) -> Result<Operands, String> {
+    match (x.number_rank(), y.number_rank()) {
+        (Some(r1), Some(r2)) => Ok(match r1.max(r2) {
+            0 => Operands::Int(x.get_int().unwrap(), y.get_int().unwrap()),
+            1 => Operands::Big(x.get_bigint().unwrap(), y.get_bigint().unwrap()),
+            2 => Operands::Ratio(x.get_ratio().unwrap(), y.get_ratio().unwrap()),
+            _ => Operands::Float(x.get_number().unwrap(), y.get_number().unwrap()),
+        }),
+        _ => Err(format!("Cannot apply {} to {:?} and {:?}", op, x, y)),
+    }
+}
+
+/// Applies an arithmetic operation at the rank of its operands. `int` returns
+/// `None` on overflow, in which case the operation is retried with bignums.
+fn arithmetic(
+    op: &str,
+    x: &Value,
+    y: &Value,
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(BigInt, BigInt) -> BigInt,
+    ratio: fn(BigRational, BigRational) -> BigRational,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value, String> {
+    Ok(match operands(op, x, y)? {
+        Operands::Int(i1, i2) => match int(i1, i2) {
+            Some(i) => Value::Int(i),
+            None => Value::from_bigint(big(BigInt::from(i1), BigInt::from(i2))),
+        },
+        Operands::Big(i1, i2) => Value::from_bigint(big(i1, i2)),
+        Operands::Ratio(r1, r2) => Value::from_ratio(ratio(r1, r2)),
+        Operands::Float(f1, f2) => Value::Float(float(f1, f2)),
+    })
+}
+
+/// Exact division by zero is an error. When either operand is a float the
+/// division is done in floats and follows IEEE 754, so `(/ 1.0 0)` is infinity
+/// and `(mod 5 0.0)` is NaN.
+fn check_divisor(op: &str, dividend: &Value, divisor: &Value) -> Result<(), String> {
+    if dividend.is_exact_division_by_zero(divisor) {
+        return Err(format!("Division by zero in {}", op));
+    }
+    Ok(())
+}
+
+/// Adjusts a truncated remainder so it has the sign of the divisor.
+fn floor_mod(r: i64, divisor: i64) -> i64 {
+    if r != 0 && (r < 0) != (divisor < 0) {
+        r + divisor
+    } else {
+        r
+    }
+}
+
+impl Value {
+    /// Whether dividing by `divisor` is the division of exact numbers by zero,
+    /// which the division operations refuse.
+    pub fn is_exact_division_by_zero(&self, divisor: &Value) -> bool {
+        self.get_ratio().is_some() && divisor.get_ratio().is_some_and(|r| r.is_zero())
+    }
+
+    pub fn checked_add(&self, other: &Value) -> Result<Value, String> {
+        arithmetic(
+            "+",
+            self,
+            other,
+            i64::checked_add,
+            |x, y| x + y,
+            |x, y| x + y,
+            |x, y| x + y,
+        )
+    }
+
+    pub fn checked_sub(&self, other: &Value) -> Result<Value, String> {
+        arithmetic(
+            "-",
+            self,
+            other,
+            i64::checked_sub,
+            |x, y| x - y,
+            |x, y| x - y,
+            |x, y| x - y,
+        )
+    }
+
+    pub fn checked_mul(&self, other: &Value) -> Result<Value, String> {
+        arithmetic(
+            "*",
+            self,
+            other,
+            i64::checked_mul,
+            |x, y| x * y,
+            |x, y| x * y,
+            |x, y| x * y,
+        )
+    }
+
+    /// Division of exact numbers is exact, `(/ 1 3)` is the rational `1/3`.
+    pub fn checked_div(&self, other: &Value) -> Result<Value, String> {
+        check_divisor("/", self, other)?;
+        Ok(match operands("/", self, other)? {
+            Operands::Int(i1, i2) if i1.checked_rem(i2) == Some(0) => Value::Int(i1 / i2),
+            Operands::Int(i1, i2) => {
+                Value::from_ratio(BigRational::new(BigInt::from(i1), BigInt::from(i2)))
+            }
+            Operands::Big(i1, i2) => Value::from_ratio(BigRational::new(i1, i2)),
+            Operands::Ratio(r1, r2) => Value::from_ratio(r1 / r2),
+            Operands::Float(f1, f2) => Value::Float(f1 / f2),
+        })
+    }
+
+    /// Division truncated towards zero.
+    pub fn checked_quot(&self, other: &Value) -> Result<Value, String> {
+        check_divisor("quot", self, other)?;
+        arithmetic(
+            "quot",
+            self,
+            other,
+            i64::checked_div,
+            |x, y| x / y,
+            |x, y| (x / y).trunc(),
+            |x, y| (x / y).trunc(),
+        )
+    }
+
+    /// Remainder of `quot`, it has the sign of the dividend.
+    pub fn checked_rem(&self, other: &Value) -> Result<Value, String> {
+        check_divisor("rem", self, other)?;
+        arithmetic(
+            "rem",
+            self,
+            other,
+            i64::checked_rem,
+            |x, y| x % y,
+            |x, y| x % y,
+            |x, y| x % y,
+        )
+    }
+
+    /// Modulo of floored division, it has the sign of the divisor.
+    pub fn checked_mod(&self, other: &Value) -> Result<Value, String> {
+        check_divisor("mod", self, other)?;
+        arithmetic(
+            "mod",
+            self,
+            other,
+            |x, y| x.checked_rem(y).map(|r| floor_mod(r, y)),
+            |x, y| x.mod_floor(&y),
+            |x, y| x.clone() - y.clone() * (x / y).floor(),
+            |x, y| {
+                let r = x % y;
+                if r != 0.0 && (r < 0.0) != (y < 0.0) {
+                    r + y
+                } else {
+                    r
+                }
+            },
+        )
+    }
+
+    /// Orders two numbers, exact numbers are compared exactly. Returns `None`
+    /// when the numbers are unordered, as with NaN.
+    fn compare_numbers(&self, other: &Value) -> Result<Option<Ordering>, String> {
+        Ok(match operands("compare", self, other)? {
+            Operands::Int(i1, i2) => Some(i1.cmp(&i2)),
+            Operands::Big(i1, i2) => Some(i1.cmp(&i2)),
+            Operands::Ratio(r1, r2) => Some(r1.cmp(&r2)),
+            Operands::Float(f1, f2) => match (self, other) {
+                (Value::Float(_), Value::Float(_)) => f1.partial_cmp(&f2),
+                (Value::Float(f), exact) => compare_exact(exact, *f).map(Ordering::reverse),
+                (exact, _) => compare_exact(exact, f2),
+            },
+        })
+    }
+
+    /// Compares numbers with numbers, bools with bools and strings with strings.
+    /// Returns `None` when the values are unordered, as with NaN.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, String> {
+        match (self, other) {
+            (Value::Bool(b1), Value::Bool(b2)) => Ok(Some(b1.cmp(b2))),
+            (Value::Char(c1), Value::Char(c2)) => Ok(Some(c1.cmp(c2))),
+            _ if self.is_number() && other.is_number() => self.compare_numbers(other),
+            _ => match (self.get_str(), other.get_str()) {
+                (Some(s1), Some(s2)) => Ok(Some(s1.cmp(s2))),
+                _ => Err(format!("Cannot compare {:?} and {:?}", self, other)),
+            },
+        }
+    }
+
+    fn logical(&self, other: &Value, op: &str, f: fn(bool, bool) -> bool) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Bool(b1), Value::Bool(b2)) => Ok(Value::Bool(f(*b1, *b2))),
+            _ => Err(format!("Cannot apply {} to {:?} and {:?}", op, self, other)),
+        }
+    }
+
+    pub fn checked_and(&self, other: &Value) -> Result<Value, String> {
+        self.logical(other, "and", |b1, b2| b1 & b2)
+    }
+
+    pub fn checked_or(&self, other: &Value) -> Result<Value, String> {
+        self.logical(other, "or", |b1, b2| b1 | b2)
+    }
+
+    pub fn checked_xor(&self, other: &Value) -> Result<Value, String> {
+        self.logical(other, "xor", |b1, b2| b1 ^ b2)
+    }
+
+    pub fn checked_not(&self) -> Result<Value, String> {
+        match self {
+            Value::Bool(b) => Ok(Value::Bool(!b)),
+            _ => Err(format!("Cannot apply not to {:?}", self)),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare(other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
 impl Add for Value {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
-        match (&self, &other) {
-            (Value::Number(n1), Value::Number(n2)) => Value::Number(n1 + n2),
-            _ => {
-                panic!();
-            }
-        }
+        self.checked_add(&other)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -115,10 +702,8 @@ impl Sub for Value {
     type Output = Self;
 
     fn sub(self, other: Self) -> Self {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Value::Number(n1 - n2),
-            _ => panic!(),
-        }
+        self.checked_sub(&other)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -126,10 +711,8 @@ impl Mul for Value {
     type Output = Self;
 
     fn mul(self, other: Self) -> Self {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Value::Number(n1 * n2),
-            _ => panic!(),
-        }
+        self.checked_mul(&other)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -137,40 +720,32 @@ impl Div for Value {
     type Output = Self;
 
     fn div(self, other: Self) -> Self {
-        match (self, other) {
-            (Value::Number(n1), Value::Number(n2)) => Value::Number(n1 / n2),
-            _ => panic!(),
-        }
+        self.checked_div(&other)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 impl BitAnd for Value {
     type Output = Self;
     fn bitand(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Value::Bool(b1), Value::Bool(b2)) => Value::Bool(b1 & b2),
-            _ => panic!(),
-        }
+        self.checked_and(&rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 impl BitOr for Value {
     type Output = Self;
     fn bitor(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Value::Bool(b1), Value::Bool(b2)) => Value::Bool(b1 | b2),
-            _ => panic!(),
-        }
+        self.checked_or(&rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 impl BitXor for Value {
     type Output = Self;
     fn bitxor(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Value::Bool(b1), Value::Bool(b2)) => Value::Bool(b1 ^ b2),
-            _ => panic!(),
-        }
+        self.checked_xor(&rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -178,59 +753,109 @@ impl Not for Value {
     type Output = Self;
 
     fn not(self) -> Self::Output {
-        match self {
-            Value::Bool(b) => Value::Bool(!b),
-            _ => panic!(),
-        }
+        self.checked_not().unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+/// Names of the characters that are written by name, such as `#\newline`.
+pub const CHAR_NAMES: [(&str, char); 5] = [
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("nul", '\0'),
+];
+
+/// The reader syntax of a character: `#\a`, `#\newline` or `#\u{7}` for
+/// control characters without a name.
+pub fn char_literal(c: char) -> String {
+    match CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => format!("#\\{}", name),
+        None if c.is_control() => format!("#\\u{{{:X}}}", c as u32),
+        None => format!("#\\{}", c),
+    }
+}
+
+/// Floats always print with a fractional part so they can be told apart from
+/// integers, and infinities and NaN print as the `##inf`, `##-inf` and `##nan`
+/// literals that read back.
+fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "##nan".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "##inf" } else { "##-inf" }.to_string();
+    }
+    let s = format!("{}", f);
+    if s.contains('.') {
+        s
+    } else {
+        s + ".0"
     }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Number(value) => write!(f, "{:.1}", value),
-            Value::Bool(value) => write!(f, "{:1}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", format_float(*value)),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(c) => write!(f, "{}", char_literal(*c)),
             Value::Nil => write!(f, "nil"),
             Value::Obj(obj) => match &**obj {
-                Object::Str(s) => write!(f, "{:1}", s),
-                Object::Function(function) => write!(f, "{:?}", function),
-                Object::Closure(closure) => write!(f, "{:?}", closure),
+                Object::BigInt(i) => write!(f, "{}", i),
+                Object::Ratio(r) => write!(f, "{}", r),
+                Object::Str(s) => write!(f, "{}", s),
+                Object::Keyword(k) => write!(f, ":{}", k),
+                Object::Function(_)
+                | Object::Closure(_)
+                | Object::Native(_)
+                | Object::Error(_)
+                | Object::Continuation(_)
+                | Object::Coroutine(_)
+                | Object::LazySeq(_)
+                | Object::Struct(_)
+                | Object::Instance(_)
+                | Object::Generic(_)
+                | Object::NextMethod(_) => {
+                    write!(f, "{}", self.readable())
+                }
+                Object::List(list) => write!(f, "({})", list.iter().join(" ")),
+                Object::Vector(vector) => write!(f, "[{}]", vector.iter().join(" ")),
+                Object::Map(map) => write!(
+                    f,
+                    "{{{}}}",
+                    sorted_entries(map)
+                        .map(|(k, v)| format!("{} {}", k, v))
+                        .join(" ")
+                ),
             },
         }
     }
 }
 
+/// Values debug print in their readable form, see `printer::Readable`.
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Value::Number(value) => write!(f, "{}", value),
-            Value::Bool(value) => write!(f, "{}", value),
-            Value::Nil => write!(f, "nil"),
-            Value::Obj(obj) => match &**obj {
-                Object::Str(s) => write!(f, "{}", s),
-                Object::Function(function) => {
-                    write!(f, "{:?}", function)
-                }
-                Object::Closure(closure) => {
-                    write!(f, "{:?}", closure)
-                }
-            },
-        }
+        write!(f, "{}", self.readable())
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::chunk::Chunk;
 
     fn fixture_closure() -> Closure {
         Closure {
-            function: Box::new(Function {
+            function: Rc::new(Function {
                 arity: 2,
                 chunk: Chunk::new("test_chunk"),
                 name: "test_closure".to_string(),
                 upvalue_count: 0,
+                rest: false,
+                keys: Vec::new(),
             }),
             upvalues: vec![],
         }
@@ -238,7 +863,7 @@ mod tests {
 
     #[test]
     fn test_value_get_number() {
-        let number = Value::Number(1.0);
+        let number = Value::Float(1.0);
         assert_eq!(number.get_number(), Some(1.0));
 
         let boolean = Value::Bool(true);
@@ -250,13 +875,13 @@ mod tests {
         let boolean = Value::Bool(true);
         assert_eq!(boolean.get_bool(), Some(true));
 
-        let number = Value::Number(1.0);
+        let number = Value::Float(1.0);
         assert_eq!(number.get_bool(), None);
     }
 
     #[test]
     fn test_value_get_str() {
-        let string = Value::Obj(Box::new(Object::Str("hello".to_string())));
+        let string = Value::Obj(Box::new(Object::Str(Rc::from("hello"))));
         let boolean = Value::Bool(false);
 
         assert_eq!(string.get_str(), Some("hello"));
@@ -283,7 +908,7 @@ mod tests {
 
     #[test]
     fn test_value_is_number() {
-        let number = Value::Number(2.0);
+        let number = Value::Float(2.0);
         let boolean = Value::Bool(false);
 
         assert_eq!(number.is_number(), true);
@@ -292,7 +917,7 @@ mod tests {
 
     #[test]
     fn test_value_is_bool() {
-        let number = Value::Number(2.0);
+        let number = Value::Float(2.0);
         let boolean = Value::Bool(false);
 
         assert_eq!(boolean.is_bool(), true);
@@ -313,17 +938,50 @@ mod tests {
         let r#true = Value::Bool(true);
         let r#false = Value::Bool(false);
 
-        let number1 = Value::Number(2.0);
-        let number2 = Value::Number(2.0);
+        let number1 = Value::Float(2.0);
+        let number2 = Value::Float(2.0);
 
         assert_eq!(number1, number2);
         assert_ne!(r#true, r#false);
     }
 
     #[test]
-    #[should_panic]
-    fn test_value_eq_panic() {
-        let _ = Value::Nil == Value::Nil;
+    fn test_value_eq_mixed() {
+        let string = |s: &str| Value::Obj(Box::new(Object::Str(Rc::from(s))));
+
+        assert_eq!(Value::Nil, Value::Nil);
+        assert_ne!(Value::Nil, Value::Bool(false));
+        assert_ne!(Value::Float(1.0), Value::Bool(true));
+        assert_eq!(string("ola"), string("ola"));
+        assert_ne!(string("ola"), string("adeus"));
+        assert_eq!(Value::Int(1 << 53), Value::Float(9007199254740992.0));
+        assert_ne!(Value::Int((1 << 53) + 1), Value::Float(9007199254740992.0));
+        assert_ne!(Value::Int(i64::MAX), Value::Float(i64::MAX as f64));
+    }
+
+    #[test]
+    fn test_value_hash() {
+        let mut map = HashMap::new();
+        map.insert(Value::Float(0.0), Value::Bool(true));
+        map.insert(Value::Obj(Box::new(Object::Str(Rc::from("a")))), Value::Nil);
+
+        assert_eq!(map.get(&Value::Float(-0.0)), Some(&Value::Bool(true)));
+        map.insert(Value::Int(i64::MIN), Value::Nil);
+        assert_eq!(map.get(&Value::Float(i64::MIN as f64)), Some(&Value::Nil));
+        assert_eq!(map.get(&Value::Float(i64::MAX as f64)), None);
+        assert_eq!(
+            map.get(&Value::Obj(Box::new(Object::Str(Rc::from("a"))))),
+            Some(&Value::Nil)
+        );
+    }
+
+    #[test]
+    fn test_value_total_cmp() {
+        assert_eq!(Value::Nil.total_cmp(&Value::Float(1.0)), Ordering::Less);
+        assert_eq!(
+            Value::Float(2.0).total_cmp(&Value::Float(10.0)),
+            Ordering::Less
+        );
     }
 
     #[test]
@@ -331,8 +989,8 @@ mod tests {
         let t = Value::Bool(true);
         let f = Value::Bool(false);
 
-        let number1 = Value::Number(2.0);
-        let number2 = Value::Number(2.1);
+        let number1 = Value::Float(2.0);
+        let number2 = Value::Float(2.1);
 
         assert_eq!(number1 < number2, true);
         assert_ne!(t < f, true);
@@ -346,7 +1004,7 @@ mod tests {
 
     #[test]
     fn test_value_add() {
-        assert_eq!(Value::Number(3.0) + Value::Number(2.0), Value::Number(5.0));
+        assert_eq!(Value::Float(3.0) + Value::Float(2.0), Value::Float(5.0));
     }
 
     #[test]
@@ -357,7 +1015,7 @@ mod tests {
 
     #[test]
     fn test_value_sub() {
-        assert_eq!(Value::Number(3.0) - Value::Number(2.0), Value::Number(1.0));
+        assert_eq!(Value::Float(3.0) - Value::Float(2.0), Value::Float(1.0));
     }
 
     #[test]
@@ -368,7 +1026,7 @@ mod tests {
 
     #[test]
     fn test_value_mul() {
-        assert_eq!(Value::Number(2.0) * Value::Number(3.0), Value::Number(6.0));
+        assert_eq!(Value::Float(2.0) * Value::Float(3.0), Value::Float(6.0));
     }
 
     #[test]
@@ -379,7 +1037,7 @@ mod tests {
 
     #[test]
     fn test_value_div() {
-        assert_eq!(Value::Number(6.0) / Value::Number(2.0), Value::Number(3.0));
+        assert_eq!(Value::Float(6.0) / Value::Float(2.0), Value::Float(3.0));
     }
 
     #[test]
@@ -399,7 +1057,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_value_and_panic() {
-        let _ = Value::Number(1.0) & Value::Number(2.0);
+        let _ = Value::Float(1.0) & Value::Float(2.0);
     }
 
     #[test]
@@ -413,7 +1071,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_value_or_panic() {
-        let _ = Value::Number(1.0) | Value::Number(2.0);
+        let _ = Value::Float(1.0) | Value::Float(2.0);
     }
 
     #[test]
@@ -427,7 +1085,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_value_xor_panic() {
-        let _ = Value::Number(1.0) ^ Value::Number(2.0);
+        let _ = Value::Float(1.0) ^ Value::Float(2.0);
     }
 
     #[test]
@@ -439,18 +1097,101 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_value_not_panic() {
-        let _ = !Value::Number(1.0);
+        let _ = !Value::Float(1.0);
+    }
+
+    #[test]
+    fn test_value_int_float_eq() {
+        assert_eq!(Value::Int(1), Value::Float(1.0));
+        let mut map = HashMap::new();
+        map.insert(Value::Int(1), Value::Bool(true));
+        assert_eq!(map.get(&Value::Float(1.0)), Some(&Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_value_int_arithmetic() {
+        assert_eq!(Value::Int(3).checked_add(&Value::Int(2)), Ok(Value::Int(5)));
+        assert!(Value::Int(1)
+            .checked_add(&Value::Float(0.5))
+            .unwrap()
+            .is_float());
+        assert_eq!(Value::Int(4).checked_div(&Value::Int(2)), Ok(Value::Int(2)));
+        assert!(Value::Int(1).checked_div(&Value::Int(0)).is_err());
+    }
+
+    #[test]
+    fn test_value_bigint_promotion() {
+        let big = Value::Int(i64::MAX).checked_add(&Value::Int(1)).unwrap();
+        assert_eq!(format!("{}", big), "9223372036854775808");
+        assert_eq!(big.checked_sub(&Value::Int(1)), Ok(Value::Int(i64::MAX)));
+        assert!(big.checked_sub(&Value::Int(1)).unwrap().is_int());
+        assert_eq!(
+            Value::Int(i64::MIN).checked_quot(&Value::Int(-1)),
+            Ok(big.clone())
+        );
+        assert!(big > Value::Int(i64::MAX));
+    }
+
+    #[test]
+    fn test_value_ratio() {
+        let third = Value::Int(1).checked_div(&Value::Int(3)).unwrap();
+        assert_eq!(format!("{}", third), "1/3");
+        let sum = third.checked_add(&third).unwrap().checked_add(&third);
+        assert_eq!(sum, Ok(Value::Int(1)));
+        assert!(sum.unwrap().is_int());
+        let half = Value::Int(1).checked_div(&Value::Int(2)).unwrap();
+        assert_eq!(half, Value::Float(0.5));
+        assert!(half.checked_mul(&Value::Float(1.0)).unwrap().is_float());
+        assert!(third < half);
+    }
+
+    #[test]
+    fn test_value_ratio_hash() {
+        let half = Value::Int(1).checked_div(&Value::Int(2)).unwrap();
+        let mut map = HashMap::new();
+        map.insert(half, Value::Bool(true));
+        assert_eq!(map.get(&Value::Float(0.5)), Some(&Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_value_quot_rem_mod() {
+        assert_eq!(
+            Value::Int(-7).checked_quot(&Value::Int(2)),
+            Ok(Value::Int(-3))
+        );
+        assert_eq!(
+            Value::Int(-7).checked_rem(&Value::Int(2)),
+            Ok(Value::Int(-1))
+        );
+        assert_eq!(
+            Value::Int(-7).checked_mod(&Value::Int(2)),
+            Ok(Value::Int(1))
+        );
+        assert_eq!(
+            Value::Int(7).checked_mod(&Value::Int(-2)),
+            Ok(Value::Int(-1))
+        );
+    }
+
+    #[test]
+    fn test_value_number_display() {
+        assert_eq!(format!("{}", Value::Int(1)), "1");
+        assert_eq!(format!("{}", Value::Float(1.0)), "1.0");
+        assert_eq!(format!("{}", Value::Float(2.5)), "2.5");
+        assert_eq!(format!("{}", Value::Float(f64::INFINITY)), "##inf");
+        assert_eq!(format!("{}", Value::Float(f64::NEG_INFINITY)), "##-inf");
+        assert_eq!(format!("{}", Value::Float(f64::NAN)), "##nan");
     }
 
     //#[test]
     //fn test_value_display() {
-    //    assert_eq!(format!("{}", Value::Number(1.0)), "1.0");
-    //    assert_eq!(format!("{}", Value::Number(-1.0)), "-1.0");
+    //    assert_eq!(format!("{}", Value::Float(1.0)), "1.0");
+    //    assert_eq!(format!("{}", Value::Float(-1.0)), "-1.0");
     //    assert_eq!(format!("{}", Value::Bool(false)), "false");
     //    assert_eq!(format!("{}", Value::Bool(true)), "true");
     //    assert_eq!(format!("{}", Value::Nil), "nil");
     //    assert_eq!(
-    //        format!("{}", Value::Obj(Box::new(Object::Str("ola".to_string())))),
+    //        format!("{}", Value::Obj(Box::new(Object::Str(Rc::from("ola"))))),
     //        "ola"
     //    );
     //    assert_eq!(
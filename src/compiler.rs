@@ -1,12 +1,29 @@
 use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Num, Zero};
 use rand::Rng;
-use regex::{Captures, Regex};
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
-
-use crate::chunk::object::{Function, Object};
-use crate::chunk::value::Value;
-use crate::chunk::{Chunk, OpCode};
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::CharIndices;
+
+use crate::chunk::generic;
+use crate::chunk::object::{intern, Function, Object, StructType};
+use crate::chunk::value::{Value, CHAR_NAMES};
+use crate::chunk::{Chunk, OpCode, MAX_INDEX};
+use crate::module::{module_name, top_level_definitions, Module, Modules};
 use crate::scanner::{Scanner, Token};
+use crate::typecheck;
+
+mod pattern;
+
+use pattern::Binding;
 
 #[derive(Clone)]
 pub struct UpValue {
@@ -32,11 +49,36 @@ pub enum Ctx {
     FunctionScope(String),
 }
 
+/// The module a top-level compiler is compiling. Its globals are stored as
+/// `name/global` and only the `provided` ones can be used by other files.
+#[derive(Clone)]
+struct Namespace {
+    name: String,
+    provided: Option<Vec<String>>,
+}
+
+/// What a function generated by `defstruct` does, calls to it are compiled
+/// to the instruction directly.
+#[derive(Clone, Copy)]
+enum Accessor {
+    Constructor,
+    Predicate,
+    Getter(usize),
+    Setter(usize),
+}
+
 #[derive(Clone)]
 pub struct Compiler {
     locals: Vec<String>,
     up: Option<Box<Compiler>>,
     upvals: Vec<UpValue>,
+    globals: Vec<String>,
+    accessors: HashMap<String, (Rc<StructType>, Accessor)>,
+    namespace: Option<Namespace>,
+    modules: Rc<RefCell<Modules>>,
+    warnings: Vec<String>,
+    /// The types the sources compiled so far define, for the type checker.
+    types: typecheck::Types,
 }
 
 impl Compiler {
@@ -45,9 +87,116 @@ impl Compiler {
             locals: Vec::new(),
             up,
             upvals: Vec::new(),
+            globals: Vec::new(),
+            accessors: HashMap::new(),
+            namespace: None,
+            modules: Rc::default(),
+            warnings: Vec::new(),
+            types: typecheck::Types::default(),
+        }
+    }
+
+    /// Sets the directories searched for modules that are not found next to
+    /// the file requiring them.
+    pub fn set_search_path(&mut self, search_path: Vec<PathBuf>) {
+        self.modules.borrow_mut().set_search_path(search_path);
+    }
+
+    /// The warnings reported since the last call, such as unreachable clauses
+    /// of a `match`.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Keeps the modules compiled since the last call if the code loading
+    /// them ran without errors, and forgets them otherwise so that a failed
+    /// `require` can be retried.
+    pub fn finish_modules(&mut self, ran: bool) {
+        let mut modules = self.modules.borrow_mut();
+        if ran {
+            modules.commit();
+        } else {
+            modules.rollback();
         }
     }
 
+    fn warn(&mut self, warning: String) {
+        match &mut self.up {
+            Some(up) => up.warn(warning),
+            None => self.warnings.push(warning),
+        }
+    }
+
+    fn is_top_level(&self) -> bool {
+        self.up.is_none()
+    }
+
+    fn namespace(&self) -> Option<&Namespace> {
+        match &self.up {
+            Some(up) => up.namespace(),
+            None => self.namespace.as_ref(),
+        }
+    }
+
+    /// The name a global is stored under in the VM.
+    fn qualified(&self, name: &str) -> String {
+        match self.namespace() {
+            Some(namespace) if self.is_global(name) => format!("{}/{}", namespace.name, name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn is_global(&self, name: &str) -> bool {
+        match &self.up {
+            Some(up) => up.is_global(name),
+            None => self.globals.iter().any(|global| global == name),
+        }
+    }
+
+    fn declare_global(&mut self, name: &str) {
+        match &mut self.up {
+            Some(up) => up.declare_global(name),
+            None => {
+                if !self.globals.iter().any(|global| global == name) {
+                    self.globals.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    fn accessor(&self, name: &str) -> Option<(Rc<StructType>, Accessor)> {
+        match &self.up {
+            Some(up) => up.accessor(name),
+            None => self.accessors.get(name).cloned(),
+        }
+    }
+
+    /// The struct type named `name`, found through its accessors.
+    fn struct_type(&self, name: &str) -> Option<Rc<StructType>> {
+        match &self.up {
+            Some(up) => up.struct_type(name),
+            None => self
+                .accessors
+                .values()
+                .find(|(struct_type, _)| &*struct_type.name == name)
+                .map(|(struct_type, _)| struct_type.clone()),
+        }
+    }
+
+    fn forget_accessor(&mut self, name: &str) {
+        match &mut self.up {
+            Some(up) => up.forget_accessor(name),
+            None => {
+                self.accessors.remove(name);
+            }
+        }
+    }
+
+    /// Whether `name` is a local of this function or of an enclosing one.
+    fn is_local(&self, name: &str) -> bool {
+        self.get_local(name).is_some() || self.up.as_ref().is_some_and(|up| up.is_local(name))
+    }
+
     fn set_local(&mut self, name: String) -> usize {
         self.locals.push(name);
         self.locals.len() - 1
@@ -63,24 +212,25 @@ impl Compiler {
         None
     }
 
-    fn get_upvalue(&self, name: &str) -> Option<usize> {
-        let up = self.up.as_ref()?;
+    fn get_upvalue(&mut self, name: &str) -> Option<usize> {
+        let up = self.up.as_mut()?;
 
-        if let Some(id) = dbg!(up.get_local(name)) {
-            return Some(id);
+        if let Some(id) = up.get_local(name) {
+            return Some(self.add_upvalue(id, true));
         }
 
-        up.get_upvalue(name)
+        let id = up.get_upvalue(name)?;
+        Some(self.add_upvalue(id, false))
     }
 
-    fn add_upvalue(&mut self, id: usize, is_local: bool) -> Option<usize> {
-        for upval in self.upvals.iter() {
+    fn add_upvalue(&mut self, id: usize, is_local: bool) -> usize {
+        for (i, upval) in self.upvals.iter().enumerate() {
             if upval.index == id && upval.is_local == is_local {
-                return Some(upval.index);
+                return i;
             }
         }
         self.upvals.push(UpValue::new(is_local, id));
-        Some(self.upvals.len())
+        self.upvals.len() - 1
     }
 
     fn emit_nil(&self, chunk: &mut Chunk, line: usize) -> Result<(), String> {
@@ -88,6 +238,12 @@ impl Compiler {
         Ok(())
     }
 
+    fn emit_keyword(&self, chunk: &mut Chunk, name: &str, line: usize) -> Result<(), String> {
+        let idx = chunk.add_constant(Value::Obj(Box::new(Object::Keyword(intern(name)))));
+        chunk.write_load_constant(idx, line);
+        Ok(())
+    }
+
     fn emit_true(&self, chunk: &mut Chunk, line: usize) -> Result<(), String> {
         chunk.write_opcode(OpCode::OpTrue, line);
         Ok(())
@@ -121,44 +277,59 @@ impl Compiler {
     }
 
     fn emit_set_local(&mut self, chunk: &mut Chunk, name: &(Token, usize)) -> Result<(), String> {
-        //scanner.scan().unwrap(); //function name?
-        //let var_name = scanner.scan().unwrap().atom()?; //first arg
-        //parse(scanner, chunk, self)?;
-        let idx = self.set_local(name.0.atom()?);
+        let var_name = name.0.atom()?;
+        let idx = match self.get_local(&var_name) {
+            Some(idx) => idx,
+            None => self.set_local(var_name),
+        };
         chunk.write_opcode(OpCode::OpSetLocal, name.1);
-        chunk.write_constant(idx as u8, name.1);
+        chunk.write_index(idx, name.1);
         Ok(())
     }
 
     fn emit_set_upvalue(&mut self, chunk: &mut Chunk, name: &str) -> Result<(), String> {
-        //scanner.scan().unwrap(); //function name?
-        //let var_name = scanner.scan().unwrap().atom()?; //first arg
-        //parse(scanner, chunk, self)?;
         if let Some(id) = self.get_upvalue(name) {
             chunk.write_opcode(OpCode::OpSetUpvalue, 0);
-            chunk.write_constant(id as u8, 0);
+            chunk.write_index(id, 0);
             return Ok(());
         }
 
         Err(String::from("Failed to set upvalue"))
     }
 
+    fn emit_set_global(&mut self, chunk: &mut Chunk, name: &(Token, usize)) -> Result<(), String> {
+        let var_name = name.0.atom()?;
+        self.declare_global(&var_name);
+        // a redefined accessor is an ordinary function again
+        self.forget_accessor(&var_name);
+        let idx = identifier_constant(chunk, self.qualified(&var_name));
+        chunk.write_opcode(OpCode::OpSetGlobal, name.1);
+        chunk.write_index(idx, name.1);
+        Ok(())
+    }
+
+    fn emit_set(&mut self, chunk: &mut Chunk, name: &(Token, usize)) -> Result<(), String> {
+        let var_name = name.0.atom()?;
+        if self.get_local(&var_name).is_some() {
+            self.emit_set_local(chunk, name)
+        } else if self.get_upvalue(&var_name).is_some() {
+            self.emit_set_upvalue(chunk, &var_name)
+        } else if self.is_top_level() || self.is_global(&var_name) {
+            self.emit_set_global(chunk, name)
+        } else {
+            self.emit_set_local(chunk, name)
+        }
+    }
+
     fn emit_if(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
         let (_, line) = scanner.scan().unwrap();
         parse(scanner, chunk, self)?;
-        chunk.write_opcode(OpCode::OpJmpIfFalse, line);
-        chunk.write_constant(0, 1); //placeholder
-        let branch_idx = chunk.get_current_index()?;
+        let branch = emit_jump(chunk, OpCode::OpJmpIfFalse, line);
         parse(scanner, chunk, self)?;
-        chunk.write_opcode(OpCode::OpJmp, 1);
-        chunk.write_constant(0, 1); //placeholder
-        let jmp_idx = chunk.get_current_index()?;
-        let false_idx = jmp_idx + 1;
+        let end = emit_jump(chunk, OpCode::OpJmp, line);
+        patch_jump(chunk, branch);
         parse(scanner, chunk, self)?;
-        let end_idx = chunk.get_current_index()? + 1;
-
-        chunk.rewrite_constant(branch_idx, false_idx as u8);
-        chunk.rewrite_constant(jmp_idx, end_idx as u8);
+        patch_jump(chunk, end);
         Ok(())
     }
 
@@ -174,77 +345,664 @@ impl Compiler {
     }
 
     fn emit_do(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
-        scanner.scan().unwrap();
+        let (_, line) = scanner.scan().unwrap();
+        let mut empty = true;
         loop {
-            if scanner.peek().unwrap().0 == Token::RightParen {
+            if scanner.peek().ok_or("Expected ')' to close do")?.0 == Token::RightParen {
                 break;
             }
+            if !empty {
+                chunk.write_opcode(OpCode::OpPop, line);
+            }
+            parse(scanner, chunk, self)?;
+            empty = false;
+        }
+        if empty {
+            self.emit_nil(chunk, line)?;
+        }
+        Ok(())
+    }
+
+    /// `(let ((pattern value)...) body...)` binds each pattern to its value,
+    /// in order, and evaluates the body with the names in scope.
+    fn emit_let(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        if scanner.scan().map(|(token, _)| token) != Some(Token::LeftParen) {
+            return Err(format!(
+                "Expected a list of bindings after let at line {}",
+                line
+            ));
+        }
+        let start = self.locals.len();
+        while scanner
+            .peek()
+            .ok_or("Expected ')' to close the let bindings")?
+            .0
+            != Token::RightParen
+        {
+            let (token, line) = scanner.scan().unwrap();
+            if token != Token::LeftParen {
+                return Err(format!(
+                    "Expected (pattern value) in let at line {}, got {:?}",
+                    line, token
+                ));
+            }
+            let binding = pattern::read_binding(scanner, &mut Vec::new())?;
+            parse(scanner, chunk, self)?;
+            expect_close(scanner, &format!("the let binding at line {}", line))?;
+            self.emit_destructure(chunk, &binding, line)?;
+        }
+        scanner.scan();
+        let mut empty = true;
+        while scanner.peek().ok_or("Expected ')' to close let")?.0 != Token::RightParen {
+            if !empty {
+                chunk.write_opcode(OpCode::OpPop, line);
+            }
+            parse(scanner, chunk, self)?;
+            empty = false;
+        }
+        if empty {
+            self.emit_nil(chunk, line)?;
+        }
+        // the slots stay reserved but the names are out of scope
+        for local in &mut self.locals[start..] {
+            local.clear();
+        }
+        Ok(())
+    }
+
+    /// `(throw value)` raises `value`, the closest enclosing `try` catches it.
+    fn emit_throw(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        parse(scanner, chunk, self)?;
+        chunk.write_opcode(OpCode::OpThrow, line);
+        Ok(())
+    }
+
+    /// `(try body (catch e handler) (finally cleanup))`, both clauses are
+    /// optional. The value is the body's, or the handler's when the body raised
+    /// an error. `cleanup` runs either way, so it is compiled twice: after the
+    /// body and handler, and in a handler of its own that rethrows the error.
+    fn emit_try(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        if matches!(scanner.peek(), Some((Token::RightParen, _)) | None)
+            || matches!(clause_name(scanner).as_deref(), Some("catch" | "finally"))
+        {
+            return Err(format!("try expects a body at line {}", line));
+        }
+        let mut ahead = scanner.clone();
+        skip_datum(&mut ahead);
+        if clause_name(&ahead).as_deref() == Some("catch") {
+            skip_datum(&mut ahead);
+        }
+        let finally = clause_name(&ahead).as_deref() == Some("finally");
+
+        let finally_handler = if finally {
+            Some(emit_jump(chunk, OpCode::OpTry, line))
+        } else {
+            None
+        };
+        let catch_handler = emit_jump(chunk, OpCode::OpTry, line);
+        parse(scanner, chunk, self)?;
+        chunk.write_opcode(OpCode::OpEndTry, line);
+        let skip_catch = emit_jump(chunk, OpCode::OpJmp, line);
+
+        patch_jump(chunk, catch_handler);
+        if clause_name(scanner).as_deref() == Some("catch") {
+            scanner.scan();
+            scanner.scan();
+            let name = match scanner.scan() {
+                Some((Token::Atom(name), name_line)) if is_symbol(&name) => (name, name_line),
+                Some((Token::Atom(atom), _)) => {
+                    return Err(format!(
+                        "catch expects a symbol at line {}, got {}",
+                        line, atom
+                    ))
+                }
+                Some((token, _)) => {
+                    return Err(format!(
+                        "catch expects a symbol at line {}, got {:?}",
+                        line, token
+                    ))
+                }
+                None => return Err(format!("Expected a name in catch at line {}", line)),
+            };
+            let slot = self.set_local(name.0);
+            chunk.write_opcode(OpCode::OpSetLocal, name.1);
+            chunk.write_index(slot, name.1);
+            chunk.write_opcode(OpCode::OpPop, name.1);
+            parse(scanner, chunk, self)?;
+            expect_close(scanner, "catch")?;
+            // the slot stays reserved but the name is out of scope
+            self.locals[slot] = String::new();
+        } else {
+            chunk.write_opcode(OpCode::OpThrow, line);
+        }
+        patch_jump(chunk, skip_catch);
+
+        if let Some(finally_handler) = finally_handler {
+            chunk.write_opcode(OpCode::OpEndTry, line);
+            scanner.scan();
+            scanner.scan();
+            let mut cleanup = scanner.clone();
+            parse(scanner, chunk, self)?;
+            chunk.write_opcode(OpCode::OpPop, line);
+            expect_close(scanner, "finally")?;
+            let end = emit_jump(chunk, OpCode::OpJmp, line);
+
+            patch_jump(chunk, finally_handler);
+            parse(&mut cleanup, chunk, self)?;
+            chunk.write_opcode(OpCode::OpPop, line);
+            chunk.write_opcode(OpCode::OpThrow, line);
+            patch_jump(chunk, end);
+        }
+
+        match scanner.peek() {
+            Some((Token::RightParen, _)) => Ok(()),
+            _ => Err(format!(
+                "try at line {} only takes catch and finally clauses",
+                line
+            )),
+        }
+    }
+
+    /// `(assert expr)` and `(assert-eq left right)` call the natives of the
+    /// same name with the source of the form as an extra argument, so that a
+    /// failure can show what was asserted.
+    fn emit_assert(
+        &mut self,
+        chunk: &mut Chunk,
+        atom: (Token, usize),
+        scanner: &mut Scanner,
+    ) -> Result<(), String> {
+        let source = form_text(scanner);
+        let name = atom.0.atom()?;
+        let expected = if name == "assert" { 1 } else { 2 };
+        let idx = identifier_constant(chunk, name.clone());
+        chunk.write_opcode(OpCode::OpGetGlobal, atom.1);
+        chunk.write_index(idx, atom.1);
+        scanner.scan();
+
+        let mut argc = 0;
+        while scanner.peek().ok_or("Expected ')' to close assert")?.0 != Token::RightParen {
             parse(scanner, chunk, self)?;
+            argc += 1;
         }
+        if argc != expected {
+            return Err(format!(
+                "{} takes {} argument(s) at line {}, got {}",
+                name, expected, atom.1, argc
+            ));
+        }
+        let idx = chunk.add_constant(Value::Obj(Box::new(Object::Str(source.into()))));
+        chunk.write_load_constant(idx, atom.1);
+        chunk.write_opcode(OpCode::OpCall, atom.1);
+        chunk.write_constant(expected + 1, atom.1);
         Ok(())
     }
 
     fn emit_defun(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        scanner.scan().unwrap();
+        let name = scanner.scan().ok_or("Expected function name")?;
+        let var_name = name.0.atom()?;
+
+        // Declare the function before compiling its body so that it can call itself.
+        let local = self.declare_definition(&var_name);
+
         chunk.write_opcode(OpCode::OpClosure, scanner.get_line());
-        let (lambda, lambda_compiler) = parse_defun(scanner, self)?;
-        let idx = chunk.add_constant(Value::Obj(Box::new(lambda.clone())));
-        chunk.write_constant(idx as u8, scanner.get_line());
+        let (lambda, lambda_compiler) = parse_function(var_name, scanner, self)?;
+        let idx = chunk.add_constant(Value::Obj(Box::new(lambda)));
+        chunk.write_index(idx, scanner.get_line());
 
         for upval in lambda_compiler.upvals.iter() {
             chunk.write_constant(upval.is_local as u8, scanner.get_line());
-            chunk.write_constant(upval.index as u8, scanner.get_line());
+            chunk.write_index(upval.index, scanner.get_line());
         }
 
-        let idx = self.set_local(lambda.get_function().ok_or("Failed to find function")?.name);
-        chunk.write_opcode(OpCode::OpSetLocal, scanner.get_line());
-        chunk.write_constant(idx as u8, scanner.get_line());
+        self.emit_definition(chunk, local, &name)
+    }
+
+    /// Declares the name a `defun` or `defgeneric` defines: a global at top
+    /// level, otherwise a local of the enclosing function, whose slot is
+    /// returned.
+    fn declare_definition(&mut self, name: &str) -> Option<usize> {
+        if self.is_top_level() || self.is_global(name) {
+            self.declare_global(name);
+            None
+        } else {
+            Some(match self.get_local(name) {
+                Some(idx) => idx,
+                None => self.set_local(name.to_string()),
+            })
+        }
+    }
 
+    fn emit_definition(
+        &mut self,
+        chunk: &mut Chunk,
+        local: Option<usize>,
+        name: &(Token, usize),
+    ) -> Result<(), String> {
+        match local {
+            Some(idx) => {
+                chunk.write_opcode(OpCode::OpSetLocal, name.1);
+                chunk.write_index(idx, name.1);
+                Ok(())
+            }
+            None => self.emit_set_global(chunk, name),
+        }
+    }
+
+    /// `(defgeneric area (shape))` defines `area` as a generic function of
+    /// one argument, whose methods are added by `defmethod`.
+    fn emit_defgeneric(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let name = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a name after defgeneric at line {}", line))?;
+        let var_name = name.0.atom()?;
+        let local = self.declare_definition(&var_name);
+        if scanner.scan().map(|(token, _)| token) != Some(Token::LeftParen) {
+            return Err(format!(
+                "Expected the parameters of {} at line {}",
+                var_name, line
+            ));
+        }
+        let mut arity = 0;
+        loop {
+            match scanner.scan() {
+                Some((Token::RightParen, _)) => break,
+                Some((Token::Atom(param), _)) if !param.starts_with('&') => arity += 1,
+                Some((token, line)) => {
+                    return Err(format!(
+                        "Unexpected {:?} in the parameters of {} at line {}",
+                        token, var_name, line
+                    ))
+                }
+                None => return Err("Expected ')' to close the parameter list".to_string()),
+            }
+        }
+
+        let idx = identifier_constant(chunk, "make-generic".to_string());
+        chunk.write_opcode(OpCode::OpGetGlobal, line);
+        chunk.write_index(idx, line);
+        for constant in [
+            Value::Obj(Box::new(Object::Str(intern(&var_name)))),
+            Value::Int(arity),
+        ] {
+            let idx = chunk.add_constant(constant);
+            chunk.write_load_constant(idx, line);
+        }
+        chunk.write_opcode(OpCode::OpCall, line);
+        chunk.write_constant(2, line);
+        self.emit_definition(chunk, local, &name)
+    }
+
+    /// `(defmethod area ((c circle)) body)` adds a method to the generic
+    /// function `area` for when its argument is a `circle`. A parameter is
+    /// `(name type)` with a struct or built-in type, or a plain name for any
+    /// value. In `body`, `(next-method)` calls the next most specific method
+    /// with the same arguments.
+    fn emit_defmethod(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let generic = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a name after defmethod at line {}", line))?;
+        let generic_name = generic.0.atom()?;
+        if scanner.scan().map(|(token, _)| token) != Some(Token::LeftParen) {
+            return Err(format!(
+                "Expected the parameters of the {} method at line {}",
+                generic_name, line
+            ));
+        }
+        let mut params = vec!["next-method".to_string()];
+        let mut specializers = Vec::new();
+        loop {
+            match scanner.scan() {
+                Some((Token::RightParen, _)) => break,
+                Some((Token::Atom(param), _)) => {
+                    params.push(param);
+                    specializers.push(Value::Nil);
+                }
+                Some((Token::LeftParen, line)) => {
+                    params.push(
+                        scanner
+                            .scan()
+                            .ok_or("Expected a parameter name")?
+                            .0
+                            .atom()?,
+                    );
+                    let type_name = scanner.scan().ok_or("Expected a type")?.0.atom()?;
+                    specializers.push(self.specializer(&type_name, line)?);
+                    expect_close(scanner, "the parameter")?;
+                }
+                Some((token, line)) => {
+                    return Err(format!(
+                        "Unexpected {:?} in the parameters of the {} method at line {}",
+                        token, generic_name, line
+                    ))
+                }
+                None => return Err("Expected ')' to close the parameter list".to_string()),
+            }
+        }
+
+        let idx = identifier_constant(chunk, "add-method".to_string());
+        chunk.write_opcode(OpCode::OpGetGlobal, line);
+        chunk.write_index(idx, line);
+        self.resolve_variable(chunk, &generic)?;
+        let idx = chunk.add_constant(Value::Obj(Box::new(Object::Vector(Rc::new(specializers)))));
+        chunk.write_load_constant(idx, line);
+        let method = compile_function(generic_name, params, scanner, self)?;
+        emit_closure(chunk, method, line);
+        chunk.write_opcode(OpCode::OpCall, line);
+        chunk.write_constant(3, line);
         Ok(())
     }
 
+    /// What a method parameter of type `name` matches: a built-in type as a
+    /// keyword, a struct type, or nil for `any`.
+    fn specializer(&self, name: &str, line: usize) -> Result<Value, String> {
+        if name == "any" {
+            return Ok(Value::Nil);
+        }
+        if generic::TYPES.contains(&name) {
+            return Ok(Value::Obj(Box::new(Object::Keyword(intern(name)))));
+        }
+        match self.struct_type(name) {
+            Some(struct_type) => Ok(Value::Obj(Box::new(Object::Struct(struct_type)))),
+            None => Err(format!("Unknown type {} at line {}", name, line)),
+        }
+    }
+
     fn emit_lambda(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
-        chunk.write_opcode(OpCode::OpClosure, scanner.get_line());
-        let (lambda, lambda_compiler) = parse_lambda(scanner, self)?;
-        let idx = chunk.add_constant(Value::Obj(Box::new(lambda)));
-        chunk.write_constant(idx as u8, scanner.get_line());
+        let lambda = parse_lambda(scanner, self)?;
+        emit_closure(chunk, lambda, scanner.get_line());
+        Ok(())
+    }
 
-        for upval in lambda_compiler.upvals.iter() {
-            chunk.write_constant(upval.is_local as u8, scanner.get_line());
-            chunk.write_constant(upval.index as u8, scanner.get_line());
+    /// `(reset body)` runs `body` as a function call that delimits the
+    /// continuations captured by `shift` inside it.
+    fn emit_reset(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let body = compile_function("reset".to_string(), Vec::new(), scanner, self)?;
+        emit_closure(chunk, body, line);
+        chunk.write_opcode(OpCode::OpReset, line);
+        Ok(())
+    }
+
+    /// `(shift k body)` captures the rest of the computation up to the
+    /// enclosing `reset` as the function `k`, and makes the value of `body`
+    /// the value of the `reset`.
+    fn emit_shift(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let k = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a name after shift at line {}", line))?;
+        let body = compile_function("shift".to_string(), vec![k.0.atom()?], scanner, self)?;
+        emit_closure(chunk, body, line);
+        chunk.write_opcode(OpCode::OpShift, line);
+        Ok(())
+    }
+
+    /// `(resume co value?)` runs a coroutine until it yields or returns. The
+    /// first resume passes `value` to the coroutine's function, later ones
+    /// make it the value of the pending `yield`.
+    fn emit_resume(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let mut argc = 0;
+        while scanner.peek().ok_or("Expected ')' to close resume")?.0 != Token::RightParen {
+            parse(scanner, chunk, self)?;
+            argc += 1;
+        }
+        if !(1..=2).contains(&argc) {
+            return Err(format!(
+                "resume takes 1 or 2 arguments at line {}, got {}",
+                line, argc
+            ));
+        }
+        chunk.write_opcode(OpCode::OpResume, line);
+        chunk.write_constant(argc - 1, line);
+        Ok(())
+    }
+
+    /// `(yield value?)` suspends the running coroutine, its `resume`
+    /// evaluates to `value`.
+    fn emit_yield(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        if scanner.peek().ok_or("Expected ')' to close yield")?.0 == Token::RightParen {
+            self.emit_nil(chunk, line)?;
+        } else {
+            parse(scanner, chunk, self)?;
         }
+        chunk.write_opcode(OpCode::OpYield, line);
+        Ok(())
+    }
 
+    /// `(lazy-seq body)` is a sequence whose items are those of the collection
+    /// `body` evaluates to. `body` runs the first time the sequence is walked.
+    fn emit_lazy_seq(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let body = compile_function("lazy-seq".to_string(), Vec::new(), scanner, self)?;
+        let idx = identifier_constant(chunk, "lazy-seq".to_string());
+        chunk.write_opcode(OpCode::OpGetGlobal, line);
+        chunk.write_index(idx, line);
+        emit_closure(chunk, body, line);
+        chunk.write_opcode(OpCode::OpCall, line);
+        chunk.write_constant(1, line);
         Ok(())
     }
 
-    fn emit_integer(&self, chunk: &mut Chunk, atom: (Token, usize)) -> Result<(), String> {
-        let i: i32 = atom.0.atom()?.parse().unwrap();
-        chunk.write_opcode(OpCode::OpConst, atom.1);
-        let constant = chunk.add_constant(Value::Number(i as f64));
-        chunk.write_constant(constant as u8, atom.1);
+    /// `(doseq (x coll) body...)` evaluates `body` with `x` bound to each item
+    /// of `coll` and evaluates to nil. It is compiled to a loop over the
+    /// sequence of `coll`, which stays on the stack, so `yield` and `shift`
+    /// can be used in the body. Each item gets its own cell, so closures made
+    /// in the body keep the item of their iteration.
+    fn emit_doseq(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        if scanner.scan().map(|(token, _)| token) != Some(Token::LeftParen) {
+            return Err(format!(
+                "Expected (name collection) after doseq at line {}",
+                line
+            ));
+        }
+        let name = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a name after doseq at line {}", line))?
+            .0
+            .atom()?;
+        parse(scanner, chunk, self)?;
+        expect_close(scanner, "the doseq binding")?;
+        chunk.write_opcode(OpCode::OpSeq, line);
+
+        let slot = self.set_local(name);
+        let start = chunk.len();
+        chunk.write_opcode(OpCode::OpNext, line);
+        chunk.write_index(slot, line);
+        chunk.write_jump_target(0, line);
+        let exit = chunk.len() - 2;
+        while scanner.peek().ok_or("Expected ')' to close doseq")?.0 != Token::RightParen {
+            parse(scanner, chunk, self)?;
+            chunk.write_opcode(OpCode::OpPop, line);
+        }
+        chunk.write_opcode(OpCode::OpJmp, line);
+        chunk.write_jump_target(start, line);
+        patch_jump(chunk, exit);
+        // the slot stays reserved but the name is out of scope
+        self.locals[slot] = String::new();
+        self.emit_nil(chunk, line)
+    }
+
+    /// `(defstruct point x y)` defines the record type `point` with the
+    /// functions `make-point`, `point?`, and `point-x` and `set-point-x!` for
+    /// each field.
+    fn emit_defstruct(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        if !self.is_top_level() {
+            return Err(format!(
+                "defstruct is only allowed at top level, line {}",
+                line
+            ));
+        }
+        let name = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a struct name after defstruct at line {}", line))?
+            .0
+            .atom()?;
+        let mut fields: Vec<Rc<str>> = Vec::new();
+        while scanner.peek().ok_or("Expected ')' to close defstruct")?.0 != Token::RightParen {
+            let field = scanner.scan().unwrap().0.atom()?;
+            if fields.iter().any(|other| **other == *field) {
+                return Err(format!(
+                    "Duplicate field {} in struct {} at line {}",
+                    field, name, line
+                ));
+            }
+            fields.push(intern(&field));
+        }
+
+        let struct_type = Rc::new(StructType {
+            name: intern(&name),
+            fields,
+        });
+        let mut accessors = vec![
+            (struct_type.constructor(), Accessor::Constructor),
+            (struct_type.predicate(), Accessor::Predicate),
+        ];
+        for slot in 0..struct_type.fields.len() {
+            accessors.push((struct_type.getter(slot), Accessor::Getter(slot)));
+            accessors.push((struct_type.setter(slot), Accessor::Setter(slot)));
+        }
+        for (accessor_name, accessor) in accessors {
+            let function = accessor_function(&accessor_name, &struct_type, accessor, line);
+            let idx = chunk.add_constant(Value::Obj(Box::new(function)));
+            chunk.write_opcode(OpCode::OpClosure, line);
+            chunk.write_index(idx, line);
+            self.emit_set_global(chunk, &(Token::Atom(accessor_name.clone()), line))?;
+            chunk.write_opcode(OpCode::OpPop, line);
+            self.accessors
+                .insert(accessor_name, (struct_type.clone(), accessor));
+        }
+        self.emit_nil(chunk, line)
+    }
+
+    /// `(require "lib/util.flox")` and `(import util)` compile the file the
+    /// first time it is required and run its top-level forms, later requires
+    /// evaluate to nil without doing anything.
+    fn emit_require(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (head, line) = scanner.scan().unwrap();
+        let head = head.atom()?;
+        let (spec, _) = scanner
+            .scan()
+            .ok_or_else(|| format!("Expected a module after {} at line {}", head, line))?;
+        if !self.is_top_level() {
+            return Err(format!(
+                "{} is only allowed at top level, line {}",
+                head, line
+            ));
+        }
+        let spec = spec.atom()?;
+        let path = if head == "import" {
+            format!("{}.flox", spec)
+        } else if spec.starts_with('"') || spec.starts_with("#\"") {
+            parse_string(&spec, line)?
+        } else {
+            return Err(format!("require expects a path string at line {}", line));
+        };
+
+        let path = self.modules.borrow().resolve(&path)?;
+        if self.modules.borrow().get(&path).is_none() {
+            let (function, warnings) = compile_module(&path, &self.modules)?;
+            for warning in warnings {
+                self.warn(warning);
+            }
+            let idx = chunk.add_constant(Value::Obj(Box::new(function)));
+            chunk.write_opcode(OpCode::OpClosure, line);
+            chunk.write_index(idx, line);
+            chunk.write_opcode(OpCode::OpCall, line);
+            chunk.write_constant(0, line);
+            chunk.write_opcode(OpCode::OpPop, line);
+        }
+        self.emit_nil(chunk, line)
+    }
+
+    /// `(provide name ...)` limits what other files can use from a module.
+    fn emit_provide(&mut self, chunk: &mut Chunk, scanner: &mut Scanner) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let mut names = Vec::new();
+        while let Some((Token::Atom(name), _)) = scanner.peek() {
+            scanner.scan();
+            names.push(name);
+        }
+        match &mut self.namespace {
+            Some(namespace) if self.up.is_none() => {
+                namespace
+                    .provided
+                    .get_or_insert_with(Vec::new)
+                    .extend(names);
+                self.emit_nil(chunk, line)
+            }
+            _ => Err(format!(
+                "provide is only allowed at the top level of a module, line {}",
+                line
+            )),
+        }
+    }
+
+    fn emit_number(&self, chunk: &mut Chunk, atom: (Token, usize)) -> Result<(), String> {
+        let literal = atom.0.atom()?;
+        let value = parse_number(&literal)
+            .ok_or_else(|| format!("Invalid number literal {} at line {}", literal, atom.1))?;
+        let constant = chunk.add_constant(value);
+        chunk.write_load_constant(constant, atom.1);
+        Ok(())
+    }
+    fn emit_char(&self, chunk: &mut Chunk, atom: (Token, usize)) -> Result<(), String> {
+        let literal = atom.0.atom()?;
+        let c = parse_char(&literal["#\\".len()..])
+            .ok_or_else(|| format!("Invalid character literal {} at line {}", literal, atom.1))?;
+        let constant = chunk.add_constant(Value::Char(c));
+        chunk.write_load_constant(constant, atom.1);
         Ok(())
     }
+
     fn emit_string(&self, chunk: &mut Chunk, atom: (Token, usize)) -> Result<(), String> {
-        chunk.write_opcode(OpCode::OpConst, atom.1);
-        let s: &str = &atom.0.atom()?;
-        let s = Object::Str(unescape_str(&s[1..s.len() - 1]));
+        let s = Object::Str(intern(&parse_string(&atom.0.atom()?, atom.1)?));
         let constant = chunk.add_constant(Value::Obj(Box::new(s)));
-        chunk.write_constant(constant as u8, atom.1);
+        chunk.write_load_constant(constant, atom.1);
         Ok(())
     }
 
     fn emit_get_local(&self, chunk: &mut Chunk, id: usize) -> Result<(), String> {
         chunk.write_opcode(OpCode::OpGetLocal, 1);
-        chunk.write_constant(id as u8, 1);
+        chunk.write_index(id, 1);
+        Ok(())
+    }
+
+    fn emit_get_upvalue(
+        &mut self,
+        chunk: &mut Chunk,
+        id: usize,
+        line: usize,
+    ) -> Result<(), String> {
+        chunk.write_opcode(OpCode::OpGetUpvalue, line);
+        chunk.write_index(id, line);
         Ok(())
     }
 
-    fn emit_get_upvalue(&mut self, chunk: &mut Chunk, atom: &(Token, usize)) -> Result<(), String> {
-        let id = self
-            .get_upvalue(&atom.0.atom()?)
-            .ok_or(format!("Cannot find {:?}", atom.0))?;
-        self.add_upvalue(id, true);
-        chunk.write_opcode(OpCode::OpGetUpvalue, atom.1);
-        chunk.write_constant(dbg!(id as u8), atom.1);
+    fn emit_get_global(&mut self, chunk: &mut Chunk, atom: &(Token, usize)) -> Result<(), String> {
+        let name = atom.0.atom()?;
+        if let Some((module, global)) = name.split_once('/') {
+            let module = self.modules.borrow().by_name(module);
+            if module.is_some_and(|module| !module.exports.iter().any(|export| export == global)) {
+                return Err(format!(
+                    "{} is not provided by its module at line {}",
+                    name, atom.1
+                ));
+            }
+        }
+        let idx = identifier_constant(chunk, self.qualified(&name));
+        chunk.write_opcode(OpCode::OpGetGlobal, atom.1);
+        chunk.write_index(idx, atom.1);
         Ok(())
     }
 
@@ -254,42 +1012,349 @@ impl Compiler {
         atom: (Token, usize),
         scanner: &mut Scanner,
     ) -> Result<(), String> {
-        scanner.scan().unwrap();
-        chunk.write_opcode(OpCode::OpGetLocal, atom.1);
-        let idx = match self.get_local(&atom.0.atom()?) {
-            Some(idx) => idx,
-            None => {
-                return Err(format!("Symbol {} is not defined", atom.0.atom()?));
+        let name = atom.0.atom()?;
+        if !self.is_local(&name) {
+            if let Some((struct_type, accessor)) = self.accessor(&name) {
+                return self.emit_accessor_call(chunk, &name, struct_type, accessor, scanner);
             }
-        };
-        chunk.write_constant(idx as u8, 1);
+        }
+        scanner.scan().unwrap();
+        self.resolve_variable(chunk, &atom)?;
+        self.emit_call(chunk, atom.1, scanner)
+    }
+
+    /// Compiles a call to a struct accessor as its instruction, with the slot
+    /// of the field resolved here.
+    fn emit_accessor_call(
+        &mut self,
+        chunk: &mut Chunk,
+        name: &str,
+        struct_type: Rc<StructType>,
+        accessor: Accessor,
+        scanner: &mut Scanner,
+    ) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        let mut argc = 0;
+        while scanner.peek().ok_or("Expected ')' to close call")?.0 != Token::RightParen {
+            parse(scanner, chunk, self)?;
+            argc += 1;
+        }
+        let expected = accessor_arity(&struct_type, accessor);
+        if argc != expected {
+            return Err(format!(
+                "{} takes {} argument(s) at line {}, got {}",
+                name, expected, line, argc
+            ));
+        }
+        emit_accessor(chunk, &struct_type, accessor, line);
+        Ok(())
+    }
+
+    fn emit_call(
+        &mut self,
+        chunk: &mut Chunk,
+        line: usize,
+        scanner: &mut Scanner,
+    ) -> Result<(), String> {
+        let mut argc = 0;
         loop {
-            if scanner.peek().unwrap().0 == Token::RightParen {
+            if scanner.peek().ok_or("Expected ')' to close call")?.0 == Token::RightParen {
                 break;
             }
             parse(scanner, chunk, self)?;
+            argc += 1;
+        }
+        if argc > u8::MAX as usize {
+            return Err(format!(
+                "Too many arguments in a call at line {}, the limit is {}",
+                line,
+                u8::MAX
+            ));
         }
 
-        chunk.write_opcode(OpCode::OpCall, atom.1);
+        chunk.write_opcode(OpCode::OpCall, line);
+        chunk.write_constant(argc as u8, line);
         Ok(())
     }
 
     fn resolve_variable(&mut self, chunk: &mut Chunk, atom: &(Token, usize)) -> Result<(), String> {
-        let local = self.get_local(&atom.0.atom()?);
-        if local.is_some() {
-            self.emit_get_local(chunk, local.unwrap())?;
+        let name = atom.0.atom()?;
+        if let Some(local) = self.get_local(&name) {
+            self.emit_get_local(chunk, local)?;
+        } else if let Some(upvalue) = self.get_upvalue(&name) {
+            self.emit_get_upvalue(chunk, upvalue, atom.1)?;
         } else {
-            self.emit_get_upvalue(chunk, atom)?;
+            self.emit_get_global(chunk, atom)?;
         }
         Ok(())
     }
 }
 
+/// Writes the closure over a function compiled by `compile_function`.
+fn emit_closure(chunk: &mut Chunk, (function, compiler): (Object, Compiler), line: usize) {
+    chunk.write_opcode(OpCode::OpClosure, line);
+    let idx = chunk.add_constant(Value::Obj(Box::new(function)));
+    chunk.write_index(idx, line);
+    for upval in compiler.upvals.iter() {
+        chunk.write_constant(upval.is_local as u8, line);
+        chunk.write_index(upval.index, line);
+    }
+}
+
+fn accessor_arity(struct_type: &StructType, accessor: Accessor) -> usize {
+    match accessor {
+        Accessor::Constructor => struct_type.fields.len(),
+        Accessor::Predicate | Accessor::Getter(_) => 1,
+        Accessor::Setter(_) => 2,
+    }
+}
+
+/// Writes the instruction of an accessor, its arguments are on the stack.
+fn emit_accessor(chunk: &mut Chunk, struct_type: &Rc<StructType>, accessor: Accessor, line: usize) {
+    let idx = chunk.add_constant(Value::Obj(Box::new(Object::Struct(struct_type.clone()))));
+    let (opcode, slot) = match accessor {
+        Accessor::Constructor => (OpCode::OpInstance, None),
+        Accessor::Predicate => (OpCode::OpIsInstance, None),
+        Accessor::Getter(slot) => (OpCode::OpGetField, Some(slot)),
+        Accessor::Setter(slot) => (OpCode::OpSetField, Some(slot)),
+    };
+    chunk.write_opcode(opcode, line);
+    chunk.write_index(idx, line);
+    if let Some(slot) = slot {
+        chunk.write_index(slot, line);
+    }
+}
+
+/// The function `defstruct` defines for an accessor, for when it is used as
+/// a value rather than called by name.
+fn accessor_function(
+    name: &str,
+    struct_type: &Rc<StructType>,
+    accessor: Accessor,
+    line: usize,
+) -> Object {
+    let arity = accessor_arity(struct_type, accessor);
+    let mut chunk = Chunk::new(name);
+    for slot in 0..arity {
+        chunk.write_opcode(OpCode::OpGetLocal, line);
+        chunk.write_index(slot, line);
+    }
+    emit_accessor(&mut chunk, struct_type, accessor, line);
+    chunk.write_opcode(OpCode::OpRet, line);
+    Object::Function(Rc::new(Function {
+        name: name.to_string(),
+        chunk,
+        upvalue_count: 0,
+        arity,
+        rest: false,
+        keys: Vec::new(),
+    }))
+}
+
+/// Writes a jump with a placeholder target, returns where to patch it.
+fn emit_jump(chunk: &mut Chunk, opcode: OpCode, line: usize) -> usize {
+    chunk.write_opcode(opcode, line);
+    chunk.write_jump_target(0, line);
+    chunk.len() - 2
+}
+
+/// Points the jump at `idx` to the next instruction written.
+fn patch_jump(chunk: &mut Chunk, idx: usize) {
+    chunk.rewrite_jump_target(idx, chunk.len());
+}
+
+/// Skips one datum without compiling it.
+fn skip_datum(scanner: &mut Scanner) {
+    let mut depth = 0;
+    while let Some((token, _)) = scanner.scan() {
+        match token {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+            Token::RightParen | Token::RightBracket | Token::RightBrace => depth -= 1,
+            Token::Atom(_) | Token::Keyword(_) => {}
+        }
+        if depth <= 0 {
+            return;
+        }
+    }
+}
+
+/// Rebuilds the source of the form whose head is at the scanner, as in
+/// `(assert (> x 0))`.
+fn form_text(scanner: &Scanner) -> String {
+    let mut ahead = scanner.clone();
+    let mut text = String::from("(");
+    let mut depth = 1;
+    while let Some((token, _)) = ahead.scan() {
+        let (piece, opens) = match &token {
+            Token::LeftParen => ("(".to_string(), true),
+            Token::LeftBracket => ("[".to_string(), true),
+            Token::LeftBrace => ("{".to_string(), true),
+            Token::RightParen => (")".to_string(), false),
+            Token::RightBracket => ("]".to_string(), false),
+            Token::RightBrace => ("}".to_string(), false),
+            Token::Atom(atom) => (atom.clone(), false),
+            Token::Keyword(name) => (format!(":{}", name), false),
+        };
+        let closes = matches!(
+            token,
+            Token::RightParen | Token::RightBracket | Token::RightBrace
+        );
+        if !closes && !text.ends_with(['(', '[', '{']) {
+            text.push(' ');
+        }
+        text.push_str(&piece);
+        depth += opens as i32 - closes as i32;
+        if depth == 0 {
+            break;
+        }
+    }
+    text
+}
+
+/// The head of the form at the scanner, as in `catch` for `(catch e ...)`.
+fn clause_name(scanner: &Scanner) -> Option<String> {
+    let mut ahead = scanner.clone();
+    match ahead.scan()? {
+        (Token::LeftParen, _) => ahead.scan()?.0.atom().ok(),
+        _ => None,
+    }
+}
+
+fn expect_close(scanner: &mut Scanner, form: &str) -> Result<(), String> {
+    match scanner.scan() {
+        Some((Token::RightParen, _)) => Ok(()),
+        Some((token, line)) => Err(format!(
+            "Expected ')' to close {} at line {}, got {:?}",
+            form, line, token
+        )),
+        None => Err(format!("Expected ')' to close {}", form)),
+    }
+}
+
+fn identifier_constant(chunk: &mut Chunk, name: String) -> usize {
+    chunk.add_constant(Value::Obj(Box::new(Object::Str(intern(&name)))))
+}
+
+/// Compiles the file at `path` like `compile`, modules it requires are
+/// resolved relative to it.
+pub fn compile_file(path: &Path, chunk: &mut Chunk, compiler: &mut Compiler) -> Result<(), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    let path = path.canonicalize().map_err(|err| err.to_string())?;
+    compiler.modules.borrow_mut().enter(path)?;
+    let result = compile(&source, chunk, compiler);
+    compiler.modules.borrow_mut().leave();
+    result
+}
+
+/// Compiles the module at `path` into a function that runs its top-level
+/// forms, and records what it exports. Also returns the warnings for it.
+fn compile_module(
+    path: &Path,
+    modules: &Rc<RefCell<Modules>>,
+) -> Result<(Object, Vec<String>), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    let name = module_name(path);
+    let mut compiler = Compiler::new(None);
+    compiler.modules = modules.clone();
+    compiler.namespace = Some(Namespace {
+        name: name.clone(),
+        provided: None,
+    });
+    for definition in top_level_definitions(&source) {
+        compiler.declare_global(&definition);
+    }
+
+    let mut function = Function {
+        arity: 0,
+        chunk: Chunk::new(&name),
+        name: name.clone(),
+        upvalue_count: 0,
+        rest: false,
+        keys: Vec::new(),
+    };
+    modules.borrow_mut().enter(path.to_path_buf())?;
+    let result = compile(&source, &mut function.chunk, &mut compiler);
+    modules.borrow_mut().leave();
+    result.map_err(|err| format!("{}: {}", path.display(), err))?;
+    let warnings = compiler
+        .take_warnings()
+        .into_iter()
+        .map(|warning| format!("{}: {}", path.display(), warning))
+        .collect();
+
+    let exports = match compiler.namespace.and_then(|namespace| namespace.provided) {
+        Some(provided) => {
+            if let Some(missing) = provided
+                .iter()
+                .find(|name| !compiler.globals.contains(name))
+            {
+                return Err(format!(
+                    "{}: provides undefined {}",
+                    path.display(),
+                    missing
+                ));
+            }
+            provided
+        }
+        None => compiler.globals,
+    };
+    modules.borrow_mut().insert(Module {
+        name,
+        path: path.to_path_buf(),
+        exports,
+    })?;
+    Ok((Object::Function(Rc::new(function)), warnings))
+}
+
+/// Compiles every top-level form in `source`, the chunk returns the value of
+/// the last one (or nil when there are none).
 pub fn compile(source: &str, chunk: &mut Chunk, compiler: &mut Compiler) -> Result<(), String> {
     let mut scanner = Scanner::new(source);
-    parse(&mut scanner, chunk, compiler)?;
+    if let Some(err) = scanner.error() {
+        return Err(err.to_string());
+    }
+    let types = typecheck::check(&scanner, &compiler.types)?;
+    // every run of a top-level chunk gets a new frame, so the slots reserved
+    // by an earlier compile are free again
+    if compiler.is_top_level() {
+        compiler.locals.clear();
+    }
+    let mut empty = true;
+    while scanner.peek().is_some() {
+        if !empty {
+            chunk.write_opcode(OpCode::OpPop, scanner.get_line());
+        }
+        parse(&mut scanner, chunk, compiler)?;
+        empty = false;
+    }
+    if empty {
+        compiler.emit_nil(chunk, 0)?;
+    }
     chunk.write_opcode(OpCode::OpRet, scanner.get_line());
+    check_limits(chunk, compiler)?;
+    compiler.types = types;
+    Ok(())
+}
 
+/// Checks that the constants and slots of a compiled chunk fit in the operands
+/// written by `Chunk::write_index`.
+fn check_limits(chunk: &Chunk, compiler: &Compiler) -> Result<(), String> {
+    for (what, count) in [
+        ("constants", chunk.constant_count()),
+        ("local variables", compiler.locals.len()),
+        ("captured variables", compiler.upvals.len()),
+    ] {
+        if count > MAX_INDEX + 1 {
+            return Err(format!(
+                "Too many {} in {}, the limit is {}",
+                what,
+                chunk.get_name(),
+                MAX_INDEX + 1
+            ));
+        }
+    }
     Ok(())
 }
 
@@ -301,13 +1366,25 @@ fn parse(scanner: &mut Scanner, chunk: &mut Chunk, compiler: &mut Compiler) -> R
 
     match &token.0 {
         Token::LeftParen => read_seq(scanner, chunk, compiler)?,
+        Token::LeftBracket => read_vector(scanner, chunk, compiler)?,
+        Token::LeftBrace => read_map(scanner, chunk, compiler)?,
         Token::RightParen => {
             return Err("unexpected ')'".to_string());
         }
+        Token::RightBracket => {
+            return Err("unexpected ']'".to_string());
+        }
+        Token::RightBrace => {
+            return Err("unexpected '}'".to_string());
+        }
         Token::Atom(_) => {
             scanner.scan().unwrap();
             read_atom(token, scanner, chunk, compiler)?;
         }
+        Token::Keyword(name) => {
+            scanner.scan().unwrap();
+            compiler.emit_keyword(chunk, name, token.1)?;
+        }
     };
 
     Ok(())
@@ -345,6 +1422,9 @@ fn binary(
         "-" => chunk.write_opcode(OpCode::OpSub, op.1),
         "*" => chunk.write_opcode(OpCode::OpMul, op.1),
         "/" => chunk.write_opcode(OpCode::OpDiv, op.1),
+        "quot" => chunk.write_opcode(OpCode::OpQuot, op.1),
+        "rem" => chunk.write_opcode(OpCode::OpRem, op.1),
+        "mod" => chunk.write_opcode(OpCode::OpMod, op.1),
         "=" => chunk.write_opcode(OpCode::OpEq, op.1),
         "!=" => chunk.write_opcode(OpCode::OpNe, op.1),
         ">" => chunk.write_opcode(OpCode::OpBt, op.1),
@@ -370,97 +1450,496 @@ fn read_seq(
 ) -> Result<(), String> {
     let _ = scanner.scan();
 
-    let op = scanner.peek().unwrap();
+    // unbalanced input is common in the REPL, where it must not panic
+    let op = scanner.peek().ok_or("Missing )")?;
     match op.0 {
         Token::Atom(_) => read_atom(op, scanner, chunk, compiler)?,
-        Token::LeftParen => parse(scanner, chunk, compiler)?,
+        // keywords look themselves up in their argument
+        Token::LeftParen | Token::Keyword(_) => {
+            parse(scanner, chunk, compiler)?;
+            compiler.emit_call(chunk, op.1, scanner)?;
+        }
         _ => {
             return Err(format!("unexpected token in sequence: {:?}", op));
         }
     };
 
-    scanner.scan().unwrap();
+    scanner.scan().ok_or("Missing )")?;
+
+    Ok(())
+}
+
+/// Compiles the elements up to the closing `delimiter`, returning how many there were.
+fn read_elements(
+    delimiter: Token,
+    scanner: &mut Scanner,
+    chunk: &mut Chunk,
+    compiler: &mut Compiler,
+) -> Result<usize, String> {
+    let mut count = 0;
+    loop {
+        let token = scanner
+            .peek()
+            .ok_or(format!("Expected {:?} before end of input", delimiter))?;
+        if token.0 == delimiter {
+            let (_, line) = scanner.scan().unwrap();
+            if count > MAX_INDEX {
+                return Err(format!(
+                    "Too many elements in a literal at line {}, the limit is {}",
+                    line, MAX_INDEX
+                ));
+            }
+            return Ok(count);
+        }
+        parse(scanner, chunk, compiler)?;
+        count += 1;
+    }
+}
 
+fn read_vector(
+    scanner: &mut Scanner,
+    chunk: &mut Chunk,
+    compiler: &mut Compiler,
+) -> Result<(), String> {
+    let (_, line) = scanner.scan().unwrap();
+    let count = read_elements(Token::RightBracket, scanner, chunk, compiler)?;
+    chunk.write_opcode(OpCode::OpVector, line);
+    chunk.write_index(count, line);
+    Ok(())
+}
+
+fn read_map(
+    scanner: &mut Scanner,
+    chunk: &mut Chunk,
+    compiler: &mut Compiler,
+) -> Result<(), String> {
+    let (_, line) = scanner.scan().unwrap();
+    let count = read_elements(Token::RightBrace, scanner, chunk, compiler)?;
+    if count % 2 != 0 {
+        return Err(format!(
+            "Map literal on line {} must have an even number of forms",
+            line
+        ));
+    }
+    chunk.write_opcode(OpCode::OpMap, line);
+    chunk.write_index(count / 2, line);
     Ok(())
 }
 
-fn unescape_str(s: &str) -> String {
-    let re: Regex = Regex::new(r"\\(.)").unwrap();
-    re.replace_all(s, |caps: &Captures| {
-        (if &caps[1] == "n" { "\n" } else { &caps[1] }).to_string()
+/// Returns the contents of a string literal that starts at `line`: a raw
+/// `#"..."` literal is taken as written, a `"..."` literal has its escapes
+/// resolved.
+pub(crate) fn parse_string(literal: &str, line: usize) -> Result<String, String> {
+    if let Some(raw) = literal.strip_prefix('#') {
+        return Ok(raw[1..raw.len() - 1].to_string());
+    }
+    let contents = &literal[1..literal.len() - 1];
+    unescape_str(contents).map_err(|(offset, err)| {
+        let before = &contents[..offset];
+        format!(
+            "{} at line {}, character {} of the string literal",
+            err,
+            line + before.matches('\n').count(),
+            before.chars().count() + 2
+        )
     })
-    .to_string()
 }
 
-fn read_shallow_list(scanner: &mut Scanner) -> Option<Vec<Token>> {
-    assert!(scanner.scan().unwrap().0 == Token::LeftParen);
-    let mut v: Vec<Token> = Vec::new();
-    loop {
-        let s = scanner.scan().unwrap().0;
-        if s == Token::RightParen {
-            break;
+/// Resolves the escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\xHH` and
+/// `\u{H...}`. A backslash at the end of a line continues the string on the
+/// next one, skipping the line break and the indentation after it. Errors carry
+/// the byte offset of the offending escape.
+pub(crate) fn unescape_str(s: &str) -> Result<String, (usize, String)> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
         }
-
-        v.push(s);
+        let escaped = match chars.next() {
+            Some((_, escaped)) => escaped,
+            None => return Err((offset, "Unterminated escape".to_string())),
+        };
+        let c = match escaped {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' => escaped,
+            'x' => read_hex(&mut chars, Some(2)).ok_or_else(|| {
+                (
+                    offset,
+                    "Invalid escape \\x, expected two hex digits".to_string(),
+                )
+            })?,
+            'u' => chars
+                .next_if(|&(_, c)| c == '{')
+                .and_then(|_| read_hex(&mut chars, None))
+                .ok_or_else(|| {
+                    (
+                        offset,
+                        "Malformed \\u escape, expected \\u{H...} with a code point".to_string(),
+                    )
+                })?,
+            '\n' | '\r' => {
+                while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+                continue;
+            }
+            c => return Err((offset, format!("Unknown escape \\{}", c))),
+        };
+        unescaped.push(c);
     }
+    Ok(unescaped)
+}
 
-    Some(v)
+/// Reads the code point of a `\xHH` escape (`len` digits) or of a `\u{H...}`
+/// escape (digits up to the closing brace). Gives `None` when the digits are
+/// missing, malformed or not closed by a brace.
+fn read_hex(chars: &mut Peekable<CharIndices>, len: Option<usize>) -> Option<char> {
+    let digits: String = match len {
+        Some(len) => chars.take(len).map(|(_, c)| c).collect(),
+        None => {
+            let mut digits = String::new();
+            loop {
+                match chars.next()? {
+                    (_, '}') => break digits,
+                    (_, c) => digits.push(c),
+                }
+            }
+        }
+    };
+    if len.is_some_and(|len| digits.len() != len) {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
 }
 
 fn parse_lambda(
     scanner: &mut Scanner,
-    compiler: &Compiler,
+    compiler: &mut Compiler,
 ) -> Result<(Object, Compiler), String> {
     assert!(scanner.scan().unwrap().0 == Token::Atom("lambda".to_string()));
-    let args = read_shallow_list(scanner).unwrap();
     let mut rng = rand::thread_rng();
     let r: u32 = rng.gen();
     let name = format!("f{}", r);
 
-    let mut compiler = Compiler::new(Some(Box::new((*compiler).clone())));
+    parse_function(name, scanner, compiler)
+}
 
-    let mut function = Function {
-        arity: args.len(),
-        chunk: Chunk::new(&name),
-        name,
-        upvalue_count: 0,
+/// A lambda list: positional parameters, then optionally either `& rest`,
+/// which collects the other arguments in a list, or `&key` followed by
+/// keyword parameters, each a name or `(name default)`. Parameters can be
+/// annotated as `(x : number)` or `(port : integer 8080)`, the annotations
+/// are checked by `typecheck` and skipped here. A positional parameter can
+/// also be a pattern such as `(a b & rest)` or `{:keys [x y]}`, it takes a
+/// hidden slot that is destructured on entry.
+struct Params {
+    positional: Vec<String>,
+    rest: Option<String>,
+    keys: Vec<(String, Option<Scanner>)>,
+    patterns: Vec<(usize, Binding, usize)>,
+}
+
+/// Whether the parameter at the scanner is a pattern rather than a name or
+/// an annotated `(name : type)`.
+fn is_pattern_param(scanner: &Scanner) -> bool {
+    let mut ahead = scanner.clone();
+    match ahead.scan() {
+        Some((Token::LeftBracket | Token::LeftBrace, _)) => true,
+        Some((Token::LeftParen, _)) => {
+            ahead.scan();
+            !matches!(ahead.scan(), Some((Token::Atom(colon), _)) if colon == ":")
+        }
+        _ => false,
+    }
+}
+
+fn read_params(scanner: &mut Scanner) -> Result<Params, String> {
+    let line = scanner.get_line();
+    match scanner.scan() {
+        Some((Token::LeftParen, _)) => {}
+        _ => return Err(format!("Expected a parameter list at line {}", line)),
+    }
+    let mut params = Params {
+        positional: Vec::new(),
+        rest: None,
+        keys: Vec::new(),
+        patterns: Vec::new(),
     };
+    let mut in_keys = false;
+    loop {
+        if !in_keys && is_pattern_param(scanner) {
+            let line = scanner.get_line();
+            let binding = pattern::read_binding(scanner, &mut Vec::new())?;
+            params
+                .patterns
+                .push((params.positional.len(), binding, line));
+            params.positional.push(String::new());
+            continue;
+        }
+        match scanner.scan() {
+            Some((Token::RightParen, _)) => break,
+            Some((Token::Atom(atom), line)) if atom == "&" && !in_keys => {
+                params.rest = Some(read_rest_param(scanner, line)?);
+                break;
+            }
+            Some((Token::Atom(atom), _)) if atom == "&key" && !in_keys => in_keys = true,
+            Some((Token::Atom(atom), _)) if in_keys => params.keys.push((atom, None)),
+            Some((Token::Atom(atom), _)) => params.positional.push(atom),
+            Some((Token::LeftParen, line)) if !in_keys => {
+                let name = scanner
+                    .scan()
+                    .ok_or("Expected a parameter name")?
+                    .0
+                    .atom()?;
+                skip_annotation(scanner);
+                expect_close(scanner, &format!("the parameter {} at line {}", name, line))?;
+                params.positional.push(name);
+            }
+            Some((Token::LeftParen, line)) => {
+                let name = scanner
+                    .scan()
+                    .ok_or("Expected a parameter name")?
+                    .0
+                    .atom()?;
+                skip_annotation(scanner);
+                if matches!(scanner.peek(), Some((Token::RightParen, _)) | None) {
+                    return Err(format!("Expected a default for {} at line {}", name, line));
+                }
+                let default = scanner.clone();
+                skip_datum(scanner);
+                expect_close(
+                    scanner,
+                    &format!("the default of {} at line {}", name, line),
+                )?;
+                params.keys.push((name, Some(default)));
+            }
+            Some((token, line)) => {
+                return Err(format!(
+                    "Unexpected {:?} in parameter list at line {}",
+                    token, line
+                ))
+            }
+            None => return Err("Expected ')' to close the parameter list".to_string()),
+        }
+    }
+    Ok(params)
+}
 
-    for arg in args {
-        compiler.set_local(arg.atom()?);
+/// Reads the name after `&` in a lambda list, which has to be its last item,
+/// and the closing parenthesis.
+fn read_rest_param(scanner: &mut Scanner, line: usize) -> Result<String, String> {
+    let name = match scanner.scan() {
+        Some((Token::Atom(name), _)) if !name.starts_with('&') => name,
+        _ => {
+            return Err(format!(
+                "Expected a parameter name after & at line {}",
+                line
+            ))
+        }
+    };
+    match scanner.scan() {
+        Some((Token::RightParen, _)) => Ok(name),
+        _ => Err(format!(
+            "The & parameter has to be the last one at line {}",
+            line
+        )),
     }
-    parse(scanner, &mut function.chunk, &mut compiler)?;
+}
 
-    function.chunk.write_opcode(OpCode::OpRet, 1);
-    function.upvalue_count = compiler.upvals.len();
-    Ok((Object::Function(Box::new(function)), compiler))
+/// Compiles an argument list and body into a function. The enclosing compiler is
+/// lent to the function's compiler for the duration so that upvalues resolved
+/// through it are recorded in the enclosing scope as well.
+fn parse_function(
+    name: String,
+    scanner: &mut Scanner,
+    compiler: &mut Compiler,
+) -> Result<(Object, Compiler), String> {
+    let params = read_params(scanner)?;
+    skip_annotation(scanner);
+    compile_body(name, params, scanner, compiler)
 }
 
-fn parse_defun(
+/// Skips a type annotation such as `: number`.
+fn skip_annotation(scanner: &mut Scanner) {
+    if matches!(scanner.peek(), Some((Token::Atom(colon), _)) if colon == ":") {
+        scanner.scan();
+        scanner.scan();
+    }
+}
+
+/// Compiles the next form as the body of a function taking `args`.
+fn compile_function(
+    name: String,
+    args: Vec<String>,
     scanner: &mut Scanner,
-    compiler: &Compiler,
+    compiler: &mut Compiler,
 ) -> Result<(Object, Compiler), String> {
-    assert!(scanner.scan().unwrap().0 == Token::Atom("defun".to_string()));
-    let name = scanner.scan().unwrap().0.atom().unwrap();
-    let args = read_shallow_list(scanner).unwrap();
+    let params = Params {
+        positional: args,
+        rest: None,
+        keys: Vec::new(),
+        patterns: Vec::new(),
+    };
+    compile_body(name, params, scanner, compiler)
+}
 
-    let mut compiler = Compiler::new(Some(Box::new((*compiler).clone())));
+fn compile_body(
+    name: String,
+    params: Params,
+    scanner: &mut Scanner,
+    compiler: &mut Compiler,
+) -> Result<(Object, Compiler), String> {
+    let enclosing = std::mem::replace(compiler, Compiler::new(None));
+    let mut function_compiler = Compiler::new(Some(Box::new(enclosing)));
 
     let mut function = Function {
-        arity: args.len(),
+        arity: params.positional.len(),
         chunk: Chunk::new(&name),
         name,
         upvalue_count: 0,
+        rest: params.rest.is_some(),
+        keys: params.keys.iter().map(|(key, _)| intern(key)).collect(),
     };
 
-    for arg in args {
-        compiler.set_local(arg.atom()?);
+    for arg in params.positional.into_iter().chain(params.rest) {
+        function_compiler.set_local(arg);
     }
-    parse(scanner, &mut function.chunk, &mut compiler)?;
+    let result = emit_param_patterns(
+        &params.patterns,
+        &mut function.chunk,
+        &mut function_compiler,
+    )
+    .and_then(|_| emit_key_defaults(params.keys, &mut function.chunk, &mut function_compiler))
+    .and_then(|_| parse(scanner, &mut function.chunk, &mut function_compiler));
+    *compiler = *function_compiler.up.take().unwrap();
+    result?;
 
     function.chunk.write_opcode(OpCode::OpRet, 1);
-    function.upvalue_count = compiler.upvals.len();
-    Ok((Object::Function(Box::new(function)), compiler))
+    check_limits(&function.chunk, &function_compiler)?;
+    function.upvalue_count = function_compiler.upvals.len();
+    Ok((Object::Function(Rc::new(function)), function_compiler))
+}
+
+/// Destructures the positional parameters that are patterns.
+fn emit_param_patterns(
+    patterns: &[(usize, Binding, usize)],
+    chunk: &mut Chunk,
+    compiler: &mut Compiler,
+) -> Result<(), String> {
+    for (slot, binding, line) in patterns {
+        let line = *line;
+        chunk.write_opcode(OpCode::OpGetLocal, line);
+        chunk.write_index(*slot, line);
+        compiler.emit_destructure(chunk, binding, line)?;
+    }
+    Ok(())
+}
+
+/// Declares the `&key` parameters and fills in the defaults of the ones the
+/// caller left out or passed as nil. Each default can see the parameters
+/// before it.
+fn emit_key_defaults(
+    keys: Vec<(String, Option<Scanner>)>,
+    chunk: &mut Chunk,
+    compiler: &mut Compiler,
+) -> Result<(), String> {
+    for (key, default) in keys {
+        let slot = compiler.set_local(key);
+        let Some(mut default) = default else {
+            continue;
+        };
+        let line = default.get_line();
+        chunk.write_opcode(OpCode::OpGetLocal, line);
+        chunk.write_index(slot, line);
+        compiler.emit_nil(chunk, line)?;
+        chunk.write_opcode(OpCode::OpEq, line);
+        let given = emit_jump(chunk, OpCode::OpJmpIfFalse, line);
+        parse(&mut default, chunk, compiler)?;
+        chunk.write_opcode(OpCode::OpSetLocal, line);
+        chunk.write_index(slot, line);
+        chunk.write_opcode(OpCode::OpPop, line);
+        patch_jump(chunk, given);
+    }
+    Ok(())
+}
+
+/// Parses the part of a character literal after `#\`: a single character, a
+/// name such as `newline` or a code point such as `u{1F600}`.
+pub(crate) fn parse_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => match CHAR_NAMES.iter().find(|(name, _)| *name == s) {
+            Some((_, c)) => Some(*c),
+            None => {
+                let hex = s.strip_prefix("u{")?.strip_suffix('}')?;
+                char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+            }
+        },
+    }
+}
+
+/// Parses an integer literal (decimal, `0x` hexadecimal or `0b` binary), a
+/// rational literal like `1/3` or a float literal, including `##inf`, `##-inf`
+/// and `##nan`. Integers too large for an `i64` become bignums. Returns `None`
+/// for malformed literals.
+pub(crate) fn parse_number(s: &str) -> Option<Value> {
+    lazy_static! {
+        static ref DEC_RE: Regex = Regex::new(r"^[+-]?[0-9]+$").unwrap();
+        static ref RADIX_RE: Regex = Regex::new(r"^([+-]?)0(x[0-9a-fA-F]+|b[01]+)$").unwrap();
+        static ref RATIO_RE: Regex = Regex::new(r"^([+-]?[0-9]+)/([0-9]+)$").unwrap();
+        static ref FLOAT_RE: Regex =
+            Regex::new(r"^[+-]?([0-9]+\.[0-9]*|\.[0-9]+|[0-9]+)([eE][+-]?[0-9]+)?$").unwrap();
+    }
+
+    match s {
+        "##inf" => return Some(Value::Float(f64::INFINITY)),
+        "##-inf" => return Some(Value::Float(f64::NEG_INFINITY)),
+        "##nan" => return Some(Value::Float(f64::NAN)),
+        _ => {}
+    }
+
+    if DEC_RE.is_match(s) {
+        BigInt::from_str_radix(s, 10).ok().map(Value::from_bigint)
+    } else if let Some(captures) = RADIX_RE.captures(s) {
+        let digits = &captures[2];
+        let radix = if digits.starts_with('x') { 16 } else { 2 };
+        let literal = format!("{}{}", &captures[1], &digits[1..]);
+        BigInt::from_str_radix(&literal, radix)
+            .ok()
+            .map(Value::from_bigint)
+    } else if let Some(captures) = RATIO_RE.captures(s) {
+        let numerator = BigInt::from_str_radix(&captures[1], 10).ok()?;
+        let denominator = BigInt::from_str_radix(&captures[2], 10).ok()?;
+        if denominator.is_zero() {
+            return None;
+        }
+        Some(Value::from_ratio(BigRational::new(numerator, denominator)))
+    } else if FLOAT_RE.is_match(s) {
+        s.parse().ok().map(Value::Float)
+    } else {
+        None
+    }
+}
+
+/// Whether an atom is a number such as `-1`, `.5`, `0xff` or `##Inf`.
+pub(crate) fn is_number_literal(atom: &str) -> bool {
+    lazy_static! {
+        static ref NUMBER_RE: Regex =
+            Regex::new(r"^([+-]?([0-9]|\.[0-9]|0x[0-9a-fA-F]|0b[01])|##)").unwrap();
+    }
+    NUMBER_RE.is_match(atom)
+}
+
+/// Whether an atom is a name rather than a literal such as `1`, `"a"` or `nil`.
+fn is_symbol(atom: &str) -> bool {
+    !matches!(atom, "nil" | "true" | "false")
+        && !atom.starts_with(['"', '#'])
+        && !is_number_literal(atom)
 }
 
 fn read_atom(
@@ -470,38 +1949,59 @@ fn read_atom(
     compiler: &mut Compiler,
 ) -> Result<(), String> {
     lazy_static! {
-        static ref INT_RE: Regex = Regex::new(r"^-?[0-9]+$").unwrap();
-        static ref STR_RE: Regex = Regex::new(r#""(?:\\.|[^\\"])*""#).unwrap();
+        static ref STR_RE: Regex = Regex::new(r#"(?s)"(?:\\.|[^\\"])*""#).unwrap();
     }
 
     match &atom.0.atom()? as &str {
         "nil" => compiler.emit_nil(chunk, atom.1),
         "true" => compiler.emit_true(chunk, atom.1),
         "false" => compiler.emit_false(chunk, atom.1),
-        "+" | "-" | "*" | "/" | "=" | "!=" | "<" | "<=" | ">" | ">=" | "and" | "nand" | "or"
-        | "nor" | "xor" | "xnor" => compiler.emit_binary_operation(chunk, atom, scanner),
+        // out of call position an operator is the native of the same name, as
+        // in `(reduce + 0 xs)`
+        "+" | "-" | "*" | "/" | "quot" | "rem" | "mod" | "=" | "!=" | "<" | "<=" | ">" | ">="
+        | "and" | "nand" | "or" | "nor" | "xor" | "xnor" | "not"
+            if scanner.previous() != Some(Token::LeftParen) =>
+        {
+            compiler.resolve_variable(chunk, &atom)
+        }
+        "+" | "-" | "*" | "/" | "quot" | "rem" | "mod" | "=" | "!=" | "<" | "<=" | ">" | ">="
+        | "and" | "nand" | "or" | "nor" | "xor" | "xnor" => {
+            compiler.emit_binary_operation(chunk, atom, scanner)
+        }
         "print" => compiler.emit_print(chunk, atom, scanner),
         "set!" => {
             scanner.scan().unwrap();
             let name = scanner.scan().unwrap();
             parse(scanner, chunk, compiler)?;
-            if compiler.get_local(&name.0.atom()?).is_some() {
-                compiler.emit_set_local(chunk, &name)
-            } else if compiler.get_upvalue(&name.0.atom()?).is_some() {
-                compiler.emit_set_upvalue(chunk, &name.0.atom()?)
-            } else {
-                compiler.emit_set_local(chunk, &name)
-            }
+            compiler.emit_set(chunk, &name)
         }
         "if" => compiler.emit_if(chunk, scanner),
         "not" => compiler.emit_not(chunk, atom, scanner),
         "do" => compiler.emit_do(chunk, scanner),
+        "let" => compiler.emit_let(chunk, scanner),
+        "throw" => compiler.emit_throw(chunk, scanner),
+        "reset" => compiler.emit_reset(chunk, scanner),
+        "shift" => compiler.emit_shift(chunk, scanner),
+        "resume" => compiler.emit_resume(chunk, scanner),
+        "yield" => compiler.emit_yield(chunk, scanner),
+        "lazy-seq" => compiler.emit_lazy_seq(chunk, scanner),
+        "doseq" => compiler.emit_doseq(chunk, scanner),
+        "match" => compiler.emit_match(chunk, scanner),
+        "defstruct" => compiler.emit_defstruct(chunk, scanner),
+        "assert" | "assert-eq" => compiler.emit_assert(chunk, atom, scanner),
+        "try" => compiler.emit_try(chunk, scanner),
         "lambda" => compiler.emit_lambda(chunk, scanner),
         "defun" => compiler.emit_defun(chunk, scanner),
+        "defgeneric" => compiler.emit_defgeneric(chunk, scanner),
+        "defmethod" => compiler.emit_defmethod(chunk, scanner),
+        "require" | "import" => compiler.emit_require(chunk, scanner),
+        "provide" => compiler.emit_provide(chunk, scanner),
         _ => {
-            if INT_RE.is_match(&atom.0.atom()?) {
-                compiler.emit_integer(chunk, atom)
-            } else if STR_RE.is_match(&atom.0.atom()?) {
+            if is_number_literal(&atom.0.atom()?) {
+                compiler.emit_number(chunk, atom)
+            } else if atom.0.atom()?.starts_with("#\\") {
+                compiler.emit_char(chunk, atom)
+            } else if atom.0.atom()?.starts_with("#\"") || STR_RE.is_match(&atom.0.atom()?) {
                 compiler.emit_string(chunk, atom)
             } else if scanner.previous() != Some(Token::LeftParen) {
                 //resolve variable
@@ -565,4 +2065,195 @@ mod tests {
             vec![op!(OpCode::OpConst), constant!(0), op!(OpCode::OpRet)]
         );
     }
+
+    #[rstest]
+    fn test_compile_forms(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("1 2", &mut chunk, &mut compiler).unwrap();
+        assert_eq!(
+            chunk.get_code(),
+            vec![
+                op!(OpCode::OpConst),
+                constant!(0),
+                op!(OpCode::OpPop),
+                op!(OpCode::OpConst),
+                constant!(1),
+                op!(OpCode::OpRet)
+            ]
+        );
+        assert!(compile("1)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[test]
+    fn test_form_text() {
+        let mut scanner = Scanner::new("(assert-eq (f [1 2] {\"a\" x}) 3)");
+        scanner.scan();
+        assert_eq!(form_text(&scanner), "(assert-eq (f [1 2] {\"a\" x}) 3)");
+    }
+
+    #[rstest]
+    fn test_assert_arity(mut compiler: Compiler, mut chunk: Chunk) {
+        assert!(compile("(assert 1 2)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(assert-eq 1)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_struct_accessors(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(defstruct point x y)", &mut chunk, &mut compiler).unwrap();
+        let mut chunk = Chunk::new("test");
+        compile("(point-y p)", &mut chunk, &mut compiler).unwrap();
+        assert!(format!("{}", chunk).contains("OpGetField 1:'#<struct point>' 1"));
+        assert!(compile("(make-point 1)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(lambda () (defstruct p))", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(defstruct p a a)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_key_params(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(defun f (x &key (a 1) b) x)", &mut chunk, &mut compiler).unwrap();
+        let function = chunk
+            .constants
+            .iter()
+            .find_map(Value::get_function)
+            .unwrap();
+        assert_eq!(function.arity, 1);
+        assert_eq!(function.keys, vec![intern("a"), intern("b")]);
+        assert!(compile("(lambda (&key (a)) a)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(lambda (&key [a]) a)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_rest_param(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(defun f (x & more) x)", &mut chunk, &mut compiler).unwrap();
+        let function = chunk
+            .constants
+            .iter()
+            .find_map(Value::get_function)
+            .unwrap();
+        assert_eq!(function.arity, 1);
+        assert!(function.rest);
+        assert_eq!(
+            compile("(lambda (& more x) x)", &mut chunk, &mut compiler),
+            Err("The & parameter has to be the last one at line 0".to_string())
+        );
+        assert!(compile("(lambda (x &) x)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(lambda (& &key a) a)", &mut chunk, &mut compiler).is_err());
+        assert_eq!(
+            compile("(defun g (x & more) x) (g)", &mut chunk, &mut compiler),
+            Err("Type error at line 0, column 23: g takes at least 1 arguments, got 0".to_string())
+        );
+    }
+
+    #[rstest]
+    fn test_method_specializers(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(defstruct circle r)", &mut chunk, &mut compiler).unwrap();
+        let mut chunk = Chunk::new("test");
+        compile(
+            "(defmethod f ((c circle) (n number) x) n)",
+            &mut chunk,
+            &mut compiler,
+        )
+        .unwrap();
+        assert!(format!("{}", chunk).contains("[#<struct circle> :number nil]"));
+        let err = compile("(defmethod f ((s shape)) s)", &mut chunk, &mut compiler).unwrap_err();
+        assert_eq!(err, "Unknown type shape at line 0");
+        assert!(compile("(defgeneric f (x &key y))", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_match_decision_tree(mut compiler: Compiler, mut chunk: Chunk) {
+        compile(
+            "(match x ((list a) a) ((list a b) b) (_ 0))",
+            &mut chunk,
+            &mut compiler,
+        )
+        .unwrap();
+        let listing = format!("{}", chunk);
+        // the type is tested once for both list patterns
+        assert_eq!(listing.matches("OpIsType").count(), 1);
+        assert_eq!(listing.matches("OpLength").count(), 2);
+        assert!(compiler.take_warnings().is_empty());
+    }
+
+    #[rstest]
+    fn test_match_unreachable_clauses(mut compiler: Compiler, mut chunk: Chunk) {
+        compile(
+            "(match x\n ((list a b) a)\n ((list 1 2) 0)\n (y y)\n (_ 1))",
+            &mut chunk,
+            &mut compiler,
+        )
+        .unwrap();
+        assert_eq!(
+            compiler.take_warnings(),
+            vec![
+                "Unreachable clause (list 1 2) in match at line 2",
+                "Unreachable clause _ in match at line 4"
+            ]
+        );
+        let err = compile("(match x ((shape a) a))", &mut chunk, &mut compiler).unwrap_err();
+        assert_eq!(err, "Unknown pattern (shape ...) at line 0");
+        assert!(compile("(match x ([a a] a))", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_match_rest_patterns(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(match x ([a & rest] rest))", &mut chunk, &mut compiler).unwrap();
+        let listing = format!("{}", chunk);
+        assert!(listing.contains("OpBe"));
+        assert!(listing.contains("OpUnpack 1 1"));
+        for source in [
+            "(match x ([a & r b] a))",
+            "(match x ([a &] a))",
+            "(match x ({:a &} 1))",
+            "(match x (& 1))",
+        ] {
+            assert!(
+                compile(source, &mut chunk, &mut compiler).is_err(),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[rstest]
+    fn test_destructuring(mut compiler: Compiler, mut chunk: Chunk) {
+        compile("(let (((a b & rest) xs)) rest)", &mut chunk, &mut compiler).unwrap();
+        assert!(format!("{}", chunk).contains("OpUnpack 2 1"));
+        assert!(compiler.get_local("rest").is_none());
+        assert!(compile("(let ((1 x)) x)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(let (((a & b c) x)) a)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(let (({:vals [a]} x)) a)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(defun f ([a a]) a)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    fn test_resume_arity(mut compiler: Compiler, mut chunk: Chunk) {
+        assert!(compile("(resume)", &mut chunk, &mut compiler).is_err());
+        assert!(compile("(resume co 1 2)", &mut chunk, &mut compiler).is_err());
+    }
+
+    #[rstest]
+    #[case(r"a\tb", "a\tb")]
+    #[case(r#"\r\0\\\""#, "\r\0\\\"")]
+    #[case(r"\x41\u{e9}\u{1F600}", "Aé😀")]
+    #[case("one \\\n    two", "one two")]
+    fn test_unescape_str(#[case] escaped: &str, #[case] unescaped: &str) {
+        assert_eq!(unescape_str(escaped), Ok(unescaped.to_string()));
+    }
+
+    #[rstest]
+    fn test_unknown_escape(mut compiler: Compiler, mut chunk: Chunk) {
+        let err = compile("(print\n \"ab\\q\")", &mut chunk, &mut compiler).unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown escape \\q at line 1, character 4 of the string literal"
+        );
+        assert!(unescape_str(r"\x4").is_err());
+        assert!(unescape_str(r"\u{110000}").is_err());
+    }
+
+    #[rstest]
+    fn test_raw_string(mut compiler: Compiler, mut chunk: Chunk) {
+        compile(r#"#"C:\dir\n""#, &mut chunk, &mut compiler).unwrap();
+        assert_eq!(chunk.get_constant(1).1, &Value::from(r"C:\dir\n"));
+    }
 }
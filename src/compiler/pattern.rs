@@ -0,0 +1,938 @@
+use std::fmt;
+use std::rc::Rc;
+
+use itertools::Itertools;
+
+use crate::chunk::object::{intern, Object, StructType};
+use crate::chunk::value::Value;
+use crate::chunk::{Chunk, OpCode};
+use crate::compiler::{
+    emit_jump, expect_close, is_number_literal, parse, parse_char, parse_number, parse_string,
+    patch_jump, skip_datum, Compiler,
+};
+use crate::scanner::{Scanner, Token};
+
+/// A pattern of `match`. Names bind the part of the value they stand for,
+/// `_` matches anything without binding it.
+#[derive(Clone)]
+pub(super) enum Pattern {
+    Wildcard,
+    Bind(String),
+    Literal(Value),
+    List(Vec<Pattern>),
+    Vector(Vec<Pattern>),
+    /// `& pattern`, the last item of a list or vector pattern. It matches the
+    /// items past the ones before it, so the sequence can be longer.
+    Rest(Box<Pattern>),
+    /// Matches maps that have all the keys, whatever other keys they have.
+    Map(Vec<(Value, Pattern)>),
+    Struct(Rc<StructType>, Vec<Pattern>),
+}
+
+/// What a refutable pattern tests for at the top of the value it matches.
+/// Sequences have a length, or a least length when they end with a rest.
+#[derive(Clone)]
+enum Constructor {
+    Literal(Value),
+    List(usize, bool),
+    Vector(usize, bool),
+    Map(Vec<Value>),
+    Struct(Rc<StructType>),
+}
+
+impl Constructor {
+    fn is_same(&self, other: &Constructor) -> bool {
+        match (self, other) {
+            (Constructor::Literal(x), Constructor::Literal(y)) => x == y,
+            (Constructor::List(n, rest), Constructor::List(m, other))
+            | (Constructor::Vector(n, rest), Constructor::Vector(m, other)) => {
+                n == m && rest == other
+            }
+            (Constructor::Map(keys), Constructor::Map(others)) => {
+                keys.len() == others.len() && keys.iter().all(|key| others.contains(key))
+            }
+            (Constructor::Struct(t), Constructor::Struct(u)) => Rc::ptr_eq(t, u),
+            _ => false,
+        }
+    }
+
+    /// Whether some value has both constructors without them being the same:
+    /// maps with different keys, and sequences of a length both allow.
+    fn overlaps(&self, other: &Constructor) -> bool {
+        if self.is_same(other) {
+            return false;
+        }
+        match (self, other) {
+            (Constructor::Map(_), Constructor::Map(_)) => true,
+            (Constructor::List(n, rest), Constructor::List(m, other))
+            | (Constructor::Vector(n, rest), Constructor::Vector(m, other)) => {
+                match (rest, other) {
+                    (true, true) => true,
+                    (true, false) => m >= n,
+                    (false, true) => n >= m,
+                    (false, false) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// How many parts the constructor takes apart, the rest is one part.
+    fn arity(&self) -> usize {
+        match self {
+            Constructor::Literal(_) => 0,
+            Constructor::List(n, rest) | Constructor::Vector(n, rest) => n + *rest as usize,
+            Constructor::Map(keys) => keys.len(),
+            Constructor::Struct(struct_type) => struct_type.fields.len(),
+        }
+    }
+
+    /// The built-in type tested once for all the constructors of a kind, as
+    /// a specializer of `generic::matches`.
+    fn type_test(&self) -> Option<&'static str> {
+        match self {
+            Constructor::List(..) => Some("list"),
+            Constructor::Vector(..) => Some("vector"),
+            Constructor::Map(_) => Some("map"),
+            Constructor::Literal(_) | Constructor::Struct(_) => None,
+        }
+    }
+
+    fn is_same_kind(&self, other: &Constructor) -> bool {
+        match (self, other) {
+            (Constructor::Struct(t), Constructor::Struct(u)) => Rc::ptr_eq(t, u),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Pattern {
+    fn constructor(&self) -> Option<Constructor> {
+        Some(match self {
+            Pattern::Wildcard | Pattern::Bind(_) => return None,
+            Pattern::Literal(value) => Constructor::Literal(value.clone()),
+            Pattern::List(items) => Constructor::List(fixed_len(items), has_rest(items)),
+            Pattern::Vector(items) => Constructor::Vector(fixed_len(items), has_rest(items)),
+            // only found in a sequence, which takes it apart as its last part
+            Pattern::Rest(_) => unreachable!(),
+            Pattern::Map(entries) => {
+                Constructor::Map(entries.iter().map(|(key, _)| key.clone()).collect())
+            }
+            Pattern::Struct(struct_type, _) => Constructor::Struct(struct_type.clone()),
+        })
+    }
+
+    /// The patterns of the parts `constructor` takes apart, in its order.
+    fn children(&self, constructor: &Constructor) -> Vec<Pattern> {
+        match (self, constructor) {
+            (Pattern::List(items) | Pattern::Vector(items) | Pattern::Struct(_, items), _) => items
+                .iter()
+                .map(|item| match item {
+                    Pattern::Rest(rest) => (**rest).clone(),
+                    item => item.clone(),
+                })
+                .collect(),
+            (Pattern::Map(entries), Constructor::Map(keys)) => keys
+                .iter()
+                .map(|key| {
+                    let (_, pattern) = entries.iter().find(|(other, _)| other == key).unwrap();
+                    pattern.clone()
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn has_rest(items: &[Pattern]) -> bool {
+    matches!(items.last(), Some(Pattern::Rest(_)))
+}
+
+/// The number of items before the rest of a sequence pattern.
+fn fixed_len(items: &[Pattern]) -> usize {
+    items.len() - has_rest(items) as usize
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Bind(name) => write!(f, "{}", name),
+            Pattern::Literal(value) => write!(f, "{}", value.readable()),
+            Pattern::List(items) => write!(f, "(list {})", items.iter().join(" ")),
+            Pattern::Vector(items) => write!(f, "[{}]", items.iter().join(" ")),
+            Pattern::Rest(rest) => write!(f, "& {}", rest),
+            Pattern::Map(entries) => write!(
+                f,
+                "{{{}}}",
+                entries
+                    .iter()
+                    .map(|(key, pattern)| format!("{} {}", key.readable(), pattern))
+                    .join(" ")
+            ),
+            Pattern::Struct(struct_type, fields) => {
+                write!(f, "({} {})", struct_type.name, fields.iter().join(" "))
+            }
+        }
+    }
+}
+
+/// Reads the pattern at the scanner. `names` collects the names it binds, a
+/// name cannot be bound twice.
+pub(super) fn read_pattern(
+    scanner: &mut Scanner,
+    compiler: &Compiler,
+    names: &mut Vec<String>,
+) -> Result<Pattern, String> {
+    let (token, line) = scanner
+        .scan()
+        .ok_or("Expected a pattern but the input ended")?;
+    match token {
+        Token::Atom(atom) => match literal(&atom, line)? {
+            Some(value) => Ok(Pattern::Literal(value)),
+            None if atom == "_" => Ok(Pattern::Wildcard),
+            None if atom == "&" => Err(format!(
+                "& must come before the last item of a list or vector pattern at line {}",
+                line
+            )),
+            None => bind_name(atom, names, line).map(Pattern::Bind),
+        },
+        Token::Keyword(name) => Ok(Pattern::Literal(Value::Obj(Box::new(Object::Keyword(
+            intern(&name),
+        ))))),
+        Token::LeftBracket => Ok(Pattern::Vector(read_sequence(
+            scanner,
+            compiler,
+            names,
+            Token::RightBracket,
+        )?)),
+        Token::LeftBrace => {
+            let mut entries = Vec::new();
+            while scanner.peek().map(|(token, _)| token) != Some(Token::RightBrace) {
+                let key = match read_pattern(scanner, compiler, names)? {
+                    Pattern::Literal(key) => key,
+                    other => {
+                        return Err(format!(
+                            "Expected a literal key in a map pattern at line {}, got {}",
+                            line, other
+                        ))
+                    }
+                };
+                entries.push((key, read_pattern(scanner, compiler, names)?));
+            }
+            scanner.scan();
+            Ok(Pattern::Map(entries))
+        }
+        Token::LeftParen => {
+            let head = scanner
+                .scan()
+                .ok_or_else(|| format!("Expected a pattern at line {}", line))?
+                .0
+                .atom()?;
+            if head == "list" {
+                let items = read_sequence(scanner, compiler, names, Token::RightParen)?;
+                return Ok(Pattern::List(items));
+            }
+            let items = read_patterns(scanner, compiler, names, Token::RightParen)?;
+            let struct_type = compiler
+                .struct_type(&head)
+                .ok_or_else(|| format!("Unknown pattern ({} ...) at line {}", head, line))?;
+            if items.len() != struct_type.fields.len() {
+                return Err(format!(
+                    "The pattern for {} needs {} fields at line {}, got {}",
+                    head,
+                    struct_type.fields.len(),
+                    line,
+                    items.len()
+                ));
+            }
+            Ok(Pattern::Struct(struct_type, items))
+        }
+        token => Err(format!(
+            "Unexpected {:?} in a pattern at line {}",
+            token, line
+        )),
+    }
+}
+
+fn read_patterns(
+    scanner: &mut Scanner,
+    compiler: &Compiler,
+    names: &mut Vec<String>,
+    close: Token,
+) -> Result<Vec<Pattern>, String> {
+    let mut patterns = Vec::new();
+    loop {
+        match scanner.peek() {
+            Some((token, _)) if token == close => break,
+            Some(_) => patterns.push(read_pattern(scanner, compiler, names)?),
+            None => return Err(format!("Expected {:?} to close a pattern", close)),
+        }
+    }
+    scanner.scan();
+    Ok(patterns)
+}
+
+/// Reads the items of a list or vector pattern, the last of which can be a
+/// rest `& pattern`.
+fn read_sequence(
+    scanner: &mut Scanner,
+    compiler: &Compiler,
+    names: &mut Vec<String>,
+    close: Token,
+) -> Result<Vec<Pattern>, String> {
+    let mut patterns = Vec::new();
+    loop {
+        match scanner.peek() {
+            Some((token, _)) if token == close => break,
+            Some((Token::Atom(atom), line)) if atom == "&" => {
+                scanner.scan();
+                if scanner.peek().map_or(true, |(token, _)| token == close) {
+                    return Err(format!("Expected a pattern after & at line {}", line));
+                }
+                let rest = read_pattern(scanner, compiler, names)?;
+                if scanner.peek().map(|(token, _)| token) != Some(close.clone()) {
+                    return Err(format!(
+                        "& must come before the last item of a list or vector pattern at line {}",
+                        line
+                    ));
+                }
+                patterns.push(Pattern::Rest(Box::new(rest)));
+            }
+            Some(_) => patterns.push(read_pattern(scanner, compiler, names)?),
+            None => return Err(format!("Expected {:?} to close a pattern", close)),
+        }
+    }
+    scanner.scan();
+    Ok(patterns)
+}
+
+/// The value of a literal atom, or `None` for a name.
+fn literal(atom: &str, line: usize) -> Result<Option<Value>, String> {
+    Ok(Some(match atom {
+        "nil" => Value::Nil,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ if is_number_literal(atom) => parse_number(atom)
+            .ok_or_else(|| format!("Invalid number literal {} at line {}", atom, line))?,
+        _ if atom.starts_with("#\\") => Value::Char(
+            parse_char(&atom["#\\".len()..])
+                .ok_or_else(|| format!("Invalid character literal {} at line {}", atom, line))?,
+        ),
+        _ if atom.starts_with('"') || atom.starts_with("#\"") => {
+            Value::Obj(Box::new(Object::Str(intern(&parse_string(atom, line)?))))
+        }
+        _ => return Ok(None),
+    }))
+}
+
+/// A clause of `match`: `(pattern body)` or `(pattern :when guard body)`.
+struct Clause {
+    pattern: Pattern,
+    guard: Option<Scanner>,
+    body: Scanner,
+    line: usize,
+}
+
+/// A row of the matrix of patterns still to test. `bindings` are the names
+/// already matched, with the local slot holding their value.
+#[derive(Clone)]
+struct Row {
+    patterns: Vec<Pattern>,
+    bindings: Vec<(String, usize)>,
+    clause: usize,
+}
+
+/// Rows to try, on the values in the local slots `occurrences`, when the
+/// ones before them fail.
+#[derive(Clone)]
+struct Matrix {
+    rows: Vec<Row>,
+    occurrences: Vec<usize>,
+}
+
+impl Compiler {
+    /// Compiles `(match expr clause...)` to a decision tree. Each node tests
+    /// the type or value of one part of the value and jumps to the subtree of
+    /// the clauses it leaves possible. Parts of the value are kept in hidden
+    /// locals, the names a clause binds are in scope in its guard and body
+    /// only. Clauses that no path reaches are reported as warnings.
+    pub(super) fn emit_match(
+        &mut self,
+        chunk: &mut Chunk,
+        scanner: &mut Scanner,
+    ) -> Result<(), String> {
+        let (_, line) = scanner.scan().unwrap();
+        parse(scanner, chunk, self)?;
+        let subject = self.set_local(String::new());
+        chunk.write_opcode(OpCode::OpSetLocal, line);
+        chunk.write_index(subject, line);
+        chunk.write_opcode(OpCode::OpPop, line);
+
+        let mut clauses = Vec::new();
+        while scanner.peek().map(|(token, _)| token) == Some(Token::LeftParen) {
+            let (_, line) = scanner.scan().unwrap();
+            let pattern = read_pattern(scanner, self, &mut Vec::new())?;
+            let guard = match scanner.peek() {
+                Some((Token::Keyword(keyword), _)) if keyword == "when" => {
+                    scanner.scan();
+                    let guard = scanner.clone();
+                    skip_datum(scanner);
+                    Some(guard)
+                }
+                _ => None,
+            };
+            let body = scanner.clone();
+            skip_datum(scanner);
+            expect_close(scanner, "the match clause")?;
+            clauses.push(Clause {
+                pattern,
+                guard,
+                body,
+                line,
+            });
+        }
+        if let Some((token, line)) = scanner
+            .peek()
+            .filter(|(token, _)| *token != Token::RightParen)
+        {
+            return Err(format!(
+                "Expected a clause (pattern body) in match at line {}, got {:?}",
+                line, token
+            ));
+        }
+
+        let rows = clauses
+            .iter()
+            .enumerate()
+            .map(|(clause, Clause { pattern, .. })| Row {
+                patterns: vec![pattern.clone()],
+                bindings: Vec::new(),
+                clause,
+            })
+            .collect();
+        let mut tree = DecisionTree {
+            clauses: &clauses,
+            subject,
+            reached: vec![false; clauses.len()],
+            ends: Vec::new(),
+        };
+        tree.emit(self, chunk, rows, &[subject], &[])?;
+        for end in std::mem::take(&mut tree.ends) {
+            patch_jump(chunk, end);
+        }
+        for (clause, reached) in clauses.iter().zip(tree.reached) {
+            if !reached {
+                self.warn(format!(
+                    "Unreachable clause {} in match at line {}",
+                    clause.pattern, clause.line
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct DecisionTree<'a> {
+    clauses: &'a [Clause],
+    subject: usize,
+    reached: Vec<bool>,
+    /// The jumps to the end of the match, after each body.
+    ends: Vec<usize>,
+}
+
+impl DecisionTree<'_> {
+    /// Emits the tests selecting the first row of `rows` that matches the
+    /// values in `occurrences`, falling back to the last of `fallbacks`.
+    fn emit(
+        &mut self,
+        compiler: &mut Compiler,
+        chunk: &mut Chunk,
+        rows: Vec<Row>,
+        occurrences: &[usize],
+        fallbacks: &[Matrix],
+    ) -> Result<(), String> {
+        let Some(first) = rows.first() else {
+            return match fallbacks.split_last() {
+                Some((fallback, rest)) => self.emit(
+                    compiler,
+                    chunk,
+                    fallback.rows.clone(),
+                    &fallback.occurrences,
+                    rest,
+                ),
+                None => {
+                    let line = self.clauses.last().map_or(0, |clause| clause.line);
+                    emit_get_local(chunk, self.subject, line);
+                    chunk.write_opcode(OpCode::OpNoMatch, line);
+                    Ok(())
+                }
+            };
+        };
+        match first
+            .patterns
+            .iter()
+            .position(|p| p.constructor().is_some())
+        {
+            Some(column) => self.emit_switch(compiler, chunk, rows, occurrences, fallbacks, column),
+            None => self.emit_leaf(compiler, chunk, rows, occurrences, fallbacks),
+        }
+    }
+
+    /// The first row matches, binds its names and runs its body when its guard
+    /// holds, the other rows are tried when it does not.
+    fn emit_leaf(
+        &mut self,
+        compiler: &mut Compiler,
+        chunk: &mut Chunk,
+        mut rows: Vec<Row>,
+        occurrences: &[usize],
+        fallbacks: &[Matrix],
+    ) -> Result<(), String> {
+        let row = rows.remove(0);
+        let clause = &self.clauses[row.clause];
+        self.reached[row.clause] = true;
+
+        let mut bindings = row.bindings;
+        for (pattern, occurrence) in row.patterns.iter().zip(occurrences) {
+            if let Pattern::Bind(name) = pattern {
+                bindings.push((name.clone(), *occurrence));
+            }
+        }
+        let mut slots = Vec::new();
+        for (name, occurrence) in bindings {
+            emit_get_local(chunk, occurrence, clause.line);
+            let slot = compiler.set_local(name);
+            chunk.write_opcode(OpCode::OpSetLocal, clause.line);
+            chunk.write_index(slot, clause.line);
+            chunk.write_opcode(OpCode::OpPop, clause.line);
+            slots.push(slot);
+        }
+
+        let failed = match &clause.guard {
+            Some(guard) => {
+                parse(&mut guard.clone(), chunk, compiler)?;
+                Some(emit_jump(chunk, OpCode::OpJmpIfFalse, clause.line))
+            }
+            None => None,
+        };
+        parse(&mut clause.body.clone(), chunk, compiler)?;
+        self.ends.push(emit_jump(chunk, OpCode::OpJmp, clause.line));
+        // the slots stay reserved but the names are out of scope
+        for slot in slots {
+            compiler.locals[slot] = String::new();
+        }
+
+        match failed {
+            Some(failed) => {
+                patch_jump(chunk, failed);
+                self.emit(compiler, chunk, rows, occurrences, fallbacks)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Tests the value in `column` against the constructors of the rows, and
+    /// continues with the rows each one leaves. Rows from the first one whose
+    /// constructor overlaps one before it are tried when these fail.
+    fn emit_switch(
+        &mut self,
+        compiler: &mut Compiler,
+        chunk: &mut Chunk,
+        mut rows: Vec<Row>,
+        occurrences: &[usize],
+        fallbacks: &[Matrix],
+        column: usize,
+    ) -> Result<(), String> {
+        let line = self.clauses[rows[0].clause].line;
+        let occurrence = occurrences[column];
+        let mut constructors: Vec<Constructor> = Vec::new();
+        let split = rows
+            .iter()
+            .position(|row| match row.patterns[column].constructor() {
+                Some(constructor) => {
+                    let overlaps = constructors
+                        .iter()
+                        .any(|other| other.overlaps(&constructor));
+                    if !overlaps && !constructors.iter().any(|other| other.is_same(&constructor)) {
+                        constructors.push(constructor);
+                    }
+                    overlaps
+                }
+                None => false,
+            })
+            .unwrap_or(rows.len());
+        let mut fallbacks = fallbacks.to_vec();
+        let rest = rows.split_off(split);
+        if !rest.is_empty() {
+            fallbacks.push(Matrix {
+                rows: rest,
+                occurrences: occurrences.to_vec(),
+            });
+        }
+
+        // constructors of different kinds never match the same value, so
+        // each kind is tested once for all of its constructors
+        let mut kinds: Vec<Vec<Constructor>> = Vec::new();
+        for constructor in constructors {
+            match kinds
+                .iter_mut()
+                .find(|kind| kind[0].is_same_kind(&constructor))
+            {
+                Some(kind) => kind.push(constructor),
+                None => kinds.push(vec![constructor]),
+            }
+        }
+        let mut to_default = Vec::new();
+        let count = kinds.len();
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let wrong_type = emit_type_test(chunk, &kind[0], occurrence, line);
+            for constructor in kind {
+                let failed = emit_test(chunk, &constructor, occurrence, line);
+                let parts = emit_parts(compiler, chunk, &constructor, occurrence, line);
+                let specialized = rows
+                    .iter()
+                    .filter_map(|row| specialize(row, column, &constructor, occurrence))
+                    .collect();
+                let mut inner = occurrences.to_vec();
+                inner.splice(column..=column, parts);
+                self.emit(compiler, chunk, specialized, &inner, &fallbacks)?;
+                for jump in failed {
+                    patch_jump(chunk, jump);
+                }
+            }
+            // literals have no type test, the next kinds are still possible
+            if let Some(wrong_type) = wrong_type {
+                if i + 1 < count {
+                    to_default.push(emit_jump(chunk, OpCode::OpJmp, line));
+                }
+                patch_jump(chunk, wrong_type);
+            }
+        }
+        for jump in to_default {
+            patch_jump(chunk, jump);
+        }
+
+        let defaults = rows
+            .iter()
+            .filter_map(|row| default_row(row, column, occurrence))
+            .collect();
+        let mut rest = occurrences.to_vec();
+        rest.remove(column);
+        self.emit(compiler, chunk, defaults, &rest, &fallbacks)
+    }
+}
+
+/// The row for the values `constructor` takes apart, or `None` when the
+/// pattern in `column` has another constructor.
+fn specialize(
+    row: &Row,
+    column: usize,
+    constructor: &Constructor,
+    occurrence: usize,
+) -> Option<Row> {
+    let pattern = &row.patterns[column];
+    let mut bindings = row.bindings.clone();
+    let parts = match pattern.constructor() {
+        Some(other) if other.is_same(constructor) => pattern.children(constructor),
+        Some(_) => return None,
+        None => {
+            if let Pattern::Bind(name) = pattern {
+                bindings.push((name.clone(), occurrence));
+            }
+            vec![Pattern::Wildcard; constructor.arity()]
+        }
+    };
+    let mut patterns = row.patterns.clone();
+    patterns.splice(column..=column, parts);
+    Some(Row {
+        patterns,
+        bindings,
+        clause: row.clause,
+    })
+}
+
+/// The row for values none of the constructors match, or `None` when the
+/// pattern in `column` has a constructor.
+fn default_row(row: &Row, column: usize, occurrence: usize) -> Option<Row> {
+    let mut row = row.clone();
+    match row.patterns.remove(column) {
+        Pattern::Wildcard => {}
+        Pattern::Bind(name) => row.bindings.push((name, occurrence)),
+        _ => return None,
+    }
+    Some(row)
+}
+
+fn emit_get_local(chunk: &mut Chunk, slot: usize, line: usize) {
+    chunk.write_opcode(OpCode::OpGetLocal, line);
+    chunk.write_index(slot, line);
+}
+
+/// Tests the type shared by the constructors of a kind, returning the jump
+/// taken when the value has another type.
+fn emit_type_test(
+    chunk: &mut Chunk,
+    constructor: &Constructor,
+    occurrence: usize,
+    line: usize,
+) -> Option<usize> {
+    let (opcode, constant) = match (constructor, constructor.type_test()) {
+        (Constructor::Struct(struct_type), _) => (
+            OpCode::OpIsInstance,
+            Value::Obj(Box::new(Object::Struct(struct_type.clone()))),
+        ),
+        (_, Some(name)) => (
+            OpCode::OpIsType,
+            Value::Obj(Box::new(Object::Keyword(intern(name)))),
+        ),
+        (_, None) => return None,
+    };
+    emit_get_local(chunk, occurrence, line);
+    chunk.write_opcode(opcode, line);
+    let idx = chunk.add_constant(constant);
+    chunk.write_index(idx, line);
+    Some(emit_jump(chunk, OpCode::OpJmpIfFalse, line))
+}
+
+/// Tests a value already known to have the type of `constructor`, returning
+/// the jumps taken when it does not match.
+fn emit_test(
+    chunk: &mut Chunk,
+    constructor: &Constructor,
+    occurrence: usize,
+    line: usize,
+) -> Vec<usize> {
+    let mut jumps = Vec::new();
+    match constructor {
+        Constructor::Literal(value) => {
+            emit_get_local(chunk, occurrence, line);
+            emit_constant(chunk, value.clone(), line);
+            chunk.write_opcode(OpCode::OpEq, line);
+            jumps.push(emit_jump(chunk, OpCode::OpJmpIfFalse, line));
+        }
+        Constructor::List(n, rest) | Constructor::Vector(n, rest) => {
+            emit_get_local(chunk, occurrence, line);
+            chunk.write_opcode(OpCode::OpLength, line);
+            emit_constant(chunk, Value::Int(*n as i64), line);
+            let opcode = if *rest { OpCode::OpBe } else { OpCode::OpEq };
+            chunk.write_opcode(opcode, line);
+            jumps.push(emit_jump(chunk, OpCode::OpJmpIfFalse, line));
+        }
+        Constructor::Map(keys) => {
+            for key in keys {
+                emit_get_local(chunk, occurrence, line);
+                chunk.write_opcode(OpCode::OpHasKey, line);
+                let idx = chunk.add_constant(key.clone());
+                chunk.write_index(idx, line);
+                jumps.push(emit_jump(chunk, OpCode::OpJmpIfFalse, line));
+            }
+        }
+        Constructor::Struct(_) => {}
+    }
+    jumps
+}
+
+/// Stores the parts of a value matching `constructor` in new hidden locals
+/// and returns their slots.
+fn emit_parts(
+    compiler: &mut Compiler,
+    chunk: &mut Chunk,
+    constructor: &Constructor,
+    occurrence: usize,
+    line: usize,
+) -> Vec<usize> {
+    if let Constructor::List(n, true) | Constructor::Vector(n, true) = constructor {
+        // the items then the rest are on the stack, the rest on top
+        emit_get_local(chunk, occurrence, line);
+        chunk.write_opcode(OpCode::OpUnpack, line);
+        chunk.write_index(*n, line);
+        chunk.write_constant(1, line);
+        let mut slots: Vec<usize> = (0..=*n)
+            .map(|_| {
+                let slot = compiler.set_local(String::new());
+                chunk.write_opcode(OpCode::OpSetLocal, line);
+                chunk.write_index(slot, line);
+                chunk.write_opcode(OpCode::OpPop, line);
+                slot
+            })
+            .collect();
+        slots.reverse();
+        return slots;
+    }
+    (0..constructor.arity())
+        .map(|i| {
+            emit_get_local(chunk, occurrence, line);
+            match constructor {
+                Constructor::List(..) | Constructor::Vector(..) => {
+                    chunk.write_opcode(OpCode::OpNth, line);
+                    chunk.write_index(i, line);
+                }
+                Constructor::Map(keys) => {
+                    chunk.write_opcode(OpCode::OpGetKey, line);
+                    let idx = chunk.add_constant(keys[i].clone());
+                    chunk.write_index(idx, line);
+                }
+                Constructor::Struct(struct_type) => {
+                    chunk.write_opcode(OpCode::OpGetField, line);
+                    let struct_value = Value::Obj(Box::new(Object::Struct(struct_type.clone())));
+                    let idx = chunk.add_constant(struct_value);
+                    chunk.write_index(idx, line);
+                    chunk.write_index(i, line);
+                }
+                Constructor::Literal(_) => unreachable!(),
+            }
+            let slot = compiler.set_local(String::new());
+            chunk.write_opcode(OpCode::OpSetLocal, line);
+            chunk.write_index(slot, line);
+            chunk.write_opcode(OpCode::OpPop, line);
+            slot
+        })
+        .collect()
+}
+
+fn emit_constant(chunk: &mut Chunk, value: Value, line: usize) {
+    let idx = chunk.add_constant(value);
+    chunk.write_load_constant(idx, line);
+}
+
+/// A pattern of `let` or of a function parameter. It takes the value apart
+/// without testing it, the items past the end of a sequence are nil.
+pub(super) enum Binding {
+    Ignore,
+    Name(String),
+    /// `(a b & rest)` or `[a b & rest]`.
+    Sequence(Vec<Binding>, Option<Box<Binding>>),
+    /// `{:keys [x y]}` binds `x` to `(:x value)`, so it works on structs too.
+    Keys(Vec<String>),
+}
+
+/// Reads the binding pattern at the scanner. `names` collects the names it
+/// binds, a name cannot be bound twice.
+pub(super) fn read_binding(
+    scanner: &mut Scanner,
+    names: &mut Vec<String>,
+) -> Result<Binding, String> {
+    let (token, line) = scanner
+        .scan()
+        .ok_or("Expected a binding but the input ended")?;
+    match token {
+        Token::Atom(atom) if atom == "_" => Ok(Binding::Ignore),
+        Token::Atom(atom) => {
+            if literal(&atom, line)?.is_some() || atom == "&" {
+                return Err(format!("Cannot bind {} at line {}", atom, line));
+            }
+            bind_name(atom, names, line).map(Binding::Name)
+        }
+        Token::LeftParen | Token::LeftBracket => {
+            let close = if token == Token::LeftParen {
+                Token::RightParen
+            } else {
+                Token::RightBracket
+            };
+            let mut items = Vec::new();
+            let mut rest = None;
+            loop {
+                match scanner.peek() {
+                    Some((token, _)) if token == close => break,
+                    Some((Token::Atom(atom), _)) if atom == "&" && rest.is_none() => {
+                        scanner.scan();
+                        rest = Some(Box::new(read_binding(scanner, names)?));
+                        if scanner.peek().map(|(token, _)| token) != Some(close.clone()) {
+                            return Err(format!(
+                                "Expected the end of the pattern after the rest at line {}",
+                                line
+                            ));
+                        }
+                    }
+                    Some(_) => items.push(read_binding(scanner, names)?),
+                    None => return Err(format!("Expected {:?} to close a pattern", close)),
+                }
+            }
+            scanner.scan();
+            Ok(Binding::Sequence(items, rest))
+        }
+        Token::LeftBrace => {
+            let expected = || format!("Expected {{:keys [names]}} at line {}", line);
+            if scanner.scan().map(|(token, _)| token) != Some(Token::Keyword("keys".to_string()))
+                || scanner.scan().map(|(token, _)| token) != Some(Token::LeftBracket)
+            {
+                return Err(expected());
+            }
+            let mut keys = Vec::new();
+            loop {
+                match scanner.scan() {
+                    Some((Token::RightBracket, _)) => break,
+                    Some((Token::Atom(atom), line)) => keys.push(bind_name(atom, names, line)?),
+                    _ => return Err(expected()),
+                }
+            }
+            if scanner.scan().map(|(token, _)| token) != Some(Token::RightBrace) {
+                return Err(expected());
+            }
+            Ok(Binding::Keys(keys))
+        }
+        token => Err(format!(
+            "Unexpected {:?} in a binding at line {}",
+            token, line
+        )),
+    }
+}
+
+fn bind_name(name: String, names: &mut Vec<String>, line: usize) -> Result<String, String> {
+    if names.contains(&name) {
+        return Err(format!(
+            "{} is bound twice in a pattern at line {}",
+            name, line
+        ));
+    }
+    names.push(name.clone());
+    Ok(name)
+}
+
+impl Compiler {
+    /// Binds the names of `binding` to the parts of the value on top of the
+    /// stack, which is popped. The names are locals from here on.
+    pub(super) fn emit_destructure(
+        &mut self,
+        chunk: &mut Chunk,
+        binding: &Binding,
+        line: usize,
+    ) -> Result<(), String> {
+        match binding {
+            Binding::Ignore => chunk.write_opcode(OpCode::OpPop, line),
+            Binding::Name(name) => {
+                let slot = self.set_local(name.clone());
+                chunk.write_opcode(OpCode::OpSetLocal, line);
+                chunk.write_index(slot, line);
+                chunk.write_opcode(OpCode::OpPop, line);
+            }
+            Binding::Sequence(items, rest) => {
+                chunk.write_opcode(OpCode::OpUnpack, line);
+                chunk.write_index(items.len(), line);
+                chunk.write_constant(rest.is_some() as u8, line);
+                // the parts are on the stack in order, the last one on top
+                if let Some(rest) = rest {
+                    self.emit_destructure(chunk, rest, line)?;
+                }
+                for item in items.iter().rev() {
+                    self.emit_destructure(chunk, item, line)?;
+                }
+            }
+            Binding::Keys(keys) => {
+                let slot = self.set_local(String::new());
+                chunk.write_opcode(OpCode::OpSetLocal, line);
+                chunk.write_index(slot, line);
+                chunk.write_opcode(OpCode::OpPop, line);
+                for key in keys {
+                    self.emit_keyword(chunk, key, line)?;
+                    emit_get_local(chunk, slot, line);
+                    chunk.write_opcode(OpCode::OpCall, line);
+                    chunk.write_constant(1, line);
+                    self.emit_destructure(chunk, &Binding::Name(key.clone()), line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+
+/// Conversion from a flox value into a host type.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+/// Conversion from a host type into a flox value.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+fn type_error<T>(expected: &str, value: &Value) -> Result<T, String> {
+    Err(format!("Expected {}, got: {:?}", expected, value))
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_number() {
+            Some(n) => Ok(n),
+            None => type_error("number", value),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_int() {
+            Some(i) => Ok(i),
+            None => type_error("integer", value),
+        }
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_char() {
+            Some(c) => Ok(c),
+            None => type_error("char", value),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_bool() {
+            Some(b) => Ok(b),
+            None => type_error("bool", value),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_str() {
+            Some(s) => Ok(s.to_string()),
+            None => type_error("string", value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_vector().or_else(|| value.get_list()) {
+            Some(list) => list.iter().map(T::from_value).collect(),
+            None => type_error("vector", value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value.get_map() {
+            Some(map) => map
+                .iter()
+                .map(|(k, v)| Ok((String::from_value(k)?, T::from_value(v)?)))
+                .collect(),
+            None => type_error("map", value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => Ok(Some(T::from_value(value)?)),
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Int(self as i64)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl IntoValue for char {
+    fn into_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Obj(Box::new(Object::Str(self.into())))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        self.to_string().into_value()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let vector = self.into_iter().map(IntoValue::into_value).collect();
+        Value::Obj(Box::new(Object::Vector(Rc::new(vector))))
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        let map = self
+            .into_iter()
+            .map(|(k, v)| (k.into_value(), v.into_value()))
+            .collect();
+        Value::Obj(Box::new(Object::Map(Rc::new(map))))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+macro_rules! impl_from_for_value {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Value {
+                    value.into_value()
+                }
+            }
+        )*
+    };
+}
+
+impl_from_for_value!(f64, i32, i64, char, bool, String, &str);
+
+impl<T: IntoValue> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Value {
+        value.into_value()
+    }
+}
+
+impl<T: IntoValue> From<HashMap<String, T>> for Value {
+    fn from(value: HashMap<String, T>) -> Value {
+        value.into_value()
+    }
+}
+
+impl<T: IntoValue> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.into_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_round_trip() {
+        let value: Value = 2.5.into();
+        assert_eq!(f64::from_value(&value), Ok(2.5));
+        assert!(bool::from_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_int_round_trip() {
+        let value: Value = 3.into();
+        assert_eq!(i64::from_value(&value), Ok(3));
+        assert_eq!(f64::from_value(&value), Ok(3.0));
+        assert!(i64::from_value(&Value::Float(3.0)).is_err());
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let value: Value = "ola".into();
+        assert_eq!(String::from_value(&value), Ok("ola".to_string()));
+    }
+
+    #[test]
+    fn test_vec_round_trip() {
+        let value: Value = vec![1.0, 2.0].into();
+        assert_eq!(Vec::<f64>::from_value(&value), Ok(vec![1.0, 2.0]));
+    }
+
+    #[test]
+    fn test_map_round_trip() {
+        let map = HashMap::from([("a".to_string(), true)]);
+        let value: Value = map.clone().into();
+        assert_eq!(HashMap::<String, bool>::from_value(&value), Ok(map));
+    }
+
+    #[test]
+    fn test_option_round_trip() {
+        let value: Value = None::<f64>.into();
+        assert!(value.is_nil());
+        assert_eq!(Option::<f64>::from_value(&value), Ok(None));
+        assert_eq!(Option::<f64>::from_value(&Value::Int(1)), Ok(Some(1.0)));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::chunk::object::NativeFn;
+use crate::chunk::Chunk;
+use crate::chunk::Value;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::{compile, compile_file, Compiler};
+use crate::convert::{FromValue, IntoValue};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+/// A long lived compiler and virtual machine pair. Globals defined by one call
+/// to `eval` are visible to the next, and flox functions can be called from the
+/// host by name.
+pub struct Interpreter {
+    compiler: Compiler,
+    vm: VirtualMachine,
+    debug: bool,
+}
+
+impl Interpreter {
+    pub fn new(debug: bool) -> Interpreter {
+        Interpreter {
+            compiler: Compiler::new(None),
+            vm: VirtualMachine::new(debug),
+            debug,
+        }
+    }
+
+    /// An interpreter whose machine does not load the prelude.
+    pub fn without_prelude(debug: bool) -> Interpreter {
+        Interpreter {
+            compiler: Compiler::new(None),
+            vm: VirtualMachine::without_prelude(debug),
+            debug,
+        }
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, VMErr> {
+        let mut chunk = Chunk::new("main");
+        let compiled = compile(source, &mut chunk, &mut self.compiler);
+        self.run(compiled.map(|_| chunk))
+    }
+
+    /// Evaluates the file at `path`, the modules it requires are looked up
+    /// next to it before the search path.
+    pub fn eval_file(&mut self, path: &Path) -> Result<Value, VMErr> {
+        let mut chunk = Chunk::new("main");
+        let compiled = compile_file(path, &mut chunk, &mut self.compiler);
+        self.run(compiled.map(|_| chunk))
+    }
+
+    /// Runs a compiled chunk. The modules it requires only count as loaded
+    /// once it ran without errors.
+    fn run(&mut self, chunk: Result<Chunk, String>) -> Result<Value, VMErr> {
+        let result = chunk.map_err(VMErr::CompileError).and_then(|chunk| {
+            if self.debug {
+                println!("{}", chunk);
+            }
+            self.vm.run(&chunk)
+        });
+        self.compiler.finish_modules(result.is_ok());
+        result
+    }
+
+    /// The compiler warnings of the sources evaluated since the last call,
+    /// such as unreachable clauses of a `match`. The interpreter does not print
+    /// them, that is left to the caller.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.compiler.take_warnings()
+    }
+
+    /// Realizes the lazy sequences in a value, such as one returned by `eval`,
+    /// so that printing it shows their items.
+    pub fn realize(&mut self, value: &Value) -> Result<Value, VMErr> {
+        self.vm.realize(value)
+    }
+
+    /// Sets the directories searched by `require` and `import`.
+    pub fn set_search_path(&mut self, search_path: Vec<PathBuf>) {
+        self.compiler.set_search_path(search_path);
+    }
+
+    pub fn eval_as<T: FromValue>(&mut self, source: &str) -> Result<T, VMErr> {
+        let value = self.eval(source)?;
+        T::from_value(&value).map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))
+    }
+
+    /// Calls the global function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, VMErr> {
+        let callee = self.vm.get_global(name).cloned().ok_or_else(|| {
+            VMErr::RuntimeError(
+                ErrorKind::UndefinedSymbol,
+                format!("Symbol {} is not defined", name),
+            )
+        })?;
+
+        self.vm.call(&callee, args)
+    }
+
+    pub fn call_as<T: FromValue>(&mut self, name: &str, args: &[Value]) -> Result<T, VMErr> {
+        let value = self.call(name, args)?;
+        T::from_value(&value).map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))
+    }
+
+    /// Exposes a Rust function to flox code under `name`.
+    pub fn define_native(&mut self, name: &str, arity: Option<usize>, function: NativeFn) {
+        self.vm.define_native(name, arity, function);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.vm.get_global(name)
+    }
+
+    pub fn set_global<T: IntoValue>(&mut self, name: &str, value: T) {
+        self.vm.set_global(name, value.into_value());
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::MAX_INDEX;
+
+    #[test]
+    fn test_eval_keeps_state() {
+        let mut interp = Interpreter::default();
+        interp.eval("(set! x 41)").unwrap();
+        assert_eq!(interp.eval_as::<f64>("(+ x 1)").unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_call() {
+        let mut interp = Interpreter::default();
+        interp.eval("(defun f (x) (+ x 1))").unwrap();
+        assert_eq!(interp.call_as::<f64>("f", &[1.into()]).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_without_prelude() {
+        let mut interp = Interpreter::without_prelude(false);
+        assert!(interp.eval("(identity 1)").is_err());
+        let mut interp = Interpreter::default();
+        assert_eq!(interp.eval_as::<f64>("(identity 1)").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_take_warnings() {
+        let mut interp = Interpreter::default();
+        interp.eval("(match 1 (x x) (1 0))").unwrap();
+        assert_eq!(
+            interp.take_warnings(),
+            vec!["Unreachable clause 1 in match at line 0"]
+        );
+        assert!(interp.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_top_level_slots_are_reused() {
+        let mut interp = Interpreter::default();
+        for _ in 0..=MAX_INDEX + 1 {
+            interp.eval("(let ((x 1)) x)").unwrap();
+        }
+        assert_eq!(
+            interp
+                .eval_as::<i64>("(try (throw 1) (catch e (match e (n n))))")
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_call_undefined() {
+        let mut interp = Interpreter::default();
+        assert!(interp.call("f", &[]).is_err());
+    }
+
+    #[test]
+    fn test_call_wrong_arity() {
+        let mut interp = Interpreter::default();
+        interp.eval("(defun f (x) x)").unwrap();
+        assert!(interp.call("f", &[]).is_err());
+        assert_eq!(interp.call_as::<f64>("f", &[3.into()]).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_define_native() {
+        fn double(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+            args[0]
+                .checked_mul(&Value::Int(2))
+                .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))
+        }
+
+        let mut interp = Interpreter::default();
+        interp.define_native("double", Some(1), double);
+        assert_eq!(interp.eval_as::<i64>("(double 21)").unwrap(), 42);
+        assert_eq!(interp.call_as::<f64>("double", &[2.5.into()]).unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_set_global() {
+        let mut interp = Interpreter::default();
+        interp.set_global("names", vec!["a", "b"]);
+        assert_eq!(
+            interp.eval_as::<Vec<String>>("names").unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_require_once() {
+        let mut interp = Interpreter::default();
+        interp
+            .eval("(require \"tests/modules/counter.flox\")")
+            .unwrap();
+        interp.eval("(counter/bump)").unwrap();
+        interp
+            .eval("(require \"tests/modules/counter.flox\")")
+            .unwrap();
+        assert_eq!(interp.eval_as::<i64>("(counter/bump)").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_require_after_failed_load() {
+        let mut interp = Interpreter::default();
+        assert!(interp
+            .eval("(require \"tests/modules/util.flox\") (let x)")
+            .is_err());
+        interp
+            .eval("(require \"tests/modules/util.flox\")")
+            .unwrap();
+        assert_eq!(interp.eval_as::<i64>("(util/double 2)").unwrap(), 4);
+
+        assert!(interp
+            .eval("(require \"tests/modules/fragile.flox\")")
+            .is_err());
+        interp.eval("(set! ready true)").unwrap();
+        interp
+            .eval("(require \"tests/modules/fragile.flox\")")
+            .unwrap();
+        assert_eq!(interp.eval_as::<i64>("(fragile/answer)").unwrap(), 42);
+    }
+
+    #[test]
+    fn test_import_search_path() {
+        let mut interp = Interpreter::default();
+        assert!(interp.eval("(import util)").is_err());
+        interp.set_search_path(vec![PathBuf::from("tests/modules")]);
+        assert_eq!(
+            interp
+                .eval_as::<i64>("(import util) (util/double 4)")
+                .unwrap(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_module_errors() {
+        let mut interp = Interpreter::default();
+        let err = interp
+            .eval("(require \"tests/modules/cycle_a.flox\")")
+            .unwrap_err();
+        assert!(matches!(err, VMErr::CompileError(msg) if msg.contains("Import cycle")));
+
+        interp
+            .eval("(require \"tests/modules/util.flox\")")
+            .unwrap();
+        assert!(interp.eval("(util/helper 1)").is_err());
+        assert!(interp.eval("(defun f () (require \"x.flox\"))").is_err());
+        assert!(interp.eval("(provide f)").is_err());
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let mut interp = Interpreter::default();
+        let area = interp
+            .eval_file(Path::new("tests/modules/lib/geometry.flox"))
+            .unwrap();
+        assert_eq!(area, Value::Int(12));
+    }
+
+    #[test]
+    fn test_uncaught_exception() {
+        let mut interp = Interpreter::default();
+        let err = interp.eval("(try (throw 1) (finally 2)) 3").unwrap_err();
+        assert!(matches!(err, VMErr::Exception(Value::Int(1))));
+        assert!(interp.eval("(/ 1 0)").is_err());
+        assert_eq!(
+            interp
+                .eval_as::<i64>("(try (throw 2) (catch e e))")
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_long_jumps() {
+        let mut interp = Interpreter::default();
+        let source = "(set! x 1) ".repeat(100) + "(if (= x 1) (try (throw 7) (catch e e)) 0)";
+        assert_eq!(interp.eval_as::<i64>(&source).unwrap(), 7);
+    }
+}
@@ -77,6 +77,9 @@ fn opcode_to_string(opcode: OpCode) -> &'static str {
         OpCode::OpSub => "SUB",
         OpCode::OpMul => "MUL",
         OpCode::OpDiv => "DIV",
+        OpCode::OpQuot => "QUOT",
+        OpCode::OpRem => "REM",
+        OpCode::OpMod => "MOD",
         OpCode::OpNil => "NIL",
         OpCode::OpTrue => "TRUE",
         OpCode::OpFalse => "FALSE",
@@ -100,6 +103,31 @@ fn opcode_to_string(opcode: OpCode) -> &'static str {
         OpCode::OpSetUpvalue => "SETUP",
         OpCode::OpClosure => "CLOSURE",
         OpCode::OpPrint => "PRINT",
+        OpCode::OpPop => "POP",
+        OpCode::OpGetGlobal => "GETGLOBAL",
+        OpCode::OpSetGlobal => "SETGLOBAL",
+        OpCode::OpVector => "VECTOR",
+        OpCode::OpMap => "MAP",
+        OpCode::OpTry => "TRY",
+        OpCode::OpEndTry => "ENDTRY",
+        OpCode::OpThrow => "THROW",
+        OpCode::OpReset => "RESET",
+        OpCode::OpShift => "SHIFT",
+        OpCode::OpResume => "RESUME",
+        OpCode::OpYield => "YIELD",
+        OpCode::OpInstance => "INSTANCE",
+        OpCode::OpIsInstance => "ISINSTANCE",
+        OpCode::OpGetField => "GETFIELD",
+        OpCode::OpSetField => "SETFIELD",
+        OpCode::OpIsType => "ISTYPE",
+        OpCode::OpLength => "LENGTH",
+        OpCode::OpNth => "NTH",
+        OpCode::OpHasKey => "HASKEY",
+        OpCode::OpGetKey => "GETKEY",
+        OpCode::OpNoMatch => "NOMATCH",
+        OpCode::OpUnpack => "UNPACK",
+        OpCode::OpSeq => "SEQ",
+        OpCode::OpNext => "NEXT",
     }
 }
 
@@ -115,6 +143,9 @@ fn string_to_opcode(s: &str) -> OpCode {
         "SUB" => OpCode::OpSub,
         "MUL" => OpCode::OpMul,
         "DIV" => OpCode::OpDiv,
+        "QUOT" => OpCode::OpQuot,
+        "REM" => OpCode::OpRem,
+        "MOD" => OpCode::OpMod,
         "NIL" => OpCode::OpNil,
         "TRUE" => OpCode::OpTrue,
         "FALSE" => OpCode::OpFalse,
@@ -138,6 +169,31 @@ fn string_to_opcode(s: &str) -> OpCode {
         "SETUP" => OpCode::OpSetUpvalue,
         "CLOSURE" => OpCode::OpClosure,
         "PRINT" => OpCode::OpPrint,
+        "POP" => OpCode::OpPop,
+        "GETGLOBAL" => OpCode::OpGetGlobal,
+        "SETGLOBAL" => OpCode::OpSetGlobal,
+        "VECTOR" => OpCode::OpVector,
+        "MAP" => OpCode::OpMap,
+        "TRY" => OpCode::OpTry,
+        "ENDTRY" => OpCode::OpEndTry,
+        "THROW" => OpCode::OpThrow,
+        "RESET" => OpCode::OpReset,
+        "SHIFT" => OpCode::OpShift,
+        "RESUME" => OpCode::OpResume,
+        "YIELD" => OpCode::OpYield,
+        "INSTANCE" => OpCode::OpInstance,
+        "ISINSTANCE" => OpCode::OpIsInstance,
+        "GETFIELD" => OpCode::OpGetField,
+        "SETFIELD" => OpCode::OpSetField,
+        "ISTYPE" => OpCode::OpIsType,
+        "LENGTH" => OpCode::OpLength,
+        "NTH" => OpCode::OpNth,
+        "HASKEY" => OpCode::OpHasKey,
+        "GETKEY" => OpCode::OpGetKey,
+        "NOMATCH" => OpCode::OpNoMatch,
+        "UNPACK" => OpCode::OpUnpack,
+        "SEQ" => OpCode::OpSeq,
+        "NEXT" => OpCode::OpNext,
         _ => panic!(),
     }
 }
@@ -1,17 +1,31 @@
+// Values hash by their immutable contents only; the interior mutability of
+// captured closure cells never contributes to a hash.
+#![allow(clippy::mutable_key_type)]
+
 pub mod chunk;
 pub mod compiler;
+pub mod convert;
+pub mod interpreter;
 pub mod ir;
+pub mod module;
+pub mod printer;
+pub mod reader;
 pub mod scanner;
+pub mod serialize;
+pub mod typecheck;
 pub mod vm;
 
-pub fn rep(input: &str, debug: bool) -> Result<String, String> {
-    let mut chk = chunk::Chunk::new("test chunk");
-    let mut comp = compiler::Compiler::new(None);
-    compiler::compile(input, &mut chk, &mut comp)?;
+pub use chunk::Value;
+pub use convert::{FromValue, IntoValue};
+pub use interpreter::Interpreter;
+pub use reader::read;
+pub use serialize::{from_value, to_value};
 
-    let mut vm = vm::VirtualMachine::new(debug);
-    match vm.run(&chk) {
+pub fn rep(input: &str, debug: bool) -> Result<String, String> {
+    let mut interp = Interpreter::new(debug);
+    match interp.eval(input).and_then(|v| interp.realize(&v)) {
         Ok(v) => Ok(format!("{}", v)),
+        Err(vm::VMErr::CompileError(err)) => Err(err),
         Err(err) => Err(format!("{:?}", err)),
     }
 }
@@ -22,6 +36,52 @@ mod tests {
 
     #[test]
     fn test_empty() {
-        assert_eq!(rep("(+ 1 1)", false).unwrap(), "2.0".to_string());
+        assert_eq!(rep("(+ 1 1)", false).unwrap(), "2".to_string());
+    }
+
+    #[test]
+    fn test_format_errors() {
+        assert!(rep("(format \"{} {}\" 1)", false).is_err());
+        assert!(rep("(format \"{name}\" 1)", false).is_err());
+        assert!(rep("(format \"{:x}\" 1)", false).is_err());
+        assert!(rep("(format \"~q\" 1)", false).is_err());
+        assert!(rep("(format \"{\" 1)", false).is_err());
+    }
+
+    #[test]
+    fn test_integer_errors() {
+        assert!(rep("(quot 1 0)", false).is_err());
+        assert!(rep("(/ 1 0)", false).is_err());
+        assert!(rep("1/0", false).is_err());
+        assert!(rep("1.2.3", false).is_err());
+    }
+
+    #[test]
+    fn test_unbalanced_parens() {
+        for source in ["(", "(+ 1 2) (", "(+ 1", "(if true 1", "(list ("] {
+            assert_eq!(rep(source, false), Err("Missing )".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        assert_eq!(
+            rep("(+ 1 2) #| a #| b |#\n(+ 3 4)", false),
+            Err("Unterminated block comment starting at line 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_string_literal_errors() {
+        assert_eq!(
+            rep("(str \"abc)", false),
+            Err("Unterminated string starting at line 0".to_string())
+        );
+        assert_eq!(
+            rep("\"\\u{41\"", false),
+            Err("Malformed \\u escape, expected \\u{H...} with a code point at line 0, character 2 of the string literal".to_string())
+        );
+        assert!(rep("\"\\u41\"", false).is_err());
+        assert!(rep("\"\\u{110000}\"", false).is_err());
     }
 }
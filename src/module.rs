@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::scanner::{Scanner, Token};
+
+/// A compiled module. Its top-level globals live in the VM as `name/global`.
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub exports: Vec<String>,
+}
+
+/// The modules loaded by a compiler and the files being compiled right now.
+/// Shared by the compilers of every file, so a module is compiled only once no
+/// matter how many files require it.
+#[derive(Debug, Default)]
+pub struct Modules {
+    search_path: Vec<PathBuf>,
+    loaded: HashMap<PathBuf, Rc<Module>>,
+    loading: Vec<PathBuf>,
+    /// The modules compiled since the last `commit` or `rollback`, their code
+    /// has not run yet.
+    pending: Vec<PathBuf>,
+}
+
+impl Modules {
+    pub fn set_search_path(&mut self, search_path: Vec<PathBuf>) {
+        self.search_path = search_path;
+    }
+
+    /// Finds `path` relative to the file being compiled (or the working
+    /// directory outside of a file), then in each directory of the search path.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let base = self
+            .loading
+            .last()
+            .and_then(|file| file.parent())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        std::iter::once(&base)
+            .chain(self.search_path.iter())
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file())
+            .and_then(|found| found.canonicalize().ok())
+            .ok_or_else(|| {
+                let dirs = std::iter::once(&base)
+                    .chain(self.search_path.iter())
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<String>>();
+                format!("Cannot find module {} in {}", path, dirs.join(", "))
+            })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Rc<Module>> {
+        self.loaded.get(path).cloned()
+    }
+
+    /// Looks a loaded module up by the namespace its globals are prefixed with.
+    pub fn by_name(&self, name: &str) -> Option<Rc<Module>> {
+        self.loaded
+            .values()
+            .find(|module| module.name == name)
+            .cloned()
+    }
+
+    /// Marks `path` as being compiled. Fails when it already is, which means
+    /// the files require each other.
+    pub fn enter(&mut self, path: PathBuf) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|file| *file == path) {
+            let cycle = self.loading[start..]
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|file| file.display().to_string())
+                .collect::<Vec<String>>();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+        self.loading.push(path);
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.loading.pop();
+    }
+
+    pub fn insert(&mut self, module: Module) -> Result<(), String> {
+        if let Some(other) = self.by_name(&module.name) {
+            return Err(format!(
+                "Module {} at {} clashes with {}",
+                module.name,
+                module.path.display(),
+                other.path.display()
+            ));
+        }
+        self.pending.push(module.path.clone());
+        self.loaded.insert(module.path.clone(), Rc::new(module));
+        Ok(())
+    }
+
+    /// Keeps the pending modules, the code that loads them ran.
+    pub fn commit(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Forgets the pending modules, so that the next require loads them again.
+    pub fn rollback(&mut self) {
+        for path in self.pending.drain(..) {
+            self.loaded.remove(&path);
+        }
+    }
+}
+
+/// Returns the module name of a file, `lib/util.flox` is `util`.
+pub fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Collects the names a file defines at top level with `defun`, `set!`,
+/// `defgeneric` or `defstruct`, so that functions can refer to globals of their
+/// module defined further down.
+pub fn top_level_definitions(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::new(source);
+    let mut definitions = Vec::new();
+    let mut depth = 0;
+    let mut after_paren = false;
+    while let Some((token, _)) = scanner.scan() {
+        match &token {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+            Token::RightParen | Token::RightBracket | Token::RightBrace => depth -= 1,
+            Token::Atom(head)
+                if depth == 1
+                    && after_paren
+                    && (head == "defun" || head == "set!" || head == "defgeneric") =>
+            {
+                if let Some((Token::Atom(name), _)) = scanner.peek() {
+                    if !definitions.contains(&name) {
+                        definitions.push(name);
+                    }
+                }
+            }
+            Token::Atom(head) if depth == 1 && after_paren && head == "defstruct" => {
+                let mut ahead = scanner.clone();
+                if let Some((Token::Atom(name), _)) = ahead.scan() {
+                    let mut names = vec![format!("make-{}", name), format!("{}?", name)];
+                    while let Some((Token::Atom(field), _)) = ahead.scan() {
+                        names.push(format!("{}-{}", name, field));
+                        names.push(format!("set-{}-{}!", name, field));
+                    }
+                    for name in names {
+                        if !definitions.contains(&name) {
+                            definitions.push(name);
+                        }
+                    }
+                }
+            }
+            Token::Atom(_) | Token::Keyword(_) => {}
+        }
+        after_paren = token == Token::LeftParen;
+    }
+    definitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_level_definitions() {
+        let source = "(defun f (x) (set! y x)) (set! z 1) (do (defun g () 1)) (f 1)";
+        assert_eq!(top_level_definitions(source), vec!["f", "z"]);
+        assert_eq!(
+            top_level_definitions("(defstruct p x)"),
+            vec!["make-p", "p?", "p-x", "set-p-x!"]
+        );
+        assert_eq!(
+            top_level_definitions("(defgeneric area (s)) (defmethod area (s) 0)"),
+            vec!["area"]
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut modules = Modules::default();
+        assert!(modules.resolve("util.flox").is_err());
+        modules.set_search_path(vec![PathBuf::from("tests/modules")]);
+        let util = modules.resolve("util.flox").unwrap();
+        assert!(util.ends_with("tests/modules/util.flox"));
+
+        modules.enter(util.clone()).unwrap();
+        assert!(modules.resolve("lib/geometry.flox").is_ok());
+        assert!(modules.enter(util).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::{sorted_entries, Value};
+
+/// Escapes a string so that `compiler::unescape_str` turns it back into the
+/// same string. Control characters without a short escape print as `\u{7f}`.
+pub fn escape_str(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Prints a value the way it is written in source, so that data values can be
+/// read back with `reader::read`. Strings are quoted and escaped, and objects
+/// that have no source form print as `#<closure f>`.
+pub struct Readable<'a>(&'a Value);
+
+impl Value {
+    pub fn readable(&self) -> Readable<'_> {
+        Readable(self)
+    }
+}
+
+impl fmt::Display for Readable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self.0, &mut Vec::new())
+    }
+}
+
+/// `path` holds the objects being printed around `value`, an object that
+/// contains itself prints as `#<cycle>` instead of recursing forever.
+fn write_value(
+    f: &mut fmt::Formatter,
+    value: &Value,
+    path: &mut Vec<*const Object>,
+) -> fmt::Result {
+    match value {
+        Value::Obj(obj) => {
+            let ptr = &**obj as *const Object;
+            if path.contains(&ptr) {
+                return write!(f, "#<cycle>");
+            }
+            path.push(ptr);
+            let result = write_object(f, obj, path);
+            path.pop();
+            result
+        }
+        _ => write!(f, "{}", value),
+    }
+}
+
+fn write_seq<'a>(
+    f: &mut fmt::Formatter,
+    values: impl Iterator<Item = &'a Value>,
+    path: &mut Vec<*const Object>,
+) -> fmt::Result {
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write_value(f, value, path)?;
+    }
+    Ok(())
+}
+
+fn write_object(
+    f: &mut fmt::Formatter,
+    obj: &Object,
+    path: &mut Vec<*const Object>,
+) -> fmt::Result {
+    match obj {
+        Object::Str(s) => write!(f, "\"{}\"", escape_str(s)),
+        Object::List(list) => {
+            write!(f, "(")?;
+            write_seq(f, list.iter(), path)?;
+            write!(f, ")")
+        }
+        Object::Vector(vector) => {
+            write!(f, "[")?;
+            write_seq(f, vector.iter(), path)?;
+            write!(f, "]")
+        }
+        Object::Map(map) => {
+            write!(f, "{{")?;
+            write_seq(f, sorted_entries(map).flat_map(|(k, v)| [k, v]), path)?;
+            write!(f, "}}")
+        }
+        Object::BigInt(i) => write!(f, "{}", i),
+        Object::Ratio(r) => write!(f, "{}", r),
+        Object::Function(function) => write!(f, "#<function {}>", function.name),
+        Object::Closure(closure) => write!(f, "#<closure {}>", closure.function.name),
+        Object::Native(native) => write!(f, "#<native {}>", native.name),
+        Object::Continuation(_) => write!(f, "#<continuation>"),
+        Object::Coroutine(c) => write!(f, "#<coroutine {}>", c.borrow().status().name()),
+        Object::LazySeq(_) => write!(f, "#<lazy-seq>"),
+        Object::Keyword(k) => write!(f, ":{}", k),
+        Object::Generic(g) => write!(f, "#<generic {}>", g.name),
+        Object::NextMethod(n) => write!(f, "#<next-method {}>", n.name),
+        Object::Struct(t) => write!(f, "#<struct {}>", t.name),
+        Object::Instance(instance) => {
+            // copies of an instance share its slots, so those are what a
+            // cycle runs through
+            let slots = Rc::as_ptr(&instance.slots) as *const Object;
+            if path.contains(&slots) {
+                return write!(f, "#<cycle>");
+            }
+            path.push(slots);
+            write!(f, "#{}{{", instance.struct_type.name)?;
+            let fields = instance.struct_type.fields.iter();
+            for (i, (field, value)) in fields.zip(instance.slots.borrow().iter()).enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, ":{} ", field)?;
+                write_value(f, value, path)?;
+            }
+            path.pop();
+            write!(f, "}}")
+        }
+        Object::Error(error) => write!(f, "#<error {}: {}>", error.kind, error.message),
+    }
+}
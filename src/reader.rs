@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::object::{intern, Object};
+use crate::chunk::value::Value;
+use crate::compiler::{parse_char, parse_number, parse_string};
+use crate::scanner::{Scanner, Token};
+
+/// Reads a single data value (numbers, strings, chars, booleans, nil, lists,
+/// vectors and maps) from its source form, without evaluating anything. This
+/// is the inverse of printing a value with `Value::readable`.
+pub fn read(source: &str) -> Result<Value, String> {
+    let mut scanner = Scanner::new(source);
+    if let Some(err) = scanner.error() {
+        return Err(err.to_string());
+    }
+    let value = read_datum(&mut scanner)?;
+    match scanner.scan() {
+        Some((token, line)) => Err(format!(
+            "Unexpected {:?} after datum at line {}",
+            token, line
+        )),
+        None => Ok(value),
+    }
+}
+
+fn read_datum(scanner: &mut Scanner) -> Result<Value, String> {
+    let (token, line) = scanner
+        .scan()
+        .ok_or_else(|| "Unexpected end of input".to_string())?;
+    match token {
+        Token::LeftParen => Ok(Value::Obj(Box::new(Object::List(Rc::new(read_until(
+            scanner,
+            Token::RightParen,
+        )?))))),
+        Token::LeftBracket => Ok(Value::Obj(Box::new(Object::Vector(Rc::new(read_until(
+            scanner,
+            Token::RightBracket,
+        )?))))),
+        Token::LeftBrace => {
+            let elements = read_until(scanner, Token::RightBrace)?;
+            if elements.len() % 2 != 0 {
+                return Err(format!(
+                    "Map literal at line {} has an odd number of forms",
+                    line
+                ));
+            }
+            let mut map = HashMap::new();
+            for kv in elements.chunks(2) {
+                map.insert(kv[0].clone(), kv[1].clone());
+            }
+            Ok(Value::Obj(Box::new(Object::Map(Rc::new(map)))))
+        }
+        Token::Atom(atom) if is_string(&atom) => {
+            let s = parse_string(&atom, line)?;
+            Ok(Value::Obj(Box::new(Object::Str(s.into()))))
+        }
+        Token::Keyword(name) => Ok(Value::Obj(Box::new(Object::Keyword(intern(&name))))),
+        Token::Atom(atom) => {
+            read_atom(&atom).ok_or_else(|| format!("Cannot read {} at line {}", atom, line))
+        }
+        token => Err(format!("Unexpected {:?} at line {}", token, line)),
+    }
+}
+
+fn read_until(scanner: &mut Scanner, close: Token) -> Result<Vec<Value>, String> {
+    let mut elements = Vec::new();
+    loop {
+        match scanner.peek() {
+            Some((token, _)) if token == close => {
+                scanner.scan();
+                return Ok(elements);
+            }
+            Some(_) => elements.push(read_datum(scanner)?),
+            None => return Err(format!("Missing {:?}", close)),
+        }
+    }
+}
+
+fn is_string(atom: &str) -> bool {
+    let quoted = atom.strip_prefix('#').unwrap_or(atom);
+    quoted.len() >= 2 && quoted.starts_with('"') && quoted.ends_with('"')
+}
+
+fn read_atom(atom: &str) -> Option<Value> {
+    match atom {
+        "nil" => Some(Value::Nil),
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ if atom.starts_with("#\\") => parse_char(&atom[2..]).map(Value::Char),
+        _ => parse_number(atom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn string(s: &str) -> Value {
+        Value::Obj(Box::new(Object::Str(s.into())))
+    }
+
+    #[test]
+    fn test_read_atoms() {
+        assert_eq!(read("1"), Ok(Value::Int(1)));
+        assert_eq!(read("-2.5"), Ok(Value::Float(-2.5)));
+        assert_eq!(read("\"a\\\"b\""), Ok(string("a\"b")));
+        assert_eq!(read("#\\space"), Ok(Value::Char(' ')));
+        assert_eq!(read("#\"C:\\dir\""), Ok(string("C:\\dir")));
+        assert_eq!(read("nil"), Ok(Value::Nil));
+        assert_eq!(
+            read(":a"),
+            Ok(Value::Obj(Box::new(Object::Keyword(intern("a")))))
+        );
+    }
+
+    #[test]
+    fn test_read_collections() {
+        let value = read("{\"a\" [1 (2 3)]}").unwrap();
+        assert_eq!(format!("{}", value), "{a [1 (2 3)]}");
+    }
+
+    #[test]
+    fn test_read_printed_non_finite_floats() {
+        for x in [f64::INFINITY, f64::NEG_INFINITY] {
+            let value = Value::Float(x);
+            assert_eq!(read(&value.to_string()), Ok(value.clone()));
+            assert_eq!(read(&value.readable().to_string()), Ok(value));
+        }
+        let nan = read(&Value::Float(f64::NAN).to_string()).unwrap();
+        assert!(nan.get_number().is_some_and(f64::is_nan));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(read("x").is_err());
+        assert!(read("(1 2").is_err());
+        assert!(read("1 2").is_err());
+        assert!(read("{1}").is_err());
+        assert!(read("\"\\q\"").is_err());
+    }
+
+    fn random_string(rng: &mut StdRng) -> String {
+        const ALPHABET: &[char] = &[
+            'a', 'Z', ' ', '"', '\\', '\n', '(', ']', 'ç', '😀', '#', '\t', '\r', '\0', '\u{7}',
+            '\u{7f}',
+        ];
+        (0..rng.gen_range(0..8))
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())])
+            .collect()
+    }
+
+    fn random_value(rng: &mut StdRng, depth: usize) -> Value {
+        let kinds = if depth == 0 { 8 } else { 11 };
+        match rng.gen_range(0..kinds) {
+            0 => Value::Nil,
+            1 => Value::Bool(rng.gen()),
+            2 => Value::Int(rng.gen()),
+            3 => Value::Float(rng.gen::<f64>() * 10f64.powi(rng.gen_range(-20..20))),
+            4 => Value::Int(i64::MAX)
+                .checked_mul(&Value::Int(rng.gen_range(2..1000)))
+                .unwrap(),
+            5 => Value::Int(rng.gen_range(-100..100))
+                .checked_div(&Value::Int(rng.gen_range(1..100)))
+                .unwrap(),
+            6 => Value::Char(rng.gen()),
+            7 => string(&random_string(rng)),
+            kind => {
+                let elements = (0..rng.gen_range(0..4))
+                    .map(|_| random_value(rng, depth - 1))
+                    .collect::<Vec<Value>>();
+                Value::Obj(Box::new(match kind {
+                    8 => Object::List(Rc::new(elements)),
+                    9 => Object::Vector(Rc::new(elements)),
+                    _ => Object::Map(Rc::new(
+                        elements
+                            .into_iter()
+                            .map(|v| (string(&random_string(rng)), v))
+                            .collect(),
+                    )),
+                }))
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_print_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..2000 {
+            let value = random_value(&mut rng, 3);
+            let printed = value.readable().to_string();
+            assert_eq!(read(&printed), Ok(value), "printed as {}", printed);
+        }
+    }
+}
@@ -2,10 +2,31 @@
 pub enum Token {
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
     Atom(String),
+    /// `:name`, without the colon.
+    Keyword(String),
 }
 
 impl Token {
+    fn new(token: &str) -> Token {
+        match token {
+            "(" => Token::LeftParen,
+            ")" => Token::RightParen,
+            "[" => Token::LeftBracket,
+            "]" => Token::RightBracket,
+            "{" => Token::LeftBrace,
+            "}" => Token::RightBrace,
+            _ => match token.strip_prefix(':') {
+                Some(name) if !name.is_empty() => Token::Keyword(name.to_string()),
+                _ => Token::Atom(token.to_string()),
+            },
+        }
+    }
+
     pub fn atom(&self) -> Result<String, String> {
         match self {
             Token::Atom(s) => Ok(s.clone()),
@@ -16,20 +37,37 @@ impl Token {
 
 #[derive(Debug, Clone)]
 pub struct Scanner {
-    tokens: Vec<(String, usize)>,
+    /// The text of each token with its line and its column, both counted
+    /// from 0.
+    tokens: Vec<(String, usize, usize)>,
     pos: usize,
     current_line: usize,
+    current_column: usize,
+    error: Option<String>,
 }
 
 impl Scanner {
+    /// Splits `source` into tokens. A source that cannot be split, such as one
+    /// with an unterminated string or block comment, gives a scanner with no
+    /// tokens whose `error` says why.
     pub fn new(source: &str) -> Scanner {
+        let (tokens, error) = match tokenize(source) {
+            Ok(tokens) => (tokens, None),
+            Err(err) => (Vec::new(), Some(err)),
+        };
         Scanner {
-            tokens: tokenize(source),
+            tokens,
             pos: 0,
             current_line: 0,
+            current_column: 0,
+            error,
         }
     }
 
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
     pub fn scan(&mut self) -> Option<(Token, usize)> {
         let token = self.peek();
         self.pos += 1;
@@ -41,23 +79,14 @@ impl Scanner {
             return None;
         }
 
-        let (token, line) = &self.tokens[self.pos];
-        let token = match token as &str {
-            "(" => Token::LeftParen,
-            ")" => Token::RightParen,
-            _ => Token::Atom(token.to_string()),
-        };
+        let (token, line, column) = &self.tokens[self.pos];
         self.current_line = *line;
-        Some((token, *line))
+        self.current_column = *column;
+        Some((Token::new(token), *line))
     }
 
     pub fn previous(&self) -> Option<Token> {
-        let token: &str = &self.tokens[self.pos - 1].0;
-        match token {
-            "(" => Some(Token::LeftParen),
-            ")" => Some(Token::RightParen),
-            _ => Some(Token::Atom(token.to_string())),
-        }
+        Some(Token::new(&self.tokens[self.pos - 1].0))
     }
 
     pub fn next_tokens(&self) -> Vec<String> {
@@ -70,21 +99,241 @@ impl Scanner {
     pub fn get_line(&self) -> usize {
         self.current_line
     }
+
+    /// The column of the token last peeked or scanned, counted from 0.
+    pub fn get_column(&self) -> usize {
+        self.current_column
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(String, usize, usize)>, String> {
+    let mut tokens: Vec<(String, usize, usize)> = Vec::new();
+    let mut chars = Cursor::new(source);
+    let mut line = 0;
+
+    loop {
+        let column = chars.column();
+        let Some(c) = chars.next() else { break };
+        match c {
+            '\n' => line += 1,
+            c if is_delimiter(c) => tokens.push((c.to_string(), line, column)),
+            c if c.is_whitespace() => {}
+            ';' => {
+                // line comment, the newline itself is counted above
+                while chars.next_if(|c| c != '\n').is_some() {}
+            }
+            '#' if chars.peek() == Some('|') => {
+                chars.next();
+                skip_block_comment(&mut chars, &mut line)?;
+            }
+            '#' if chars.peek() == Some(';') => {
+                chars.next();
+                tokens.push((DATUM_COMMENT.to_string(), line, column));
+            }
+            '"' => {
+                // strings keep their quotes and escapes, they are unescaped by the compiler
+                let start = line;
+                let mut token = String::from('"');
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    token.push(c);
+                    match c {
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                line += (escaped == '\n') as usize;
+                                token.push(escaped);
+                            }
+                        }
+                        '\n' => line += 1,
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                if !closed {
+                    return Err(unterminated_string(start));
+                }
+                tokens.push((token, start, column));
+            }
+            _ => {
+                let mut token = String::from(c);
+                if c == '#' && chars.peek() == Some('"') {
+                    // raw strings have no escapes and end at the next quote
+                    let start = line;
+                    token.push(chars.next().unwrap());
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        line += (c == '\n') as usize;
+                        token.push(c);
+                        if c == '"' {
+                            closed = true;
+                            break;
+                        }
+                    }
+                    if !closed {
+                        return Err(unterminated_string(start));
+                    }
+                    tokens.push((token, start, column));
+                    continue;
+                }
+                if c == '#' && chars.peek() == Some('\\') {
+                    // the character after `#\` is part of the literal even if it is
+                    // a delimiter or whitespace, as in `#\(`
+                    token.push(chars.next().unwrap());
+                    if let Some(c) = chars.next() {
+                        line += (c == '\n') as usize;
+                        token.push(c);
+                    }
+                    if token == "#\\u" && chars.peek() == Some('{') {
+                        // code points like `#\u{1F600}` contain braces
+                        for c in chars.by_ref() {
+                            token.push(c);
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    }
+                }
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || is_delimiter(c) || c == '"' || c == ';' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push((token, line, column));
+            }
+        }
+    }
+
+    Ok(skip_datum_comments(tokens))
 }
 
-fn tokenize(source: &str) -> Vec<(String, usize)> {
-    let source = source.replace('(', " ( ");
-    let source = source.replace(')', " ) ");
-    let lines = source.split('\n');
-    let mut tokens: Vec<(String, usize)> = Vec::new();
+/// The characters of a source being tokenized, which keeps count of the column
+/// of the next one so that tokens can record where they start.
+struct Cursor<'a> {
+    rest: &'a str,
+    column: usize,
+}
 
-    for (i, line) in lines.enumerate() {
-        for tok in line.split_whitespace() {
-            tokens.push((tok.to_string(), i));
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Cursor<'a> {
+        Cursor {
+            rest: source,
+            column: 0,
         }
     }
 
-    tokens
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn next_if(&mut self, accept: impl FnOnce(char) -> bool) -> Option<char> {
+        let c = self.peek().filter(|&c| accept(c))?;
+        self.rest = &self.rest[c.len_utf8()..];
+        self.column = if c == '\n' { 0 } else { self.column + 1 };
+        Some(c)
+    }
+
+    fn next_if_eq(&mut self, expected: char) -> Option<char> {
+        self.next_if(|c| c == expected)
+    }
+
+    /// The column of the next character, counting characters from 0.
+    fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.next_if(|_| true)
+    }
+}
+
+fn unterminated_string(line: usize) -> String {
+    format!("Unterminated string starting at line {}", line)
+}
+
+/// Marks a `#;` datum comment until `skip_datum_comments` drops it together
+/// with the datum that follows.
+const DATUM_COMMENT: &str = "#;";
+
+/// Skips a `#| ... |#` comment whose opening `#|` was already consumed. Block
+/// comments nest, so `#| a #| b |# c |#` is a single comment. Reaching the end
+/// of the source inside one is an error.
+fn skip_block_comment(chars: &mut Cursor, line: &mut usize) -> Result<(), String> {
+    let start = *line;
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => *line += 1,
+            '#' if chars.next_if_eq('|').is_some() => depth += 1,
+            '|' if chars.next_if_eq('#').is_some() => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(format!(
+        "Unterminated block comment starting at line {}",
+        start
+    ))
+}
+
+fn skip_datum_comments(tokens: Vec<(String, usize, usize)>) -> Vec<(String, usize, usize)> {
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].0 == DATUM_COMMENT {
+            i = datum_end(&tokens, i + 1);
+        } else {
+            kept.push(tokens[i].clone());
+            i += 1;
+        }
+    }
+    kept
+}
+
+/// Returns the index after the datum starting at `start`. A datum comment in
+/// front of it skips one more datum, as in `#; #; a b`.
+fn datum_end(tokens: &[(String, usize, usize)], start: usize) -> usize {
+    match tokens.get(start).map(|(token, ..)| Token::new(token)) {
+        Some(Token::Atom(atom)) if atom == DATUM_COMMENT => {
+            datum_end(tokens, datum_end(tokens, start + 1))
+        }
+        Some(Token::LeftParen | Token::LeftBracket | Token::LeftBrace) => {
+            let mut depth = 0;
+            for (i, (token, ..)) in tokens.iter().enumerate().skip(start) {
+                match Token::new(token) {
+                    Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+                    Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            tokens.len()
+        }
+        // a `#;` right before a closing delimiter has nothing to comment out
+        Some(Token::RightParen | Token::RightBracket | Token::RightBrace) => start,
+        Some(Token::Atom(_) | Token::Keyword(_)) => start + 1,
+        None => start,
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    matches!(c, '(' | ')' | '[' | ']' | '{' | '}')
 }
 
 #[cfg(test)]
@@ -107,6 +356,16 @@ mod tests {
         assert_eq!(scan.scan().unwrap().0, Token::RightParen);
     }
 
+    #[test]
+    fn test_tokenize_keywords() {
+        let mut scan = Scanner::new("(:name m) :");
+        assert_eq!(scan.scan().unwrap().0, Token::LeftParen);
+        assert_eq!(scan.scan().unwrap().0, Token::Keyword("name".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("m".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::RightParen);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom(":".to_string()));
+    }
+
     #[test]
     fn test_tokenize_with_new_lines() {
         let mut scan = Scanner::new("(+ 1 2)\n(* 2 3)");
@@ -121,4 +380,102 @@ mod tests {
         assert_eq!(scan.scan().unwrap().0, Token::Atom("3".to_string()));
         assert_eq!(scan.scan().unwrap().0, Token::RightParen);
     }
+
+    #[test]
+    fn test_tokenize_brackets() {
+        let mut scan = Scanner::new("[1 {\"a\" 2}]");
+        assert_eq!(scan.scan().unwrap().0, Token::LeftBracket);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("1".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::LeftBrace);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("\"a\"".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("2".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::RightBrace);
+        assert_eq!(scan.scan().unwrap().0, Token::RightBracket);
+    }
+
+    #[test]
+    fn test_tokenize_string_with_spaces() {
+        let mut scan = Scanner::new("(print \"hello (world)\" \"a\\\"b\")");
+        assert_eq!(scan.scan().unwrap().0, Token::LeftParen);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("print".to_string()));
+        assert_eq!(
+            scan.scan().unwrap().0,
+            Token::Atom("\"hello (world)\"".to_string())
+        );
+        assert_eq!(
+            scan.scan().unwrap().0,
+            Token::Atom("\"a\\\"b\"".to_string())
+        );
+        assert_eq!(scan.scan().unwrap().0, Token::RightParen);
+    }
+
+    #[test]
+    fn test_tokenize_chars() {
+        let mut scan = Scanner::new("(f #\\( #\\  #\\newline #\\u{41})");
+        assert_eq!(scan.scan().unwrap().0, Token::LeftParen);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("f".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("#\\(".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("#\\ ".to_string()));
+        assert_eq!(
+            scan.scan().unwrap().0,
+            Token::Atom("#\\newline".to_string())
+        );
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("#\\u{41}".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_raw_strings() {
+        let mut scan = Scanner::new("(f #\"C:\\dir\\\" x)");
+        assert_eq!(scan.scan().unwrap().0, Token::LeftParen);
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("f".to_string()));
+        assert_eq!(
+            scan.scan().unwrap().0,
+            Token::Atom("#\"C:\\dir\\\"".to_string())
+        );
+        assert_eq!(scan.scan().unwrap().0, Token::Atom("x".to_string()));
+        assert_eq!(scan.scan().unwrap().0, Token::RightParen);
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        let scan =
+            Scanner::new("; header\n(f #| block #| nested |# |# 1;x\n #\\; #;(g 2) #;#;3 4 5 #;)");
+        assert_eq!(scan.next_tokens(), vec!["(", "f", "1", "#\\;", "5", ")"]);
+        assert_eq!(scan.tokens[2].1, 1);
+    }
+
+    #[test]
+    fn test_columns() {
+        let mut scan = Scanner::new("(f \"é\" x)\n  #| c |# y");
+        let mut columns = Vec::new();
+        while scan.scan().is_some() {
+            columns.push((scan.get_line(), scan.get_column()));
+        }
+        assert_eq!(
+            columns,
+            vec![(0, 0), (0, 1), (0, 3), (0, 7), (0, 8), (1, 10)]
+        );
+    }
+
+    #[test]
+    fn test_unterminated() {
+        for (source, error) in [
+            (
+                "(f 1) #| a #| b |# c",
+                "Unterminated block comment starting at line 0",
+            ),
+            (
+                "#| a\n|# (f) #|\n",
+                "Unterminated block comment starting at line 1",
+            ),
+            ("(f\n \"abc)", "Unterminated string starting at line 1"),
+            ("(f \"a\\\")", "Unterminated string starting at line 0"),
+            ("\n\n#\"raw", "Unterminated string starting at line 2"),
+        ] {
+            let mut scan = Scanner::new(source);
+            assert_eq!(scan.error(), Some(error));
+            assert_eq!(scan.scan(), None);
+        }
+        assert_eq!(Scanner::new("\"a\" #| |#").error(), None);
+    }
 }
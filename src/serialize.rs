@@ -0,0 +1,741 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::de::value::{Error, MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{
+    self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::chunk::object::Object;
+use crate::chunk::value::{sorted_entries, Value};
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Char(c) => serializer.serialize_char(*c),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Nil => serializer.serialize_unit(),
+            Value::Obj(obj) => match &**obj {
+                Object::BigInt(i) => match (i.to_i128(), i.to_u128()) {
+                    (Some(i), _) => serializer.serialize_i128(i),
+                    (_, Some(u)) => serializer.serialize_u128(u),
+                    _ => Err(ser::Error::custom(format!(
+                        "Cannot serialize {} exactly",
+                        self
+                    ))),
+                },
+                // JSON has no rationals, they are written as the nearest float
+                Object::Ratio(_) => serializer.serialize_f64(self.get_number().unwrap()),
+                Object::Str(s) | Object::Keyword(s) => serializer.serialize_str(s),
+                Object::List(list) | Object::Vector(list) => {
+                    let mut seq = serializer.serialize_seq(Some(list.len()))?;
+                    for value in list.iter() {
+                        seq.serialize_element(value)?;
+                    }
+                    seq.end()
+                }
+                Object::Map(map) => {
+                    let mut entries = serializer.serialize_map(Some(map.len()))?;
+                    for (key, value) in sorted_entries(map) {
+                        entries.serialize_entry(key, value)?;
+                    }
+                    entries.end()
+                }
+                // walking a lazy sequence can call flox code, which needs the VM
+                Object::LazySeq(_) => Err(ser::Error::custom(
+                    "Cannot serialize a lazy sequence, realize it with Interpreter::realize first",
+                )),
+                Object::Function(_)
+                | Object::Closure(_)
+                | Object::Native(_)
+                | Object::Error(_)
+                | Object::Continuation(_)
+                | Object::Coroutine(_)
+                | Object::Struct(_)
+                | Object::Instance(_)
+                | Object::Generic(_)
+                | Object::NextMethod(_) => {
+                    Err(ser::Error::custom(format!("Cannot serialize {}", self)))
+                }
+            },
+        }
+    }
+}
+
+/// The key of the single entry map that serde_json's `arbitrary_precision`
+/// passes numbers as, so that integers outside of `u64` reach us exactly.
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Reads a JSON number literal, integers of any size stay exact.
+fn parse_number<E: de::Error>(text: &str) -> Result<Value, E> {
+    if !text.contains(['.', 'e', 'E']) {
+        if let Ok(i) = text.parse::<BigInt>() {
+            return Ok(Value::from_bigint(i));
+        }
+    }
+    match text.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok(Value::Float(f)),
+        _ => Err(E::custom(format!("number out of range: {}", text))),
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, bool, nil, string, list or map")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<Value, E> {
+        Ok(Value::Char(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Obj(Box::new(Object::Str(v.into()))))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Obj(Box::new(Object::Str(v.into()))))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut vector = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            vector.push(value);
+        }
+        Ok(Value::Obj(Box::new(Object::Vector(Rc::new(vector)))))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut entries: A) -> Result<Value, A::Error> {
+        let mut map = HashMap::new();
+        while let Some(key) = entries.next_key::<Value>()? {
+            if map.is_empty() && key.get_str() == Some(NUMBER_TOKEN) {
+                return parse_number(&entries.next_value::<String>()?);
+            }
+            map.insert(key, entries.next_value()?);
+        }
+        Ok(Value::Obj(Box::new(Object::Map(Rc::new(map)))))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Builds flox values straight from serializable host values, so that the
+/// conversion keeps what JSON cannot hold: chars, 128 bit integers and map keys
+/// that are not strings. Sequences become vectors, structs become maps with
+/// string keys and enum variants are named by strings, as in JSON.
+struct ValueSerializer;
+
+fn string(s: &str) -> Value {
+    Value::Obj(Box::new(Object::Str(s.into())))
+}
+
+fn vector(items: Vec<Value>) -> Value {
+    Value::Obj(Box::new(Object::Vector(Rc::new(items))))
+}
+
+fn map(entries: HashMap<Value, Value>) -> Value {
+    Value::Obj(Box::new(Object::Map(Rc::new(entries))))
+}
+
+/// The externally tagged form of an enum variant with data, `{variant data}`.
+fn variant(name: &str, value: Value) -> Value {
+    map(HashMap::from([(string(name), value)]))
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        Ok(Value::from_bigint(BigInt::from(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(string(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(vector(v.iter().map(|b| Value::Int((*b).into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(string(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(variant(name, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            variant: None,
+            entries: HashMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            entries: HashMap::new(),
+            key: None,
+        })
+    }
+}
+
+/// Collects the items of a sequence, tuple or tuple variant into a vector.
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let items = vector(self.items);
+        Ok(match self.variant {
+            Some(name) => variant(name, items),
+            None => items,
+        })
+    }
+}
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Collects the entries of a map, struct or struct variant into a map.
+struct MapSerializer {
+    variant: Option<&'static str>,
+    entries: HashMap<Value, Value>,
+    key: Option<Value>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.entries
+            .insert(string(key), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let entries = map(self.entries);
+        Ok(match self.variant {
+            Some(name) => variant(name, entries),
+            None => entries,
+        })
+    }
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("A map value was serialized before its key"))?;
+        self.entries.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Reads host values straight from flox values, the reverse of
+/// `ValueSerializer`. Keywords read as strings and rationals as floats.
+impl<'de> Deserializer<'de> for &'de Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Int(i) => visitor.visit_i64(*i),
+            Value::Float(f) => visitor.visit_f64(*f),
+            Value::Char(c) => visitor.visit_char(*c),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Nil => visitor.visit_unit(),
+            Value::Obj(obj) => match &**obj {
+                Object::BigInt(i) => match (i.to_i128(), i.to_u128()) {
+                    (Some(i), _) => visitor.visit_i128(i),
+                    (_, Some(u)) => visitor.visit_u128(u),
+                    _ => Err(de::Error::custom(format!(
+                        "Cannot deserialize {} exactly",
+                        self
+                    ))),
+                },
+                Object::Ratio(_) => visitor.visit_f64(self.get_number().unwrap()),
+                Object::Str(s) | Object::Keyword(s) => visitor.visit_borrowed_str(s),
+                Object::List(list) | Object::Vector(list) => {
+                    let mut items = SeqDeserializer::new(list.iter());
+                    let value = visitor.visit_seq(&mut items)?;
+                    items.end()?;
+                    Ok(value)
+                }
+                Object::Map(map) => {
+                    let mut entries = MapDeserializer::new(map.iter());
+                    let value = visitor.visit_map(&mut entries)?;
+                    entries.end()?;
+                    Ok(value)
+                }
+                _ => Err(de::Error::custom(format!("Cannot deserialize {}", self))),
+            },
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A variant is named by a string or keyword, or by the only key of a map
+    /// whose value is its data.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if let Some(name) = self.get_str().or_else(|| self.get_keyword().map(|k| &**k)) {
+            return visitor.visit_enum(name.into_deserializer());
+        }
+        match self.get_map() {
+            Some(map) if map.len() == 1 => {
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(map.iter())))
+            }
+            _ => Err(de::Error::custom(format!(
+                "Expected a variant name or a map with one entry, got {}",
+                self
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Converts any serializable host value into a flox value, keeping chars,
+/// 128 bit integers and maps with keys that are not strings.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, String> {
+    value
+        .serialize(ValueSerializer)
+        .map_err(|err| err.to_string())
+}
+
+/// Converts a flox value into any deserializable host type.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, String> {
+    T::deserialize(value).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        retries: u32,
+        ratio: f64,
+        tags: Vec<String>,
+        parent: Option<String>,
+    }
+
+    fn fixture_config() -> Config {
+        Config {
+            name: "billing".to_string(),
+            retries: 3,
+            ratio: 0.5,
+            tags: vec!["a".to_string(), "b".to_string()],
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let value = to_value(&fixture_config()).unwrap();
+        let retries = Value::from("retries");
+        assert_eq!(value.get_map().unwrap()[&retries].get_int(), Some(3));
+        assert_eq!(from_value::<Config>(&value).unwrap(), fixture_config());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let value: Value = serde_json::from_str(r#"{"a": [1, 2.5, true, null, "x"]}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":[1,2.5,true,null,"x"]}"#
+        );
+    }
+
+    #[test]
+    fn test_bigint_round_trip() {
+        let value = Value::from_bigint(BigInt::from(u64::MAX));
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, "18446744073709551615");
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: i64, h: i64 },
+    }
+
+    #[test]
+    fn test_exact_round_trip() {
+        let initial = 'é';
+        assert_eq!(to_value(&initial).unwrap(), Value::Char('é'));
+        assert_eq!(from_value::<char>(&Value::Char('é')).unwrap(), initial);
+
+        let big = u128::MAX;
+        let value = to_value(&big).unwrap();
+        assert_eq!(value.to_string(), big.to_string());
+        assert_eq!(from_value::<u128>(&value).unwrap(), big);
+
+        let keyed = HashMap::from([(1_i64, 'a'), (-2, 'b')]);
+        let value = to_value(&keyed).unwrap();
+        assert_eq!(value.get_map().unwrap()[&Value::Int(-2)], Value::Char('b'));
+        assert_eq!(from_value::<HashMap<i64, char>>(&value).unwrap(), keyed);
+    }
+
+    #[test]
+    fn test_enum_round_trip() {
+        for shape in [Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }] {
+            let value = to_value(&shape).unwrap();
+            assert_eq!(from_value::<Shape>(&value).unwrap(), shape);
+        }
+        let mut interp = crate::Interpreter::default();
+        let value = interp.eval("{:Rect {\"w\" 2 \"h\" 3}}").unwrap();
+        assert_eq!(
+            from_value::<Shape>(&value).unwrap(),
+            Shape::Rect { w: 2, h: 3 }
+        );
+    }
+
+    #[test]
+    fn test_from_value_mismatch() {
+        let err = from_value::<HashMap<String, i64>>(&to_value(&HashMap::from([(1, 2)])).unwrap());
+        assert!(err.is_err());
+        let huge = Value::from_bigint(BigInt::from(u128::MAX) * 2);
+        assert!(from_value::<u128>(&huge).is_err());
+    }
+
+    #[test]
+    fn test_serialize_closure_fails() {
+        let mut interp = crate::Interpreter::default();
+        let closure = interp.eval("(lambda (x) x)").unwrap();
+        assert!(serde_json::to_string(&closure).is_err());
+    }
+
+    #[test]
+    fn test_serialize_lazy_seq() {
+        let mut interp = crate::Interpreter::default();
+        let seq = interp.eval("(take 3 (range))").unwrap();
+        let err = serde_json::to_string(&seq).unwrap_err();
+        assert!(err.to_string().contains("realize it"));
+        let realized = interp.realize(&seq).unwrap();
+        assert_eq!(serde_json::to_string(&realized).unwrap(), "[0,1,2]");
+    }
+}
@@ -0,0 +1,1007 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use itertools::Itertools;
+
+use crate::chunk::generic::TYPES;
+use crate::compiler::is_number_literal;
+use crate::scanner::{Scanner, Token};
+
+/// The static type of an expression. Unannotated code is `Any`, which is
+/// consistent with every type, so only definite mismatches are reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    /// One of the built-in types methods specialize on, such as `number`.
+    Builtin(&'static str),
+    Struct(String),
+}
+
+impl Type {
+    fn is_subtype(&self, other: &Type) -> bool {
+        self == other
+            || *other == Type::Any
+            || (*other == Type::Builtin("number")
+                && matches!(self, Type::Builtin("integer" | "ratio" | "float")))
+    }
+
+    /// Whether a value of one type can be a value of the other.
+    fn is_consistent(&self, other: &Type) -> bool {
+        self.is_subtype(other) || other.is_subtype(self)
+    }
+
+    fn join(&self, other: &Type) -> Type {
+        if self.is_subtype(other) && other.is_subtype(self) {
+            self.clone()
+        } else if self.is_number() && other.is_number() {
+            Type::Builtin("number")
+        } else {
+            Type::Any
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        self.is_subtype(&Type::Builtin("number")) && *self != Type::Any
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Builtin(name) => write!(f, "{}", name),
+            Type::Struct(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Where a form starts, the line and column are counted from 0 like the
+/// scanner and the other compile errors do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    line: usize,
+    column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A form of the source with the position it starts at.
+#[derive(Debug, Clone, PartialEq)]
+enum Form {
+    Atom(String, Span),
+    Keyword(String, Span),
+    List(Vec<Form>, Span),
+    Vector(Vec<Form>, Span),
+    Map(Vec<Form>, Span),
+}
+
+impl Form {
+    fn span(&self) -> Span {
+        match self {
+            Form::Atom(_, span)
+            | Form::Keyword(_, span)
+            | Form::List(_, span)
+            | Form::Vector(_, span)
+            | Form::Map(_, span) => *span,
+        }
+    }
+
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Form::Atom(atom, _) => Some(atom),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Form {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Form::Atom(atom, _) => write!(f, "{}", atom),
+            Form::Keyword(name, _) => write!(f, ":{}", name),
+            Form::List(items, _) => write!(f, "({})", items.iter().join(" ")),
+            Form::Vector(items, _) => write!(f, "[{}]", items.iter().join(" ")),
+            Form::Map(items, _) => write!(f, "{{{}}}", items.iter().join(" ")),
+        }
+    }
+}
+
+/// Reads the forms of the tokens the compiler is about to compile. Malformed
+/// sources give `None` and are left for the compiler to report.
+fn read_forms(scanner: &Scanner) -> Option<Vec<Form>> {
+    let mut scanner = scanner.clone();
+    let mut forms = Vec::new();
+    while scanner.peek().is_some() {
+        forms.push(read_form(&mut scanner)?);
+    }
+    Some(forms)
+}
+
+fn read_form(scanner: &mut Scanner) -> Option<Form> {
+    let (token, line) = scanner.scan()?;
+    let span = Span {
+        line,
+        column: scanner.get_column(),
+    };
+    Some(match token {
+        Token::Atom(atom) => Form::Atom(atom, span),
+        Token::Keyword(name) => Form::Keyword(name, span),
+        Token::LeftParen => Form::List(read_items(scanner, Token::RightParen)?, span),
+        Token::LeftBracket => Form::Vector(read_items(scanner, Token::RightBracket)?, span),
+        Token::LeftBrace => Form::Map(read_items(scanner, Token::RightBrace)?, span),
+        Token::RightParen | Token::RightBracket | Token::RightBrace => return None,
+    })
+}
+
+fn read_items(scanner: &mut Scanner, close: Token) -> Option<Vec<Form>> {
+    let mut items = Vec::new();
+    while scanner.peek()?.0 != close {
+        items.push(read_form(scanner)?);
+    }
+    scanner.scan();
+    Some(items)
+}
+
+/// The annotated types of a `defun`, or of a `lambda` bound with `let`.
+#[derive(Clone)]
+struct Signature {
+    params: Vec<Type>,
+    /// Whether the function takes more arguments than `params`, with `& rest`
+    /// or `&key`.
+    variadic: bool,
+    ret: Type,
+}
+
+/// A lambda list: `x`, `(x : number)` or a pattern, then optionally `& rest`
+/// or `&key` followed by `port`, `(port 8080)` or `(port : integer 8080)`.
+/// `bound` has the names the patterns and `& rest` bind.
+struct Params<'a> {
+    positional: Vec<(String, Type)>,
+    rest: bool,
+    keys: Vec<(String, Type, Option<&'a Form>)>,
+    bound: HashMap<String, Type>,
+    annotated: bool,
+}
+
+/// What the checks of earlier sources tell about the next ones: the structs,
+/// the signatures of top-level functions and the types of globals. The REPL
+/// and embedders compile one form at a time, so a call is often checked in a
+/// later source than the function it calls.
+#[derive(Default, Clone)]
+pub struct Types {
+    structs: Vec<String>,
+    signatures: HashMap<String, Signature>,
+    globals: HashMap<String, Type>,
+}
+
+#[derive(Default)]
+struct Checker {
+    structs: Vec<String>,
+    /// How many times each name is defined anywhere in the source, only names
+    /// defined once have the type of their definition.
+    definitions: HashMap<String, usize>,
+    signatures: HashMap<String, Signature>,
+    /// The signatures of nested `defun`s and `let`-bound lambdas, with the
+    /// index of the scope that binds them.
+    local_signatures: Vec<(usize, String, Signature)>,
+    globals: HashMap<String, Type>,
+    scopes: Vec<HashMap<String, Type>>,
+    /// Whether the code being checked is in an annotated function, outside
+    /// of which operators are not checked so unannotated code runs as before.
+    strict: bool,
+    errors: Vec<String>,
+}
+
+/// Checks the type annotations of a source, as in
+/// `(defun add ((x : number) (y : number)) : number (+ x y))`: calls and
+/// returns of annotated functions, including nested `defun`s and lambdas bound
+/// with `let`, and the operations in their bodies, whose locals have the types
+/// of their annotations or of the literals and calls they are set to. Runs on
+/// the scanner of the source before it is compiled, so the source is only
+/// tokenized once. `types` is what earlier sources defined, the result adds
+/// the definitions of this one. Returns the mismatches found, one per line.
+pub fn check(scanner: &Scanner, types: &Types) -> Result<Types, String> {
+    let Some(forms) = read_forms(scanner) else {
+        return Ok(types.clone());
+    };
+    let mut checker = Checker {
+        structs: types.structs.clone(),
+        signatures: types.signatures.clone(),
+        globals: types.globals.clone(),
+        ..Checker::default()
+    };
+    checker.declare(&forms);
+    for form in &forms {
+        checker.infer(form);
+    }
+    if checker.errors.is_empty() {
+        Ok(Types {
+            structs: checker.structs,
+            signatures: checker.signatures,
+            globals: checker.globals,
+        })
+    } else {
+        Err(checker.errors.join("\n"))
+    }
+}
+
+impl Checker {
+    /// Collects the structs, the number of definitions of each name and the
+    /// signatures of top-level functions, so calls can be checked before the
+    /// function they call. Names defined again lose the types earlier
+    /// sources gave them.
+    fn declare(&mut self, forms: &[Form]) {
+        for form in forms {
+            self.count_definitions(form);
+            if let Some(("defstruct", [name, ..])) = head(form) {
+                if let Some(name) = name.atom() {
+                    if !self.structs.iter().any(|s| s == name) {
+                        self.structs.push(name.to_string());
+                    }
+                }
+            }
+        }
+        for name in self.definitions.keys() {
+            self.signatures.remove(name);
+            self.globals.remove(name);
+        }
+        for form in forms {
+            if let Some(("defun", [name, params, rest @ ..])) = head(form) {
+                let Some(name) = name.atom() else { continue };
+                if self.definitions.get(name) != Some(&1) {
+                    continue;
+                }
+                let signature = self.signature(params, rest);
+                self.signatures.insert(name.to_string(), signature);
+            }
+        }
+    }
+
+    /// The signature of a function from its lambda list and the forms after
+    /// it, without reporting unknown types, which `function` reports.
+    fn signature(&mut self, params: &Form, rest: &[Form]) -> Signature {
+        let params = self.params(params, false);
+        Signature {
+            params: params.positional.into_iter().map(|(_, t)| t).collect(),
+            variadic: params.rest || !params.keys.is_empty(),
+            ret: self.return_type(rest, false).0,
+        }
+    }
+
+    /// Binds `signature` to `name` in the innermost scope.
+    fn declare_local(&mut self, name: &str, signature: Signature) {
+        let depth = self.scopes.len() - 1;
+        self.local_signatures
+            .retain(|(d, n, _)| *d != depth || n != name);
+        self.local_signatures
+            .push((depth, name.to_string(), signature));
+    }
+
+    /// Forgets the signature of the local `name` when it is set to another
+    /// value, so that calls to it are no longer checked.
+    fn forget_local(&mut self, name: &str) {
+        if let Some(depth) = self.scopes.iter().rposition(|s| s.contains_key(name)) {
+            self.local_signatures
+                .retain(|(d, n, _)| *d != depth || n != name);
+        }
+    }
+
+    /// The signature of the local or global function `name`.
+    fn signature_of(&self, name: &str) -> Option<Signature> {
+        match self.scopes.iter().rposition(|s| s.contains_key(name)) {
+            Some(depth) => self
+                .local_signatures
+                .iter()
+                .find(|(d, n, _)| *d == depth && n == name)
+                .map(|(_, _, signature)| signature.clone()),
+            None => self.signatures.get(name).cloned(),
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+        let depth = self.scopes.len();
+        self.local_signatures.retain(|(d, _, _)| *d < depth);
+    }
+
+    fn count_definitions(&mut self, form: &Form) {
+        if let Some(("set!" | "defun" | "defgeneric", [name, ..])) = head(form) {
+            if let Some(name) = name.atom() {
+                *self.definitions.entry(name.to_string()).or_insert(0) += 1;
+            }
+        }
+        if let Form::List(items, _) | Form::Vector(items, _) | Form::Map(items, _) = form {
+            for item in items {
+                self.count_definitions(item);
+            }
+        }
+    }
+
+    fn annotation(&mut self, name: &Form, report: bool) -> Type {
+        let Some(text) = name.atom() else {
+            return Type::Any;
+        };
+        if text == "any" {
+            Type::Any
+        } else if let Some(builtin) = TYPES.iter().find(|t| **t == text) {
+            Type::Builtin(builtin)
+        } else if self.structs.iter().any(|s| s == text) {
+            Type::Struct(text.to_string())
+        } else {
+            if report {
+                self.errors
+                    .push(format!("Unknown type {} at {}", text, name.span()));
+            }
+            Type::Any
+        }
+    }
+
+    fn params<'a>(&mut self, list: &'a Form, report: bool) -> Params<'a> {
+        let mut params = Params {
+            positional: Vec::new(),
+            rest: false,
+            keys: Vec::new(),
+            bound: HashMap::new(),
+            annotated: false,
+        };
+        let Form::List(items, _) = list else {
+            return params;
+        };
+        let mut in_keys = false;
+        let mut items = items.iter();
+        while let Some(item) = items.next() {
+            match item {
+                Form::Atom(atom, _) if atom == "&" => {
+                    params.rest = true;
+                    if let Some(name) = items.next().and_then(Form::atom) {
+                        params.bound.insert(name.to_string(), Type::Any);
+                    }
+                }
+                Form::Atom(atom, _) if atom == "&key" => in_keys = true,
+                Form::Atom(atom, _) if in_keys => params.keys.push((atom.clone(), Type::Any, None)),
+                Form::Atom(atom, _) => params.positional.push((atom.clone(), Type::Any)),
+                Form::List(parts, _)
+                    if in_keys || parts.get(1).and_then(Form::atom) == Some(":") =>
+                {
+                    let Some(name) = parts.first().and_then(Form::atom) else {
+                        continue;
+                    };
+                    let (param_type, rest) = match &parts[1..] {
+                        [colon, annotation, rest @ ..] if colon.atom() == Some(":") => {
+                            params.annotated = true;
+                            (self.annotation(annotation, report), rest)
+                        }
+                        rest => (Type::Any, rest),
+                    };
+                    if in_keys {
+                        params
+                            .keys
+                            .push((name.to_string(), param_type, rest.first()));
+                    } else {
+                        params.positional.push((name.to_string(), param_type));
+                    }
+                }
+                Form::List(..) | Form::Vector(..) | Form::Map(..) if !in_keys => {
+                    params.positional.push((String::new(), Type::Any));
+                    binding_names(item, &mut params.bound);
+                }
+                _ => {}
+            }
+        }
+        params
+    }
+
+    /// The declared return type of a function and the forms of its body.
+    fn return_type<'a>(&mut self, rest: &'a [Form], report: bool) -> (Type, &'a [Form]) {
+        match rest {
+            [colon, annotation, body @ ..] if colon.atom() == Some(":") => {
+                (self.annotation(annotation, report), body)
+            }
+            body => (Type::Any, body),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// Reports `form` when its type is not consistent with `expected`.
+    fn expect(&mut self, form: &Form, expected: &Type, what: &str) -> Type {
+        let actual = self.infer(form);
+        if !actual.is_consistent(expected) {
+            self.mismatch(form, &format!("{} expects {}", what, expected), &actual);
+        }
+        actual
+    }
+
+    /// Checks an operand of a built-in operation, in annotated code only.
+    fn expect_operand(&mut self, form: &Form, expected: &Type, what: &str) -> Type {
+        if self.strict {
+            self.expect(form, expected, what)
+        } else {
+            self.infer(form)
+        }
+    }
+
+    fn mismatch(&mut self, form: &Form, expectation: &str, actual: &Type) {
+        self.errors.push(format!(
+            "Type error at {}: {}, got {} {}",
+            form.span(),
+            expectation,
+            actual,
+            form
+        ));
+    }
+
+    fn infer(&mut self, form: &Form) -> Type {
+        match form {
+            Form::Atom(atom, _) => self.infer_atom(atom),
+            Form::Keyword(..) => Type::Builtin("keyword"),
+            Form::Vector(items, _) => {
+                self.walk(items);
+                Type::Builtin("vector")
+            }
+            Form::Map(items, _) => {
+                self.walk(items);
+                Type::Builtin("map")
+            }
+            Form::List(items, _) => match items.split_first() {
+                Some((Form::Atom(op, _), args)) => self.infer_list(form, op, args),
+                Some(_) => {
+                    self.walk(items);
+                    Type::Any
+                }
+                None => Type::Any,
+            },
+        }
+    }
+
+    fn infer_atom(&self, atom: &str) -> Type {
+        match atom {
+            "nil" => Type::Builtin("nil"),
+            "true" | "false" => Type::Builtin("bool"),
+            _ if atom.starts_with('"') || atom.starts_with("#\"") => Type::Builtin("string"),
+            _ if atom.starts_with("#\\") => Type::Builtin("char"),
+            _ if is_number_literal(atom) => {
+                let digits = atom.trim_start_matches(['+', '-']);
+                if digits.starts_with("0x") || digits.starts_with("0b") {
+                    Type::Builtin("integer")
+                } else if digits.contains('/') {
+                    Type::Builtin("ratio")
+                } else if digits.starts_with("##") || digits.contains(['.', 'e', 'E']) {
+                    Type::Builtin("float")
+                } else {
+                    Type::Builtin("integer")
+                }
+            }
+            _ => self
+                .lookup(atom)
+                .or_else(|| self.globals.get(atom).cloned())
+                .unwrap_or(Type::Any),
+        }
+    }
+
+    fn walk(&mut self, forms: &[Form]) {
+        for form in forms {
+            self.infer(form);
+        }
+    }
+
+    fn infer_list(&mut self, form: &Form, op: &str, args: &[Form]) -> Type {
+        match op {
+            "+" | "-" | "*" | "/" | "quot" | "rem" | "mod" => {
+                let number = Type::Builtin("number");
+                let types: Vec<Type> = args
+                    .iter()
+                    .map(|arg| self.expect_operand(arg, &number, op))
+                    .collect();
+                if types.contains(&Type::Builtin("float")) {
+                    Type::Builtin("float")
+                } else if op != "/" && types.iter().all(|t| *t == Type::Builtin("integer")) {
+                    Type::Builtin("integer")
+                } else {
+                    number
+                }
+            }
+            "<" | "<=" | ">" | ">=" => {
+                let mut family: Option<Type> = None;
+                for arg in args {
+                    let actual = self.infer(arg);
+                    let comparable = match &actual {
+                        _ if !self.strict => continue,
+                        Type::Any => continue,
+                        t if t.is_number() => Type::Builtin("number"),
+                        Type::Builtin(name @ ("string" | "char" | "bool")) => Type::Builtin(name),
+                        _ => {
+                            let expectation =
+                                format!("{} expects number, string, char or bool", op);
+                            self.mismatch(arg, &expectation, &actual);
+                            continue;
+                        }
+                    };
+                    match &family {
+                        Some(expected) if *expected != comparable => {
+                            self.mismatch(arg, &format!("{} expects {}", op, expected), &actual)
+                        }
+                        Some(_) => {}
+                        None => family = Some(comparable),
+                    }
+                }
+                Type::Builtin("bool")
+            }
+            "=" | "!=" => {
+                self.walk(args);
+                Type::Builtin("bool")
+            }
+            "and" | "nand" | "or" | "nor" | "xor" | "xnor" | "not" => {
+                for arg in args {
+                    self.expect_operand(arg, &Type::Builtin("bool"), op);
+                }
+                Type::Builtin("bool")
+            }
+            "if" => match args {
+                [condition, then, rest @ ..] => {
+                    self.expect_operand(condition, &Type::Builtin("bool"), "if");
+                    let then = self.infer(then);
+                    match rest.first() {
+                        Some(otherwise) => {
+                            let otherwise = self.infer(otherwise);
+                            then.join(&otherwise)
+                        }
+                        None => Type::Any,
+                    }
+                }
+                _ => {
+                    self.walk(args);
+                    Type::Any
+                }
+            },
+            "do" => args
+                .iter()
+                .map(|arg| self.infer(arg))
+                .last()
+                .unwrap_or(Type::Builtin("nil")),
+            "set!" => match args {
+                [Form::Atom(name, _), value] => self.assign(name, value),
+                _ => {
+                    self.walk(args);
+                    Type::Any
+                }
+            },
+            "defun" => {
+                if let [name, rest @ ..] = args {
+                    if let Some(name) = name.atom() {
+                        let top_level = self.scopes.is_empty();
+                        if top_level && self.definitions.get(name) == Some(&1) {
+                            self.globals
+                                .insert(name.to_string(), Type::Builtin("function"));
+                        } else if let Some(scope) = self.scopes.last_mut() {
+                            scope.insert(name.to_string(), Type::Any);
+                            if let [params, body @ ..] = rest {
+                                let signature = self.signature(params, body);
+                                self.declare_local(name, signature);
+                            }
+                        }
+                        self.function(name, rest);
+                    }
+                }
+                Type::Builtin("function")
+            }
+            "lambda" => {
+                self.function("lambda", args);
+                Type::Builtin("function")
+            }
+            "defmethod" => {
+                if let [_, Form::List(params, _), body @ ..] = args {
+                    let mut scope = HashMap::from([("next-method".to_string(), Type::Any)]);
+                    for param in params {
+                        match param {
+                            Form::Atom(name, _) => {
+                                scope.insert(name.clone(), Type::Any);
+                            }
+                            Form::List(parts, _) => {
+                                if let [Form::Atom(name, _), annotation] = &parts[..] {
+                                    let specializer = self.annotation(annotation, false);
+                                    scope.insert(name.clone(), specializer);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    self.scoped(scope, body);
+                }
+                Type::Any
+            }
+            "let" => {
+                let [Form::List(bindings, _), body @ ..] = args else {
+                    return Type::Any;
+                };
+                self.scopes.push(HashMap::new());
+                for binding in bindings {
+                    if let Form::List(parts, _) = binding {
+                        if let [pattern, value] = &parts[..] {
+                            let value_type = self.infer(value);
+                            let scope = self.scopes.last_mut().unwrap();
+                            match pattern {
+                                Form::Atom(name, _) => {
+                                    scope.insert(name.clone(), value_type);
+                                    if let Some(("lambda", [params, body @ ..])) = head(value) {
+                                        let signature = self.signature(params, body);
+                                        self.declare_local(name, signature);
+                                    } else {
+                                        self.forget_local(name);
+                                    }
+                                }
+                                pattern => binding_names(pattern, scope),
+                            }
+                        }
+                    }
+                }
+                let mut result = Type::Builtin("nil");
+                for form in body {
+                    result = self.infer(form);
+                }
+                self.pop_scope();
+                result
+            }
+            "doseq" => {
+                if let [Form::List(binding, _), body @ ..] = args {
+                    if let [Form::Atom(name, _), collection] = &binding[..] {
+                        self.infer(collection);
+                        self.scoped(HashMap::from([(name.clone(), Type::Any)]), body);
+                    }
+                }
+                Type::Builtin("nil")
+            }
+            "match" => {
+                if let Some((subject, clauses)) = args.split_first() {
+                    self.infer(subject);
+                    for clause in clauses {
+                        if let Form::List(items, _) = clause {
+                            if let Some((pattern, rest)) = items.split_first() {
+                                let mut scope = HashMap::new();
+                                pattern_names(pattern, &mut scope);
+                                self.scoped(scope, rest);
+                            }
+                        }
+                    }
+                }
+                Type::Any
+            }
+            "shift" => {
+                if let [Form::Atom(k, _), body @ ..] = args {
+                    self.scoped(
+                        HashMap::from([(k.clone(), Type::Builtin("function"))]),
+                        body,
+                    );
+                }
+                Type::Any
+            }
+            "try" => {
+                for arg in args {
+                    match head(arg) {
+                        Some(("catch", [Form::Atom(name, _), body @ ..])) => {
+                            self.scoped(HashMap::from([(name.clone(), Type::Any)]), body);
+                        }
+                        _ => {
+                            self.infer(arg);
+                        }
+                    }
+                }
+                Type::Any
+            }
+            "defstruct" | "defgeneric" | "require" | "import" | "provide" => Type::Any,
+            _ => self.call(form, op, args),
+        }
+    }
+
+    fn call(&mut self, form: &Form, name: &str, args: &[Form]) -> Type {
+        let signature = self.signature_of(name);
+        if self.is_local(name) && signature.is_none() {
+            self.walk(args);
+            return Type::Any;
+        }
+        if let Some(signature) = signature {
+            let Signature {
+                params,
+                variadic,
+                ret,
+            } = signature;
+            if variadic && args.len() < params.len() {
+                self.errors.push(format!(
+                    "Type error at {}: {} takes at least {} arguments, got {}",
+                    form.span(),
+                    name,
+                    params.len(),
+                    args.len()
+                ));
+            }
+            if !variadic && args.len() != params.len() {
+                self.errors.push(format!(
+                    "Type error at {}: {} takes {} arguments, got {}",
+                    form.span(),
+                    name,
+                    params.len(),
+                    args.len()
+                ));
+            }
+            for (i, arg) in args.iter().enumerate() {
+                match params.get(i) {
+                    Some(param) => {
+                        self.expect(arg, param, name);
+                    }
+                    None => {
+                        self.infer(arg);
+                    }
+                }
+            }
+            return ret;
+        }
+        self.walk(args);
+        match name.strip_prefix("make-") {
+            Some(struct_name) if self.structs.iter().any(|s| s == struct_name) => {
+                Type::Struct(struct_name.to_string())
+            }
+            _ => Type::Any,
+        }
+    }
+
+    /// `(set! name value)` has to respect the annotation of a parameter, and
+    /// gives globals defined only there the type of `value`.
+    fn assign(&mut self, name: &str, value: &Form) -> Type {
+        let actual = self.infer(value);
+        self.forget_local(name);
+        match self.lookup(name) {
+            Some(declared) if !actual.is_consistent(&declared) => {
+                let expectation = format!("{} is declared {}", name, declared);
+                self.mismatch(value, &expectation, &actual);
+            }
+            Some(_) => {}
+            None if self.scopes.is_empty() && self.definitions.get(name) == Some(&1) => {
+                self.globals.insert(name.to_string(), actual.clone());
+            }
+            None => {}
+        }
+        actual
+    }
+
+    /// Checks a function whose lambda list and body are `rest`.
+    fn function(&mut self, name: &str, rest: &[Form]) {
+        let Some((params, rest)) = rest.split_first() else {
+            return;
+        };
+        let params = self.params(params, true);
+        let annotated = params.annotated || rest.first().and_then(Form::atom) == Some(":");
+        let (declared, body) = self.return_type(rest, true);
+        let strict = self.strict;
+        self.strict |= annotated;
+        let mut scope: HashMap<String, Type> = params.positional.into_iter().collect();
+        scope.extend(params.bound);
+        self.scopes.push(scope);
+        for (key, key_type, default) in params.keys {
+            if let Some(default) = default {
+                let what = format!("the default of {}", key);
+                self.expect(default, &key_type, &what);
+            }
+            self.scopes.last_mut().unwrap().insert(key, key_type);
+        }
+        let mut actual = Type::Any;
+        for form in body {
+            actual = self.infer(form);
+        }
+        if let Some(last) = body.last() {
+            if !actual.is_consistent(&declared) {
+                self.mismatch(
+                    last,
+                    &format!("{} should return {}", name, declared),
+                    &actual,
+                );
+            }
+        }
+        self.pop_scope();
+        self.strict = strict;
+    }
+
+    fn scoped(&mut self, scope: HashMap<String, Type>, body: &[Form]) {
+        self.scopes.push(scope);
+        self.walk(body);
+        self.pop_scope();
+    }
+}
+
+/// Adds the names a `match` pattern binds to `scope`, as `Any`.
+fn pattern_names(pattern: &Form, scope: &mut HashMap<String, Type>) {
+    match pattern {
+        Form::Atom(atom, _) => {
+            let literal = matches!(atom.as_str(), "_" | "&" | "nil" | "true" | "false")
+                || atom.starts_with(['"', '#'])
+                || is_number_literal(atom);
+            if !literal {
+                scope.insert(atom.clone(), Type::Any);
+            }
+        }
+        // the head of a list pattern is `list` or a struct name
+        Form::List(items, _) => {
+            for item in items.iter().skip(1) {
+                pattern_names(item, scope);
+            }
+        }
+        Form::Vector(items, _) | Form::Map(items, _) => {
+            for item in items {
+                pattern_names(item, scope);
+            }
+        }
+        Form::Keyword(..) => {}
+    }
+}
+
+/// Adds the names a `let` or parameter pattern binds to `scope`, as `Any`.
+fn binding_names(pattern: &Form, scope: &mut HashMap<String, Type>) {
+    match pattern {
+        Form::Atom(atom, _) if atom != "_" && atom != "&" => {
+            scope.insert(atom.clone(), Type::Any);
+        }
+        Form::List(items, _) | Form::Vector(items, _) | Form::Map(items, _) => {
+            for item in items {
+                binding_names(item, scope);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The head and arguments of a list starting with an atom.
+fn head(form: &Form) -> Option<(&str, &[Form])> {
+    match form {
+        Form::List(items, _) => match items.split_first() {
+            Some((Form::Atom(op, _), args)) => Some((op, args)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn check(source: &str) -> Result<(), String> {
+        super::check(&Scanner::new(source), &Types::default()).map(|_| ())
+    }
+
+    #[test]
+    fn test_literals() {
+        let checker = Checker::default();
+        assert_eq!(checker.infer_atom("1"), Type::Builtin("integer"));
+        assert_eq!(checker.infer_atom("-0x1f"), Type::Builtin("integer"));
+        assert_eq!(checker.infer_atom("1.5e3"), Type::Builtin("float"));
+        assert_eq!(checker.infer_atom("\"a\""), Type::Builtin("string"));
+        assert_eq!(checker.infer_atom("#\\a"), Type::Builtin("char"));
+        assert_eq!(checker.infer_atom("x"), Type::Any);
+    }
+
+    #[test]
+    fn test_consistency() {
+        let number = Type::Builtin("number");
+        assert!(Type::Builtin("integer").is_consistent(&number));
+        assert!(number.is_consistent(&Type::Builtin("float")));
+        assert!(Type::Any.is_consistent(&Type::Builtin("string")));
+        assert!(!Type::Builtin("string").is_consistent(&number));
+        assert_eq!(
+            Type::Builtin("integer").join(&Type::Builtin("float")),
+            number
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check("(+ 1 2)").is_ok());
+        // unannotated code is left to fail at runtime
+        assert!(check("(+ \"a\" 1)").is_ok());
+        assert_eq!(
+            check("(defun f ((s : string)) (+ s 1))"),
+            Err("Type error at line 0, column 27: + expects number, got string s".to_string())
+        );
+        assert!(check("(defun f (x) (+ x 1))\n(f \"a\")").is_ok());
+        let source = "(defun add ((x : number) (y : number)) : number (+ x y))\n(add 1 \"b\")";
+        assert_eq!(
+            check(source),
+            Err("Type error at line 1, column 7: add expects number, got string \"b\"".to_string())
+        );
+        assert!(check("(+ 1").is_ok());
+    }
+
+    #[test]
+    fn test_types_of_earlier_sources() {
+        let check_after = |types: &Types, source: &str| super::check(&Scanner::new(source), types);
+        let types = check_after(&Types::default(), "(defun h ((x : number)) x)").unwrap();
+        assert!(check_after(&types, "(h \"a\")").is_err());
+        assert!(check_after(&types, "(defun h (x) x) (h \"a\")").is_ok());
+
+        let types = check_after(&types, "(defstruct p x)").unwrap();
+        let types = check_after(&types, "(defun f ((q : p)) q)").unwrap();
+        assert!(check_after(&types, "(f 1)").is_err());
+        let types = check_after(&types, "(set! f 1)").unwrap();
+        assert!(check_after(&types, "(f 1)").is_ok());
+    }
+
+    #[rstest]
+    #[case(
+        "(defun f () : number \"a\")",
+        "f should return number, got string \"a\""
+    )]
+    #[case(
+        "(defun f ((x : integer)) (set! x 1.5))",
+        "x is declared integer, got float 1.5"
+    )]
+    #[case(
+        "(defun f ((b : bool)) (if b 1 \"a\"))\n(defun g ((n : number)) (+ (f true) n))",
+        ""
+    )]
+    #[case("(defun f ((x : number)) (if x 1 2))", "if expects bool, got number x")]
+    #[case(
+        "(defun f ((x : number)) (< x \"a\"))",
+        "< expects number, got string \"a\""
+    )]
+    #[case("(defun f ((x : shape)) x)", "Unknown type shape at line 0, column 15")]
+    #[case(
+        "(defun f (&key (n : integer \"a\")) n)",
+        "the default of n expects integer"
+    )]
+    #[case(
+        "(defstruct p x)\n(defun f ((q : p)) q)\n(f (make-p 1))\n(f 1)",
+        "f expects p, got integer 1"
+    )]
+    #[case("(defun f ((x : number)) x)\n(f)", "f takes 1 arguments, got 0")]
+    #[case(
+        "(set! s \"a\")\n(defun f ((x : number)) (+ x s))",
+        "+ expects number, got string s"
+    )]
+    #[case("(set! s \"a\")\n(set! s 1)\n(defun f ((x : number)) (+ x s))", "")]
+    #[case("(defun f ((x : number)) (doseq (x [\"a\"]) (+ x 1)))", "")]
+    #[case("(defun f ((x : number)) (match [\"a\"] ([x] (+ x 1)) (_ x)))", "")]
+    #[case(
+        "(defun f ((x : number)) (let ((s \"a\")) (+ x s)))",
+        "+ expects number, got string s"
+    )]
+    #[case("(defun f ((x : number) [s]) (let (((x) [\"a\"])) (+ x s)))", "")]
+    #[case(
+        "(defun f () (defun g ((x : number)) x) (g \"a\"))",
+        "g expects number, got string \"a\""
+    )]
+    #[case(
+        "(defun f () (defun g ((x : number)) x) (g))",
+        "g takes 1 arguments, got 0"
+    )]
+    #[case(
+        "(defun f ((x : number)) (+ x (let ((g (lambda () : string \"a\"))) (g))))",
+        "+ expects number, got string (let"
+    )]
+    #[case(
+        "(let ((g (lambda ((x : integer)) x))) (g 1.5))",
+        "g expects integer, got float 1.5"
+    )]
+    #[case("(let ((g (lambda ((x : integer)) x))) (let ((g 1)) (g 1.5)))", "")]
+    #[case("(let ((g (lambda ((x : integer)) x))) (set! g list) (g 1.5))", "")]
+    fn test_mismatches(#[case] source: &str, #[case] error: &str) {
+        match check(source) {
+            Ok(()) => assert_eq!(error, "", "no error for {}", source),
+            Err(err) => {
+                assert!(!error.is_empty(), "{} for {}", err, source);
+                assert!(err.contains(error), "{} for {}", err, source);
+            }
+        }
+    }
+}
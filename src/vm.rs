@@ -1,26 +1,214 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use itertools::Itertools;
+
 use crate::chunk::closure::{Closure, ObjUpvalue};
-use crate::chunk::object::{Function, Object};
+use crate::chunk::generic::{self, NextMethod};
+use crate::chunk::object::{
+    ErrorObject, Function, Instance, NativeFn, NativeFunction, Object, StructType,
+};
 use crate::chunk::value::Value;
 use crate::chunk::{Chunk, OpCode};
 
+pub mod natives;
+mod prelude;
+pub mod seq;
+
+#[derive(Clone)]
 struct CallFrame {
     closure: Box<Closure>,
     ip: usize,
     stackpointer: usize,
+    locals: Vec<Rc<RefCell<Value>>>,
+}
+
+impl CallFrame {
+    fn new(
+        closure: Box<Closure>,
+        stackpointer: usize,
+        locals: Vec<Rc<RefCell<Value>>>,
+    ) -> CallFrame {
+        CallFrame {
+            closure,
+            ip: 0,
+            stackpointer,
+            locals,
+        }
+    }
+
+    /// Returns the cell backing a local slot, creating it (and any slot before
+    /// it) if the declaration has not been executed yet.
+    fn local(&mut self, slot: usize) -> Rc<RefCell<Value>> {
+        while self.locals.len() <= slot {
+            self.locals.push(Rc::new(RefCell::new(Value::Nil)));
+        }
+        self.locals[slot].clone()
+    }
+
+    /// Binds a local slot to a new cell holding `value`, leaving the old cell
+    /// to the closures that captured it.
+    fn rebind(&mut self, slot: usize, value: Value) {
+        self.local(slot);
+        self.locals[slot] = Rc::new(RefCell::new(value));
+    }
+}
+
+/// An active `try`. When an error is raised the frames and stack are cut back
+/// to their size at the `try` and execution resumes at `ip` in that frame.
+#[derive(Clone)]
+struct Handler {
+    frames: usize,
+    stack: usize,
+    ip: usize,
+}
+
+/// An active `reset`. Its body runs as a call, in the frame at depth `frames`
+/// whose callee sits at `stack`.
+#[derive(Clone)]
+struct Prompt {
+    frames: usize,
+    stack: usize,
+}
+
+/// The part of the computation between a `shift` and its `reset`: the frames
+/// above the reset, their stack, and the handlers and prompts they installed,
+/// with depths relative to the reset. Calling it puts copies of the frames
+/// back on top of the caller, the copies share their local variables.
+pub struct Continuation {
+    frames: Vec<CallFrame>,
+    stack: Vec<Value>,
+    handlers: Vec<Handler>,
+    prompts: Vec<Prompt>,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(continuation {})", self.frames.len())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineStatus {
+    Suspended,
+    Running,
+    Dead,
+}
+
+impl CoroutineStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            CoroutineStatus::Suspended => "suspended",
+            CoroutineStatus::Running => "running",
+            CoroutineStatus::Dead => "dead",
+        }
+    }
+}
+
+/// A function that can suspend itself with `yield` and be resumed later.
+/// While suspended it owns its frames, stack, handlers and prompts, with depths
+/// relative to the `resume` that last ran it. Resuming moves them back on top
+/// of the resumer, so a coroutine runs on the machine like any other call.
+pub struct Coroutine {
+    status: CoroutineStatus,
+    started: bool,
+    frames: Vec<CallFrame>,
+    stack: Vec<Value>,
+    handlers: Vec<Handler>,
+    prompts: Vec<Prompt>,
+}
+
+impl Coroutine {
+    /// A suspended coroutine that calls `function` when it is first resumed.
+    pub fn new(function: Value) -> Coroutine {
+        Coroutine {
+            status: CoroutineStatus::Suspended,
+            started: false,
+            frames: Vec::new(),
+            stack: vec![function],
+            handlers: Vec::new(),
+            prompts: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> CoroutineStatus {
+        self.status
+    }
+}
+
+impl fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(coroutine {})", self.status.name())
+    }
+}
+
+/// A running coroutine and the depth of the frames and stack of the `resume`
+/// that runs it.
+struct Resumer {
+    frames: usize,
+    stack: usize,
+    coroutine: Rc<RefCell<Coroutine>>,
 }
 
 pub struct VirtualMachine {
     stack: Vec<Value>,
     frames: Vec<CallFrame>,
-    fp: usize,
+    handlers: Vec<Handler>,
+    prompts: Vec<Prompt>,
+    resumers: Vec<Resumer>,
+    globals: HashMap<String, Value>,
     debug: bool,
-    ip: usize,
+}
+
+/// What went wrong in a runtime error, it becomes the kind of the error
+/// value a `catch` clause binds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Type,
+    Arity,
+    DivisionByZero,
+    UndefinedSymbol,
+    Runtime,
+}
+
+impl ErrorKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Type => "type-error",
+            ErrorKind::Arity => "arity-error",
+            ErrorKind::DivisionByZero => "division-by-zero",
+            ErrorKind::UndefinedSymbol => "undefined-symbol",
+            ErrorKind::Runtime => "runtime-error",
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum VMErr {
-    CompileError,
-    RuntimeError(String),
+    CompileError(String),
+    RuntimeError(ErrorKind, String),
+    /// A value thrown with `throw` that no `try` caught.
+    Exception(Value),
+}
+
+impl VMErr {
+    /// The value a `catch` clause binds for this error.
+    fn into_value(self) -> Value {
+        match self {
+            VMErr::Exception(value) => value,
+            VMErr::CompileError(message) => {
+                let error = ErrorObject::new("compile-error", &message, HashMap::new());
+                Value::Obj(Box::new(Object::Error(error)))
+            }
+            VMErr::RuntimeError(kind, message) => {
+                let error = ErrorObject::new(kind.name(), &message, HashMap::new());
+                Value::Obj(Box::new(Object::Error(error)))
+            }
+        }
+    }
 }
 
 macro_rules! nullary {
@@ -34,7 +222,10 @@ macro_rules! unary {
     ($fn:expr, $self:expr, $ip:expr) => {{
         let arg = $self.stack.pop().unwrap();
         let f = $fn;
-        $self.stack.push(f(arg));
+        let result: Result<Value, String> = f(arg);
+        $self
+            .stack
+            .push(result.map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?);
         $self.set_ip($ip + 1);
     }};
 }
@@ -44,61 +235,610 @@ macro_rules! binary {
         let arg2 = $self.stack.pop().unwrap();
         let arg1 = $self.stack.pop().unwrap();
         let f = $fn;
-        $self.stack.push(f(arg1, arg2));
+        let result: Result<Value, String> = f(arg1, arg2);
+        $self
+            .stack
+            .push(result.map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?);
+        $self.set_ip($ip + 1);
+    }};
+}
+
+/// Like `binary`, but an exact zero divisor is a `division-by-zero` error.
+macro_rules! division {
+    ($fn:expr, $self:expr, $ip:expr) => {{
+        let arg2 = $self.stack.pop().unwrap();
+        let arg1 = $self.stack.pop().unwrap();
+        let kind = if arg1.is_exact_division_by_zero(&arg2) {
+            ErrorKind::DivisionByZero
+        } else {
+            ErrorKind::Type
+        };
+        let f = $fn;
+        let result: Result<Value, String> = f(arg1, arg2);
+        $self
+            .stack
+            .push(result.map_err(|message| VMErr::RuntimeError(kind, message))?);
         $self.set_ip($ip + 1);
     }};
 }
 
+/// Checks the target of the field accessor `accessor`.
+fn expect_instance<'a>(
+    target: &'a Value,
+    struct_type: &Rc<StructType>,
+    accessor: String,
+) -> Result<&'a Instance, VMErr> {
+    match target.get_instance() {
+        Some(instance) if instance.is_a(struct_type) => Ok(instance),
+        _ => Err(VMErr::RuntimeError(
+            ErrorKind::Type,
+            format!(
+                "{} expects a {}, got: {}",
+                accessor, struct_type.name, target
+            ),
+        )),
+    }
+}
+
+/// Orders two values, unordered values (NaN) never satisfy `accept`.
+fn compare(x: &Value, y: &Value, accept: fn(Ordering) -> bool) -> Result<Value, String> {
+    Ok(Value::Bool(x.compare(y)?.is_some_and(accept)))
+}
+
 impl VirtualMachine {
     pub fn new(debug: bool) -> VirtualMachine {
-        VirtualMachine {
+        let mut vm = VirtualMachine::without_prelude(debug);
+        prelude::load(&mut vm);
+        vm
+    }
+
+    /// A machine with only the core natives, the prelude functions such as
+    /// `apply` and `sort` are not defined.
+    pub fn without_prelude(debug: bool) -> VirtualMachine {
+        let mut vm = VirtualMachine {
             stack: Vec::new(),
             frames: Vec::new(),
-            fp: 0,
+            handlers: Vec::new(),
+            prompts: Vec::new(),
+            resumers: Vec::new(),
+            globals: HashMap::new(),
             debug,
-            ip: 0,
-        }
+        };
+        natives::register(&mut vm);
+        vm
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
     }
 
     pub fn get_chunk(&self) -> Chunk {
-        self.frames[self.fp].closure.function.chunk.clone()
+        self.frames.last().unwrap().closure.function.chunk.clone()
     }
 
     pub fn get_ip(&self) -> usize {
-        self.frames[self.fp].ip
+        self.frames.last().unwrap().ip
     }
 
     pub fn set_ip(&mut self, ip: usize) {
-        self.frames[self.fp].ip = ip;
+        self.frame().ip = ip;
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    /// Binds a native function to a global name. `arity` is `None` for
+    /// variadic functions.
+    pub fn define_native(&mut self, name: &str, arity: Option<usize>, function: NativeFn) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function,
+        };
+        self.set_global(name, Value::Obj(Box::new(Object::Native(native))));
     }
 
     pub fn run(&mut self, chunk: &Chunk) -> Result<Value, VMErr> {
-        let frame = CallFrame {
-            closure: Box::new(Closure {
-                function: Box::new(Function {
-                    arity: 0,
-                    chunk: chunk.clone(),
-                    name: "main".to_string(),
-                    upvalue_count: 0,
-                }),
-                upvalues: Vec::new(),
+        let closure = Box::new(Closure {
+            function: Rc::new(Function {
+                arity: 0,
+                chunk: chunk.clone(),
+                name: "main".to_string(),
+                upvalue_count: 0,
+                rest: false,
+                keys: Vec::new(),
             }),
-            ip: self.ip,
-            stackpointer: 0,
+            upvalues: Vec::new(),
+        });
+
+        let base = self.frames.len();
+        let stackpointer = self.stack.len();
+        self.frames
+            .push(CallFrame::new(closure, stackpointer, Vec::new()));
+        self.execute(base, stackpointer)
+    }
+
+    /// Calls `callee` with `args` and runs it to completion. Used by the host
+    /// to call back into flox code.
+    pub fn call(&mut self, callee: &Value, args: &[Value]) -> Result<Value, VMErr> {
+        let base = self.frames.len();
+        let stackpointer = self.stack.len();
+        self.stack.push(callee.clone());
+        self.stack.extend(args.iter().cloned());
+        if let Err(err) = self.call_value(args.len()) {
+            self.stack.truncate(stackpointer);
+            return Err(err);
+        }
+        if self.frames.len() == base {
+            // natives run to completion inside call_value
+            return Ok(self.stack.pop().unwrap());
+        }
+        self.execute(base, stackpointer)
+    }
+
+    fn call_value(&mut self, argc: usize) -> Result<(), VMErr> {
+        let callee = self.stack.len() - 1 - argc;
+
+        if let Some(native) = self.stack[callee].get_native().cloned() {
+            if native.arity.is_some_and(|arity| arity != argc) {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Arity,
+                    format!(
+                        "{} expects {} arguments, got {}",
+                        native.name,
+                        native.arity.unwrap(),
+                        argc
+                    ),
+                ));
+            }
+            let mut args: Vec<Value> = self.stack.drain(callee + 1..).collect();
+            self.stack.pop();
+            let result = (native.function)(self, &mut args)?;
+            self.stack.push(result);
+            return Ok(());
+        }
+
+        if let Some(continuation) = self.stack[callee].get_continuation() {
+            if argc > 1 {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Arity,
+                    format!("continuation expects 0 to 1 arguments, got {}", argc),
+                ));
+            }
+            let value = if argc == 1 {
+                self.stack.pop().unwrap()
+            } else {
+                Value::Nil
+            };
+            self.stack.pop();
+            self.reinstate(&continuation, value);
+            return Ok(());
+        }
+
+        if let Some(keyword) = self.stack[callee].get_keyword().cloned() {
+            if !(1..=2).contains(&argc) {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Arity,
+                    format!(":{} expects 1 to 2 arguments, got {}", keyword, argc),
+                ));
+            }
+            let default = if argc == 2 {
+                self.stack.pop().unwrap()
+            } else {
+                Value::Nil
+            };
+            let target = self.stack.pop().unwrap();
+            self.stack.pop();
+            let value = lookup_keyword(&keyword, &target, default)?;
+            self.stack.push(value);
+            return Ok(());
+        }
+
+        if let Some(generic) = self.stack[callee].get_generic() {
+            if generic.arity != argc {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Arity,
+                    format!(
+                        "{} expects {} arguments, got {}",
+                        generic.name, generic.arity, argc
+                    ),
+                ));
+            }
+            let args = self.stack[callee + 1..].to_vec();
+            let methods = generic.applicable(&args);
+            if methods.is_empty() {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    format!(
+                        "No method of {} applies to ({})",
+                        generic.name,
+                        args.iter().map(Value::readable).join(" ")
+                    ),
+                ));
+            }
+            return self.call_method(callee, generic.name.clone(), methods, args);
+        }
+
+        if let Some(next) = self.stack[callee].get_next_method() {
+            if argc != 0 {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Arity,
+                    format!("next-method expects 0 arguments, got {}", argc),
+                ));
+            }
+            if next.methods.is_empty() {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    format!("No next method of {}", next.name),
+                ));
+            }
+            self.stack.truncate(callee + 1);
+            self.stack.extend(next.args.iter().cloned());
+            return self.call_method(
+                callee,
+                next.name.clone(),
+                next.methods.clone(),
+                next.args.clone(),
+            );
+        }
+
+        let closure = self.stack[callee].get_closure().ok_or_else(|| {
+            VMErr::RuntimeError(
+                ErrorKind::Type,
+                format!("{} is not callable", self.stack[callee]),
+            )
+        })?;
+
+        let function = &closure.function;
+        if function.rest && argc < function.arity {
+            return Err(VMErr::RuntimeError(
+                ErrorKind::Arity,
+                format!(
+                    "{} expects at least {} arguments, got {}",
+                    function.name, function.arity, argc
+                ),
+            ));
+        }
+        if !function.rest && function.keys.is_empty() && function.arity != argc {
+            return Err(VMErr::RuntimeError(
+                ErrorKind::Arity,
+                format!(
+                    "{} expects {} arguments, got {}",
+                    function.name, function.arity, argc
+                ),
+            ));
+        }
+        if !function.keys.is_empty() && (argc < function.arity || (argc - function.arity) % 2 != 0)
+        {
+            return Err(VMErr::RuntimeError(
+                ErrorKind::Arity,
+                format!(
+                    "{} expects {} arguments plus keyword arguments, got {}",
+                    function.name, function.arity, argc
+                ),
+            ));
+        }
+
+        let mut args: Vec<Value> = self.stack.drain(callee + 1..).collect();
+        if function.rest {
+            let rest = args.split_off(function.arity);
+            args.push(Value::Obj(Box::new(Object::List(Rc::new(rest)))));
+        }
+        if !function.keys.is_empty() {
+            let pairs = args.split_off(function.arity);
+            args.extend(key_arguments(function, pairs)?);
+        }
+        let locals = args
+            .into_iter()
+            .map(|arg| Rc::new(RefCell::new(arg)))
+            .collect();
+        self.frames.push(CallFrame::new(closure, callee, locals));
+        Ok(())
+    }
+
+    /// Calls the first of `methods` in place of the generic function at
+    /// `callee`, with the rest of them as its `next-method`.
+    fn call_method(
+        &mut self,
+        callee: usize,
+        name: Rc<str>,
+        mut methods: Vec<Value>,
+        args: Vec<Value>,
+    ) -> Result<(), VMErr> {
+        let method = methods.remove(0);
+        let argc = args.len();
+        let next = NextMethod {
+            name,
+            methods,
+            args,
+        };
+        self.stack[callee] = method;
+        self.stack.insert(
+            callee + 1,
+            Value::Obj(Box::new(Object::NextMethod(Rc::new(next)))),
+        );
+        self.call_value(argc + 1)
+    }
+
+    /// Runs until the frame at depth `base` returns. On error the frames and
+    /// stack are unwound back to where they were so the machine stays usable.
+    fn execute(&mut self, base: usize, stackpointer: usize) -> Result<Value, VMErr> {
+        loop {
+            let result = self.dispatch(base);
+            match result {
+                Err(VMErr::RuntimeError(..) | VMErr::Exception(_)) if self.can_catch(base) => {
+                    self.catch(result.unwrap_err());
+                }
+                Err(_) => {
+                    self.frames.truncate(base);
+                    self.stack.truncate(stackpointer);
+                    self.drop_scopes();
+                    return result;
+                }
+                Ok(_) => return result,
+            }
+        }
+    }
+
+    /// Only handlers installed by frames of this `execute` can catch, the
+    /// ones below belong to a caller that is waiting for a native to return.
+    fn can_catch(&self, base: usize) -> bool {
+        self.handlers
+            .last()
+            .is_some_and(|handler| handler.frames > base)
+    }
+
+    fn catch(&mut self, err: VMErr) {
+        let handler = self.handlers.pop().unwrap();
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack);
+        self.drop_scopes();
+        self.stack.push(err.into_value());
+        self.set_ip(handler.ip);
+    }
+
+    /// Forgets the handlers and prompts of frames that are gone. Coroutines
+    /// whose frames are all gone have returned or raised an error, either way
+    /// they are finished.
+    fn drop_scopes(&mut self) {
+        let frames = self.frames.len();
+        while self
+            .handlers
+            .last()
+            .is_some_and(|handler| handler.frames > frames)
+        {
+            self.handlers.pop();
+        }
+        while self
+            .prompts
+            .last()
+            .is_some_and(|prompt| prompt.frames >= frames)
+        {
+            self.prompts.pop();
+        }
+        while self
+            .resumers
+            .last()
+            .is_some_and(|resumer| resumer.frames >= frames)
+        {
+            let resumer = self.resumers.pop().unwrap();
+            resumer.coroutine.borrow_mut().status = CoroutineStatus::Dead;
+        }
+    }
+
+    /// Moves everything above the innermost `reset` into a continuation. The
+    /// prompt stays, so that the `shift` body runs inside the `reset`.
+    fn capture(&mut self, base: usize) -> Result<Continuation, VMErr> {
+        // a coroutine delimits continuations, like a native call does
+        let floor = self
+            .resumers
+            .last()
+            .map_or(base, |resumer| base.max(resumer.frames + 1));
+        let prompt = match self.prompts.last() {
+            Some(prompt) if prompt.frames >= floor => prompt.clone(),
+            _ => {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    "shift without an enclosing reset".to_string(),
+                ))
+            }
         };
+        let handlers = self
+            .handlers
+            .iter()
+            .position(|handler| handler.frames > prompt.frames)
+            .map_or_else(Vec::new, |start| self.handlers.split_off(start));
+        let prompts = self
+            .prompts
+            .iter()
+            .position(|inner| inner.frames > prompt.frames)
+            .map_or_else(Vec::new, |start| self.prompts.split_off(start));
+        Ok(Continuation {
+            frames: self
+                .frames
+                .split_off(prompt.frames)
+                .into_iter()
+                .map(|mut frame| {
+                    frame.stackpointer -= prompt.stack;
+                    frame
+                })
+                .collect(),
+            stack: self.stack.split_off(prompt.stack),
+            handlers: handlers
+                .into_iter()
+                .map(|handler| Handler {
+                    frames: handler.frames - prompt.frames,
+                    stack: handler.stack - prompt.stack,
+                    ip: handler.ip,
+                })
+                .collect(),
+            prompts: prompts
+                .into_iter()
+                .map(|inner| Prompt {
+                    frames: inner.frames - prompt.frames,
+                    stack: inner.stack - prompt.stack,
+                })
+                .collect(),
+        })
+    }
+
+    /// Runs a continuation as `(reset K[value])` on top of the current frames.
+    fn reinstate(&mut self, continuation: &Continuation, value: Value) {
+        let frames = self.frames.len();
+        let stack = self.stack.len();
+        self.prompts.push(Prompt { frames, stack });
+        self.prompts
+            .extend(continuation.prompts.iter().map(|prompt| Prompt {
+                frames: prompt.frames + frames,
+                stack: prompt.stack + stack,
+            }));
+        self.handlers
+            .extend(continuation.handlers.iter().map(|handler| Handler {
+                frames: handler.frames + frames,
+                stack: handler.stack + stack,
+                ip: handler.ip,
+            }));
+        self.frames
+            .extend(continuation.frames.iter().cloned().map(|mut frame| {
+                frame.stackpointer += stack;
+                frame
+            }));
+        self.stack.extend(continuation.stack.iter().cloned());
+        self.stack.push(value);
+    }
+
+    /// Moves a suspended coroutine on top of the current frames. The first
+    /// time it calls the coroutine's function with the `argc` arguments on the
+    /// stack, afterwards the value becomes the result of the pending `yield`.
+    fn resume(&mut self, coroutine: Rc<RefCell<Coroutine>>, argc: usize) -> Result<(), VMErr> {
+        let args = self.stack.split_off(self.stack.len() - argc);
+        let mut state = coroutine.borrow_mut();
+        match state.status {
+            CoroutineStatus::Suspended => {}
+            status => {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    format!("Cannot resume a {} coroutine", status.name()),
+                ))
+            }
+        }
+        let frames = self.frames.len();
+        let stack = self.stack.len();
+        state.status = CoroutineStatus::Running;
+        self.prompts
+            .extend(state.prompts.drain(..).map(|prompt| Prompt {
+                frames: prompt.frames + frames,
+                stack: prompt.stack + stack,
+            }));
+        self.handlers
+            .extend(state.handlers.drain(..).map(|handler| Handler {
+                frames: handler.frames + frames,
+                stack: handler.stack + stack,
+                ip: handler.ip,
+            }));
+        self.frames.extend(state.frames.drain(..).map(|mut frame| {
+            frame.stackpointer += stack;
+            frame
+        }));
+        self.stack.append(&mut state.stack);
+        let start = !state.started;
+        state.started = true;
+        drop(state);
+        self.resumers.push(Resumer {
+            frames,
+            stack,
+            coroutine,
+        });
+
+        if start {
+            self.stack.extend(args);
+            self.call_value(argc)?;
+            // a native runs to completion right away
+            self.drop_scopes();
+            Ok(())
+        } else {
+            self.stack
+                .push(args.into_iter().next().unwrap_or(Value::Nil));
+            Ok(())
+        }
+    }
 
-        self.frames.pop(); //cleanup callstack
-        self.frames.push(frame);
+    /// Suspends the innermost running coroutine, moving everything above its
+    /// `resume` back into it. `value` becomes the result of the `resume`.
+    fn suspend(&mut self, base: usize, value: Value) -> Result<(), VMErr> {
+        let resumer = match self.resumers.last() {
+            Some(resumer) if resumer.frames >= base => self.resumers.pop().unwrap(),
+            Some(_) => {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    "Cannot yield across a native call".to_string(),
+                ))
+            }
+            None => {
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    "yield outside of a coroutine".to_string(),
+                ))
+            }
+        };
+        let mut state = resumer.coroutine.borrow_mut();
+        state.status = CoroutineStatus::Suspended;
+        state.frames = self
+            .frames
+            .split_off(resumer.frames)
+            .into_iter()
+            .map(|mut frame| {
+                frame.stackpointer -= resumer.stack;
+                frame
+            })
+            .collect();
+        state.stack = self.stack.split_off(resumer.stack);
+        state.handlers = self
+            .handlers
+            .iter()
+            .position(|handler| handler.frames > resumer.frames)
+            .map_or_else(Vec::new, |start| self.handlers.split_off(start))
+            .into_iter()
+            .map(|handler| Handler {
+                frames: handler.frames - resumer.frames,
+                stack: handler.stack - resumer.stack,
+                ip: handler.ip,
+            })
+            .collect();
+        state.prompts = self
+            .prompts
+            .iter()
+            .position(|prompt| prompt.frames > resumer.frames)
+            .map_or_else(Vec::new, |start| self.prompts.split_off(start))
+            .into_iter()
+            .map(|prompt| Prompt {
+                frames: prompt.frames - resumer.frames,
+                stack: prompt.stack - resumer.stack,
+            })
+            .collect();
+        self.stack.push(value);
+        Ok(())
+    }
 
+    fn dispatch(&mut self, base: usize) -> Result<Value, VMErr> {
         loop {
-            let chunk = self.get_chunk();
+            let function = self.frames.last().unwrap().closure.function.clone();
+            let chunk = &function.chunk;
             let ip = self.get_ip();
             if !chunk.is_ip_in_range(ip) {
-                return Err(VMErr::RuntimeError(format!(
-                    "Attemting to access unreachable bytecode. ip: {}, len: {}",
-                    ip,
-                    chunk.len()
-                )));
+                return Err(VMErr::RuntimeError(
+                    ErrorKind::Runtime,
+                    format!(
+                        "Attemting to access unreachable bytecode. ip: {}, len: {}",
+                        ip,
+                        chunk.len()
+                    ),
+                ));
             }
             if self.debug {
                 let (s, _) = chunk.display_instruction(ip).unwrap();
@@ -112,23 +852,14 @@ impl VirtualMachine {
             let opcode = chunk.get_opcode(ip).unwrap();
             match opcode {
                 OpCode::OpRet => {
-                    if self.frames.len() == 1 {
-                        self.ip = self.get_ip() + 1;
-                        match self.stack.last() {
-                            Some(x) => return Ok(x.clone()),
-                            None => return Ok(Value::Nil),
-                        };
-                    } else {
-                        let ret = self.stack.pop().unwrap();
-                        for _i in 0..self.frames.last().unwrap().closure.function.arity {
-                            self.stack.pop();
-                        }
-
-                        self.stack.push(ret);
-                        self.frames.pop();
-                        self.fp -= 1;
-                        self.set_ip(self.get_ip() + 1);
+                    let ret = self.stack.pop().unwrap_or(Value::Nil);
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.stackpointer);
+                    self.drop_scopes();
+                    if self.frames.len() == base {
+                        return Ok(ret);
                     }
+                    self.stack.push(ret);
                 }
                 OpCode::OpConst => {
                     let (_, value) = chunk.get_constant(ip + 1);
@@ -143,138 +874,307 @@ impl VirtualMachine {
                 OpCode::OpNil => nullary!(Value::Nil, self, ip),
                 OpCode::OpTrue => nullary!(Value::Bool(true), self, ip),
                 OpCode::OpFalse => nullary!(Value::Bool(false), self, ip),
-                OpCode::OpAdd => binary!(|x, y| { x + y }, self, ip),
-                OpCode::OpSub => binary!(|x, y| { x - y }, self, ip),
-                OpCode::OpMul => binary!(|x, y| { x * y }, self, ip),
-                OpCode::OpDiv => binary!(|x, y| { x / y }, self, ip),
-                OpCode::OpNot => unary!(|x: Value| { !x }, self, ip),
-                OpCode::OpEq => binary!(|x, y| { Value::Bool(x == y) }, self, ip),
-                OpCode::OpNe => binary!(|x, y| { Value::Bool(x != y) }, self, ip),
-                OpCode::OpBt => binary!(|x, y| { Value::Bool(x > y) }, self, ip),
-                OpCode::OpBe => binary!(|x, y| { Value::Bool(x >= y) }, self, ip),
-                OpCode::OpLt => binary!(|x, y| { Value::Bool(x < y) }, self, ip),
-                OpCode::OpLe => binary!(|x, y| { Value::Bool(x <= y) }, self, ip),
-                OpCode::OpAnd => binary!(|x, y| { x & y }, self, ip),
-                OpCode::OpNand => binary!(|x: Value, y: Value| { !(x & y) }, self, ip),
-                OpCode::OpOr => binary!(|x, y| { x | y }, self, ip),
-                OpCode::OpNor => binary!(|x: Value, y: Value| { !(x | y) }, self, ip),
-                OpCode::OpXor => binary!(|x, y| { x ^ y }, self, ip),
-                OpCode::OpXnor => binary!(|x: Value, y: Value| { !(x ^ y) }, self, ip),
+                OpCode::OpAdd => binary!(|x: Value, y| x.checked_add(&y), self, ip),
+                OpCode::OpSub => binary!(|x: Value, y| x.checked_sub(&y), self, ip),
+                OpCode::OpMul => binary!(|x: Value, y| x.checked_mul(&y), self, ip),
+                OpCode::OpDiv => division!(|x: Value, y| x.checked_div(&y), self, ip),
+                OpCode::OpQuot => division!(|x: Value, y| x.checked_quot(&y), self, ip),
+                OpCode::OpRem => division!(|x: Value, y| x.checked_rem(&y), self, ip),
+                OpCode::OpMod => division!(|x: Value, y| x.checked_mod(&y), self, ip),
+                OpCode::OpNot => unary!(|x: Value| x.checked_not(), self, ip),
+                OpCode::OpEq => binary!(|x, y| Ok(Value::Bool(x == y)), self, ip),
+                OpCode::OpNe => binary!(|x, y| Ok(Value::Bool(x != y)), self, ip),
+                OpCode::OpBt => binary!(|x, y| compare(&x, &y, Ordering::is_gt), self, ip),
+                OpCode::OpBe => binary!(|x, y| compare(&x, &y, Ordering::is_ge), self, ip),
+                OpCode::OpLt => binary!(|x, y| compare(&x, &y, Ordering::is_lt), self, ip),
+                OpCode::OpLe => binary!(|x, y| compare(&x, &y, Ordering::is_le), self, ip),
+                OpCode::OpAnd => binary!(|x: Value, y| x.checked_and(&y), self, ip),
+                OpCode::OpNand => binary!(|x: Value, y| x.checked_and(&y)?.checked_not(), self, ip),
+                OpCode::OpOr => binary!(|x: Value, y| x.checked_or(&y), self, ip),
+                OpCode::OpNor => binary!(|x: Value, y| x.checked_or(&y)?.checked_not(), self, ip),
+                OpCode::OpXor => binary!(|x: Value, y| x.checked_xor(&y), self, ip),
+                OpCode::OpXnor => binary!(|x: Value, y| x.checked_xor(&y)?.checked_not(), self, ip),
                 OpCode::OpSetLocal => {
-                    let value = self.stack.last().unwrap();
-                    let slot = chunk.get_constant_index(ip + 1);
-                    self.stack[slot] = value.clone();
-                    self.set_ip(ip + 2);
+                    let value = self.stack.last().unwrap().clone();
+                    let slot = chunk.get_index(ip + 1);
+                    *self.frame().local(slot).borrow_mut() = value;
+                    self.set_ip(ip + 3);
                 }
                 OpCode::OpGetLocal => {
-                    let slot = chunk.get_constant_index(ip + 1);
-                    let fp = self.frames.last().unwrap().stackpointer;
-                    let id = slot + fp;
-                    if id >= self.stack.len() {
-                        return Err(VMErr::RuntimeError(String::from("Out of bound access")));
-                    }
-                    self.stack.push(self.stack[id].clone());
-                    self.set_ip(ip + 2);
+                    let slot = chunk.get_index(ip + 1);
+                    let value = self.frame().local(slot).borrow().clone();
+                    self.stack.push(value);
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpGetGlobal => {
+                    let (_, name) = chunk.get_wide_constant(ip + 1);
+                    let name = name.get_str().unwrap();
+                    let value = self.globals.get(name).cloned().ok_or_else(|| {
+                        VMErr::RuntimeError(
+                            ErrorKind::UndefinedSymbol,
+                            format!("Symbol {} is not defined", name),
+                        )
+                    })?;
+                    self.stack.push(value);
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpSetGlobal => {
+                    let (_, name) = chunk.get_wide_constant(ip + 1);
+                    let value = self.stack.last().unwrap().clone();
+                    self.globals
+                        .insert(name.get_str().unwrap().to_string(), value);
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpVector => {
+                    let count = chunk.get_index(ip + 1);
+                    let vector = self.stack.split_off(self.stack.len() - count);
+                    self.stack
+                        .push(Value::Obj(Box::new(Object::Vector(Rc::new(vector)))));
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpMap => {
+                    let count = chunk.get_index(ip + 1);
+                    let entries = self.stack.split_off(self.stack.len() - 2 * count);
+                    let map = entries
+                        .into_iter()
+                        .tuples()
+                        .collect::<HashMap<Value, Value>>();
+                    self.stack
+                        .push(Value::Obj(Box::new(Object::Map(Rc::new(map)))));
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpPop => {
+                    self.stack.pop();
+                    self.set_ip(ip + 1);
                 }
                 OpCode::OpJmpIfFalse => {
-                    let idx = chunk.get_constant_index(ip + 1);
+                    let idx = chunk.get_jump_target(ip + 1);
                     let pred = self.stack.pop().unwrap();
-                    if !pred
-                        .get_bool()
-                        .ok_or_else(|| VMErr::RuntimeError("Failed to get boolean".to_string()))?
-                    {
+                    if !pred.get_bool().ok_or_else(|| {
+                        VMErr::RuntimeError(ErrorKind::Type, "Failed to get boolean".to_string())
+                    })? {
                         self.set_ip(idx);
                     } else {
-                        self.set_ip(ip + 2);
+                        self.set_ip(ip + 3);
                     }
                 }
                 OpCode::OpJmp => {
-                    let idx = chunk.get_constant_index(ip + 1);
+                    let idx = chunk.get_jump_target(ip + 1);
                     self.set_ip(idx);
                 }
                 OpCode::OpCall => {
-                    let mut args: Vec<Value> = Vec::new();
-                    loop {
-                        let v = self.stack.pop().unwrap();
-                        if v.is_closure() {
-                            let f = v.get_closure().ok_or_else(|| {
-                                VMErr::RuntimeError("Failed to find closure".to_string())
-                            })?;
-
-                            let frame = CallFrame {
-                                closure: f.clone(),
-                                ip: 0,
-                                stackpointer: self.stack.len(),
-                            };
-
-                            println!("~~~~~~~~~~~~~~");
-                            println!("{}, ", f.function.chunk);
-                            println!("~~~~~~~~~~~~~~");
-
-                            self.frames.push(frame);
-                            self.fp += 1;
-                            break;
-                        } else {
-                            args.push(v);
-                        }
-                    }
-                    for arg in args.iter().rev() {
-                        self.stack.push(arg.clone());
-                    }
+                    let argc = chunk.get_constant_index(ip + 1);
+                    self.set_ip(ip + 2);
+                    self.call_value(argc)?;
                 }
                 OpCode::OpGetUpvalue => {
-                    let slot = chunk.get_constant_index(ip + 1);
-                    self.stack.push(
-                        dbg!(
-                            &self.stack[dbg!(
-                                &self
-                                    .frames
-                                    .get(self.fp)
-                                    .expect("expected frame")
-                                    .closure
-                                    .upvalues
-                            )
-                            .get(dbg!(slot))
-                            .expect("expected slot")
-                            .location]
-                        )
-                        .clone(),
-                    );
-                    self.set_ip(ip + 2);
+                    let slot = chunk.get_index(ip + 1);
+                    let value = self.frame().closure.upvalues[slot].cell.borrow().clone();
+                    self.stack.push(value);
+                    self.set_ip(ip + 3);
                 }
                 OpCode::OpSetUpvalue => {
-                    unimplemented!();
+                    let slot = chunk.get_index(ip + 1);
+                    let value = self.stack.last().unwrap().clone();
+                    *self.frame().closure.upvalues[slot].cell.borrow_mut() = value;
+                    self.set_ip(ip + 3);
                 }
                 OpCode::OpClosure => {
-                    let function = chunk.get_constant(ip + 1).1;
+                    let function = chunk.get_wide_constant(ip + 1).1.get_function().unwrap();
 
                     let mut closure = Closure {
-                        function: function.get_function().unwrap(),
+                        function: function.clone(),
                         upvalues: Vec::new(),
                     };
-                    let function = function.get_function().unwrap();
                     for i in 0..function.upvalue_count {
-                        let is_local = chunk.get_constant_index(ip + 2 * i + 2);
-                        let index = chunk.get_constant_index(ip + 2 * i + 3);
+                        let is_local = chunk.get_constant_index(ip + 3 * i + 3);
+                        let index = chunk.get_index(ip + 3 * i + 4);
 
-                        if is_local == 1 {
-                            closure.upvalues.push(ObjUpvalue {
-                                location: dbg!(self.frames[self.fp].stackpointer + index),
-                            });
-                        }
+                        let cell = if is_local == 1 {
+                            self.frame().local(index)
+                        } else {
+                            self.frame().closure.upvalues[index].cell.clone()
+                        };
+                        closure.upvalues.push(ObjUpvalue { cell });
                     }
                     self.stack
                         .push(Value::Obj(Box::new(Object::Closure(Box::new(closure)))));
-                    self.set_ip(ip + 2 + 2 * function.upvalue_count);
-                }
-                OpCode::OpPrint => unary!(
-                    |x| {
-                        println!("{:?}", x);
-                        x
-                    },
-                    self,
-                    ip
-                ),
+                    self.set_ip(ip + 3 + 3 * function.upvalue_count);
+                }
+                OpCode::OpTry => {
+                    let handler = chunk.get_jump_target(ip + 1);
+                    self.handlers.push(Handler {
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                        ip: handler,
+                    });
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpEndTry => {
+                    self.handlers.pop();
+                    self.set_ip(ip + 1);
+                }
+                OpCode::OpThrow => {
+                    let value = self.stack.pop().unwrap();
+                    return Err(VMErr::Exception(value));
+                }
+                OpCode::OpReset => {
+                    self.set_ip(ip + 1);
+                    self.prompts.push(Prompt {
+                        frames: self.frames.len(),
+                        stack: self.stack.len() - 1,
+                    });
+                    self.call_value(0)?;
+                }
+                OpCode::OpShift => {
+                    self.set_ip(ip + 1);
+                    let body = self.stack.pop().unwrap();
+                    let continuation = self.capture(base)?;
+                    self.stack.push(body);
+                    self.stack
+                        .push(Value::Obj(Box::new(Object::Continuation(Rc::new(
+                            continuation,
+                        )))));
+                    self.call_value(1)?;
+                }
+                OpCode::OpResume => {
+                    let argc = chunk.get_constant_index(ip + 1);
+                    self.set_ip(ip + 2);
+                    let target = self.stack.len() - 1 - argc;
+                    let coroutine = self.stack[target].get_coroutine().ok_or_else(|| {
+                        VMErr::RuntimeError(
+                            ErrorKind::Type,
+                            format!("resume expects a coroutine, got: {}", self.stack[target]),
+                        )
+                    })?;
+                    self.stack.remove(target);
+                    self.resume(coroutine, argc)?;
+                }
+                OpCode::OpYield => {
+                    self.set_ip(ip + 1);
+                    let value = self.stack.pop().unwrap();
+                    self.suspend(base, value)?;
+                }
+                OpCode::OpInstance => {
+                    let struct_type = chunk.get_wide_constant(ip + 1).1.get_struct().unwrap();
+                    let slots = self
+                        .stack
+                        .split_off(self.stack.len() - struct_type.fields.len());
+                    let instance = Instance::new(struct_type, slots);
+                    self.stack
+                        .push(Value::Obj(Box::new(Object::Instance(instance))));
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpIsInstance => {
+                    let struct_type = chunk.get_wide_constant(ip + 1).1.get_struct().unwrap();
+                    let value = self.stack.pop().unwrap();
+                    let is_a = value
+                        .get_instance()
+                        .is_some_and(|instance| instance.is_a(&struct_type));
+                    self.stack.push(Value::Bool(is_a));
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpGetField => {
+                    let struct_type = chunk.get_wide_constant(ip + 1).1.get_struct().unwrap();
+                    let slot = chunk.get_index(ip + 3);
+                    let target = self.stack.pop().unwrap();
+                    let instance =
+                        expect_instance(&target, &struct_type, struct_type.getter(slot))?;
+                    let value = instance.slots.borrow()[slot].clone();
+                    self.stack.push(value);
+                    self.set_ip(ip + 5);
+                }
+                OpCode::OpSetField => {
+                    let struct_type = chunk.get_wide_constant(ip + 1).1.get_struct().unwrap();
+                    let slot = chunk.get_index(ip + 3);
+                    let value = self.stack.pop().unwrap();
+                    let target = self.stack.pop().unwrap();
+                    let instance =
+                        expect_instance(&target, &struct_type, struct_type.setter(slot))?;
+                    instance.slots.borrow_mut()[slot] = value.clone();
+                    self.stack.push(value);
+                    self.set_ip(ip + 5);
+                }
+                OpCode::OpIsType => {
+                    let (_, specializer) = chunk.get_wide_constant(ip + 1);
+                    let value = self.stack.pop().unwrap();
+                    let is_a = generic::matches(specializer, &value);
+                    self.stack.push(Value::Bool(is_a));
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpLength => {
+                    let value = self.stack.pop().unwrap();
+                    let length = match value.get_list().or(value.get_vector()) {
+                        Some(items) => items.len(),
+                        None => value.get_map().map_or(0, |map| map.len()),
+                    };
+                    self.stack.push(Value::Int(length as i64));
+                    self.set_ip(ip + 1);
+                }
+                OpCode::OpNth => {
+                    let index = chunk.get_index(ip + 1);
+                    let value = self.stack.pop().unwrap();
+                    let item = value
+                        .get_list()
+                        .or(value.get_vector())
+                        .and_then(|items| items.get(index))
+                        .cloned()
+                        .unwrap_or(Value::Nil);
+                    self.stack.push(item);
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpHasKey | OpCode::OpGetKey => {
+                    let (_, key) = chunk.get_wide_constant(ip + 1);
+                    let value = self.stack.pop().unwrap();
+                    let item = value.get_map().and_then(|map| map.get(key));
+                    self.stack.push(match opcode {
+                        OpCode::OpHasKey => Value::Bool(item.is_some()),
+                        _ => item.cloned().unwrap_or(Value::Nil),
+                    });
+                    self.set_ip(ip + 3);
+                }
+                OpCode::OpUnpack => {
+                    let count = chunk.get_index(ip + 1);
+                    let with_rest = chunk.get_constant_index(ip + 3) == 1;
+                    let value = self.stack.pop().unwrap();
+                    let (items, rest) = self.unpack(&value, count)?;
+                    self.stack.extend(items);
+                    if with_rest {
+                        self.stack.push(rest);
+                    }
+                    self.set_ip(ip + 4);
+                }
+                OpCode::OpSeq => {
+                    let value = self.stack.pop().unwrap();
+                    let seq = self.seq(&value)?;
+                    self.stack.push(Value::Obj(Box::new(Object::LazySeq(seq))));
+                    self.set_ip(ip + 1);
+                }
+                OpCode::OpNext => {
+                    // the sequence on the stack is replaced by the rest of it
+                    let slot = chunk.get_index(ip + 1);
+                    let seq = self.stack.pop().unwrap().get_lazy_seq().unwrap();
+                    match self.next_item(&seq)? {
+                        Some((item, rest)) => {
+                            self.frame().rebind(slot, item);
+                            self.stack.push(Value::Obj(Box::new(Object::LazySeq(rest))));
+                            self.set_ip(ip + 5);
+                        }
+                        None => self.set_ip(chunk.get_jump_target(ip + 3)),
+                    }
+                }
+                OpCode::OpNoMatch => {
+                    let value = self.stack.pop().unwrap();
+                    return Err(VMErr::RuntimeError(
+                        ErrorKind::Runtime,
+                        format!("No clause of match applies to {}", value.readable()),
+                    ));
+                }
+                OpCode::OpPrint => {
+                    let value = self.stack.last().unwrap().clone();
+                    println!("{:?}", self.realize(&value)?);
+                    self.set_ip(ip + 1);
+                }
             }
         }
     }
@@ -286,15 +1186,61 @@ impl Default for VirtualMachine {
     }
 }
 
+/// `(:key target default?)` looks `:key` up in a map, or the field `key` in
+/// a struct instance.
+fn lookup_keyword(keyword: &Rc<str>, target: &Value, default: Value) -> Result<Value, VMErr> {
+    if let Some(map) = target.get_map() {
+        let key = Value::Obj(Box::new(Object::Keyword(keyword.clone())));
+        return Ok(map.get(&key).cloned().unwrap_or(default));
+    }
+    if let Some(instance) = target.get_instance() {
+        let fields = &instance.struct_type.fields;
+        return Ok(match fields.iter().position(|field| field == keyword) {
+            Some(slot) => instance.slots.borrow()[slot].clone(),
+            None => default,
+        });
+    }
+    match target {
+        Value::Nil => Ok(default),
+        _ => Err(VMErr::RuntimeError(
+            ErrorKind::Type,
+            format!(":{} expects a map or struct, got: {}", keyword, target),
+        )),
+    }
+}
+
+/// Sorts the keyword arguments of a call into the slots of the function's
+/// `&key` parameters, leaving the missing ones nil.
+fn key_arguments(function: &Function, pairs: Vec<Value>) -> Result<Vec<Value>, VMErr> {
+    let mut values = vec![Value::Nil; function.keys.len()];
+    for pair in pairs.chunks(2) {
+        let slot = pair[0]
+            .get_keyword()
+            .and_then(|keyword| function.keys.iter().position(|key| key == keyword))
+            .ok_or_else(|| {
+                VMErr::RuntimeError(
+                    ErrorKind::Type,
+                    format!(
+                        "{} does not take the keyword argument {}",
+                        function.name, pair[0]
+                    ),
+                )
+            })?;
+        values[slot] = pair[1].clone();
+    }
+    Ok(values)
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_empty() {
         let mut vm = VirtualMachine::new(false);
-        let mut chunk = Chunk::new("test");
-        assert_eq!(vm.run(&mut chunk).is_err(), true);
+        let chunk = Chunk::new("test");
+        assert_eq!(vm.run(&chunk).is_err(), true);
     }
 
     #[test]
@@ -302,6 +1248,6 @@ mod tests {
         let mut vm = VirtualMachine::new(false);
         let mut chunk = Chunk::new("test");
         chunk.write_opcode(OpCode::OpRet, 1);
-        vm.run(&mut chunk).unwrap();
+        vm.run(&chunk).unwrap();
     }
 }
@@ -0,0 +1,101 @@
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+mod chars;
+mod collections;
+mod coroutines;
+mod errors;
+mod format;
+mod functional;
+mod generics;
+mod json;
+mod keywords;
+mod numeric;
+mod operators;
+mod seqs;
+mod strings;
+
+pub fn register(vm: &mut VirtualMachine) {
+    chars::register(vm);
+    collections::register(vm);
+    coroutines::register(vm);
+    errors::register(vm);
+    format::register(vm);
+    generics::register(vm);
+    json::register(vm);
+    keywords::register(vm);
+    numeric::register(vm);
+    operators::register(vm);
+    seqs::register(vm);
+    strings::register(vm);
+}
+
+/// Registers the natives of the prelude.
+pub fn register_prelude(vm: &mut VirtualMachine) {
+    functional::register(vm);
+    seqs::register_prelude(vm);
+}
+
+/// Checks that a variadic native received between `min` and `max` arguments.
+fn check_arity(name: &str, args: &[Value], min: usize, max: Option<usize>) -> Result<(), VMErr> {
+    if args.len() < min || max.is_some_and(|max| args.len() > max) {
+        let expected = match max {
+            Some(max) if max == min => format!("{}", min),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        return Err(VMErr::RuntimeError(
+            ErrorKind::Arity,
+            format!(
+                "{} expects {} arguments, got {}",
+                name,
+                expected,
+                args.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn type_error<T>(name: &str, expected: &str, value: &Value) -> Result<T, VMErr> {
+    Err(VMErr::RuntimeError(
+        ErrorKind::Type,
+        format!("{} expects {}, got: {}", name, expected, value),
+    ))
+}
+
+fn expect_str<'a>(name: &str, value: &'a Value) -> Result<&'a str, VMErr> {
+    match value.get_str() {
+        Some(s) => Ok(s),
+        None => type_error(name, "a string", value),
+    }
+}
+
+/// The text a value contributes to a string: strings and chars are inserted
+/// as they are, other values are printed.
+fn text(value: &Value) -> String {
+    match value.get_char() {
+        Some(c) => c.to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn expect_index(name: &str, value: &Value) -> Result<usize, VMErr> {
+    match value.get_int() {
+        Some(i) if i >= 0 => Ok(i as usize),
+        _ => type_error(name, "a non-negative integer index", value),
+    }
+}
+
+/// Wraps the items computed from `coll`: a list stays a list and every other
+/// collection becomes a vector.
+fn like(coll: &Value, items: Vec<Value>) -> Value {
+    Value::Obj(Box::new(if coll.get_list().is_some() {
+        Object::List(Rc::new(items))
+    } else {
+        Object::Vector(Rc::new(items))
+    }))
+}
@@ -0,0 +1,103 @@
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::vm::natives::{expect_str, type_error};
+use crate::vm::{VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("char?", Some(1), is_char);
+    vm.define_native("char->int", Some(1), char_to_int);
+    vm.define_native("int->char", Some(1), int_to_char);
+    vm.define_native("str-chars", Some(1), str_chars);
+    vm.define_native("char-alphabetic?", Some(1), char_alphabetic);
+    vm.define_native("char-numeric?", Some(1), char_numeric);
+    vm.define_native("char-whitespace?", Some(1), char_whitespace);
+    vm.define_native("char-upper-case?", Some(1), char_upper_case);
+    vm.define_native("char-lower-case?", Some(1), char_lower_case);
+    vm.define_native("char-upcase", Some(1), char_upcase);
+    vm.define_native("char-downcase", Some(1), char_downcase);
+}
+
+fn expect_char(name: &str, value: &Value) -> Result<char, VMErr> {
+    match value.get_char() {
+        Some(c) => Ok(c),
+        None => type_error(name, "a char", value),
+    }
+}
+
+fn is_char(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].is_char()))
+}
+
+fn char_to_int(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Int(expect_char("char->int", &args[0])? as i64))
+}
+
+fn int_to_char(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let c = args[0]
+        .get_int()
+        .and_then(|i| u32::try_from(i).ok())
+        .and_then(char::from_u32);
+    match c {
+        Some(c) => Ok(Value::Char(c)),
+        None => type_error("int->char", "a Unicode scalar value", &args[0]),
+    }
+}
+
+/// `(str-chars s)` returns a vector with the characters of `s`.
+fn str_chars(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let s = expect_str("str-chars", &args[0])?;
+    Ok(Value::Obj(Box::new(Object::Vector(Rc::new(
+        s.chars().map(Value::Char).collect(),
+    )))))
+}
+
+fn char_alphabetic(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(
+        expect_char("char-alphabetic?", &args[0])?.is_alphabetic(),
+    ))
+}
+
+fn char_numeric(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(
+        expect_char("char-numeric?", &args[0])?.is_numeric(),
+    ))
+}
+
+fn char_whitespace(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(
+        expect_char("char-whitespace?", &args[0])?.is_whitespace(),
+    ))
+}
+
+fn char_upper_case(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(
+        expect_char("char-upper-case?", &args[0])?.is_uppercase(),
+    ))
+}
+
+fn char_lower_case(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(
+        expect_char("char-lower-case?", &args[0])?.is_lowercase(),
+    ))
+}
+
+/// Characters whose upper case is more than one character, like `ß`, are
+/// returned unchanged.
+fn char_upcase(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let c = expect_char("char-upcase", &args[0])?;
+    Ok(Value::Char(single(c.to_uppercase()).unwrap_or(c)))
+}
+
+fn char_downcase(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let c = expect_char("char-downcase", &args[0])?;
+    Ok(Value::Char(single(c.to_lowercase()).unwrap_or(c)))
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
@@ -0,0 +1,220 @@
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::{sorted_entries, Value};
+use crate::vm::natives::{check_arity, expect_index, type_error};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("list", None, list);
+    vm.define_native("count", Some(1), count);
+    vm.define_native("get", None, get);
+    vm.define_native("nth", None, nth);
+    vm.define_native("assoc", None, assoc);
+    vm.define_native("dissoc", None, dissoc);
+    vm.define_native("keys", Some(1), keys);
+    vm.define_native("vals", Some(1), vals);
+    vm.define_native("push", Some(2), push);
+}
+
+fn list(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Obj(Box::new(Object::List(Rc::new(args.to_vec())))))
+}
+
+/// `(count coll)` is the number of items of a collection. Lazy sequences are
+/// realized to count them.
+fn count(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args[0].get_lazy_seq().is_some() {
+        let mut seq = vm.seq(&std::mem::take(&mut args[0]))?;
+        let mut count = 0;
+        while let Some((_, rest)) = vm.next_item(&seq)? {
+            count += 1;
+            seq = rest;
+        }
+        return Ok(Value::Int(count));
+    }
+    let count = match &args[0] {
+        Value::Nil => 0,
+        Value::Obj(obj) => match &**obj {
+            Object::Str(s) => s.chars().count(),
+            Object::List(l) | Object::Vector(l) => l.len(),
+            Object::Map(m) => m.len(),
+            _ => return type_error("count", "a collection", &args[0]),
+        },
+        _ => return type_error("count", "a collection", &args[0]),
+    };
+    Ok(Value::Int(count as i64))
+}
+
+/// `(get coll key default?)` looks up a map key or a sequence index, falling
+/// back to `default` (or nil) when it is missing.
+fn get(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("get", args, 2, Some(3))?;
+    let default = args.get(2).cloned().unwrap_or(Value::Nil);
+    let found = match &args[0] {
+        Value::Nil => None,
+        Value::Obj(obj) => match &**obj {
+            Object::Map(m) => m.get(&args[1]).cloned(),
+            Object::List(l) | Object::Vector(l) => match args[1].get_int() {
+                Some(i) if i >= 0 => l.get(i as usize).cloned(),
+                _ => None,
+            },
+            _ => return type_error("get", "a map or sequence", &args[0]),
+        },
+        _ => return type_error("get", "a map or sequence", &args[0]),
+    };
+    Ok(found.unwrap_or(default))
+}
+
+/// `(nth seq index default?)` is like `get` for sequences, but indexing past
+/// the end without a default is an error.
+fn nth(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("nth", args, 2, Some(3))?;
+    let seq = match args[0].get_vector().or_else(|| args[0].get_list()) {
+        Some(seq) => seq,
+        None => return type_error("nth", "a sequence", &args[0]),
+    };
+    let index = expect_index("nth", &args[1])?;
+    match (seq.get(index), args.get(2)) {
+        (Some(value), _) => Ok(value.clone()),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(VMErr::RuntimeError(
+            ErrorKind::Runtime,
+            format!(
+                "nth: index {} out of bounds for length {}",
+                index,
+                seq.len()
+            ),
+        )),
+    }
+}
+
+/// `(assoc coll key value ...)` returns a map with the entries added, or a
+/// vector with the indices replaced. The collection is updated in place when
+/// nothing else holds it, such as a variable, and copied otherwise.
+fn assoc(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("assoc", args, 3, None)?;
+    if args.len() % 2 == 0 {
+        return Err(VMErr::RuntimeError(
+            ErrorKind::Runtime,
+            "assoc expects an even number of keys and values".to_string(),
+        ));
+    }
+
+    let (coll, pairs) = args.split_first_mut().unwrap();
+    let pairs = pairs.chunks_mut(2);
+    match coll {
+        Value::Nil => {
+            return Ok(Value::Obj(Box::new(Object::Map(Rc::new(
+                pairs
+                    .map(|kv| (std::mem::take(&mut kv[0]), std::mem::take(&mut kv[1])))
+                    .collect(),
+            )))))
+        }
+        Value::Obj(obj) => match &mut **obj {
+            Object::Map(map) => {
+                let entries = Rc::make_mut(map);
+                for kv in pairs {
+                    entries.insert(std::mem::take(&mut kv[0]), std::mem::take(&mut kv[1]));
+                }
+            }
+            Object::Vector(items) => {
+                let vector = Rc::make_mut(items);
+                for kv in pairs {
+                    let index = expect_index("assoc", &kv[0])?;
+                    let value = std::mem::take(&mut kv[1]);
+                    match index.cmp(&vector.len()) {
+                        std::cmp::Ordering::Less => vector[index] = value,
+                        std::cmp::Ordering::Equal => vector.push(value),
+                        std::cmp::Ordering::Greater => {
+                            return Err(VMErr::RuntimeError(
+                                ErrorKind::Runtime,
+                                format!(
+                                    "assoc: index {} out of bounds for length {}",
+                                    index,
+                                    vector.len()
+                                ),
+                            ))
+                        }
+                    }
+                }
+            }
+            _ => return type_error("assoc", "a map or vector", coll),
+        },
+        _ => return type_error("assoc", "a map or vector", coll),
+    }
+    Ok(std::mem::take(coll))
+}
+
+/// `(dissoc map key ...)` returns a map without the keys, updated in place
+/// like `assoc` does.
+fn dissoc(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("dissoc", args, 1, None)?;
+    let (coll, keys) = args.split_first_mut().unwrap();
+    match coll {
+        Value::Nil => return Ok(Value::Nil),
+        Value::Obj(obj) => match &mut **obj {
+            Object::Map(map) => {
+                let entries = Rc::make_mut(map);
+                for key in keys.iter() {
+                    entries.remove(key);
+                }
+            }
+            _ => return type_error("dissoc", "a map", coll),
+        },
+        _ => return type_error("dissoc", "a map", coll),
+    }
+    Ok(std::mem::take(coll))
+}
+
+fn keys(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_map() {
+        Some(m) => Ok(Value::Obj(Box::new(Object::List(Rc::new(
+            sorted_entries(m).map(|(k, _)| k.clone()).collect(),
+        ))))),
+        None => type_error("keys", "a map", &args[0]),
+    }
+}
+
+fn vals(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_map() {
+        Some(m) => Ok(Value::Obj(Box::new(Object::List(Rc::new(
+            sorted_entries(m).map(|(_, v)| v.clone()).collect(),
+        ))))),
+        None => type_error("vals", "a map", &args[0]),
+    }
+}
+
+/// `(push seq value)` returns a vector or list with `value` appended, updated
+/// in place like `assoc` does.
+fn push(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let value = std::mem::take(&mut args[1]);
+    match &mut args[0] {
+        Value::Obj(obj) => match &mut **obj {
+            Object::Vector(items) | Object::List(items) => Rc::make_mut(items).push(value),
+            _ => return type_error("push", "a vector or list", &args[0]),
+        },
+        _ => return type_error("push", "a vector or list", &args[0]),
+    }
+    Ok(std::mem::take(&mut args[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_updates_unshared_vectors_in_place() {
+        let mut vm = VirtualMachine::new(false);
+        let items = Rc::new(vec![Value::Int(1)]);
+        let address = Rc::as_ptr(&items);
+        let vector = Value::Obj(Box::new(Object::Vector(items)));
+        let pushed = push(&mut vm, &mut [vector, Value::Int(2)]).unwrap();
+        assert!(std::ptr::eq(pushed.get_vector().unwrap(), address));
+
+        let shared = pushed.clone();
+        let copied = push(&mut vm, &mut [shared, Value::Int(3)]).unwrap();
+        assert_eq!(pushed.get_vector().unwrap().len(), 2);
+        assert_eq!(copied.get_vector().unwrap().len(), 3);
+    }
+}
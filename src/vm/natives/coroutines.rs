@@ -0,0 +1,40 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::chunk::object::{intern, Object};
+use crate::chunk::value::Value;
+use crate::vm::natives::type_error;
+use crate::vm::{Coroutine, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("coroutine", Some(1), coroutine);
+    vm.define_native("coroutine?", Some(1), is_coroutine);
+    vm.define_native("coroutine-status", Some(1), coroutine_status);
+}
+
+/// `(coroutine f)` wraps `f` in a suspended coroutine, the first `resume`
+/// calls it.
+fn coroutine(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args[0].get_closure().is_none() && args[0].get_native().is_none() {
+        return type_error("coroutine", "a function", &args[0]);
+    }
+    let coroutine = Coroutine::new(args[0].clone());
+    Ok(Value::Obj(Box::new(Object::Coroutine(Rc::new(
+        RefCell::new(coroutine),
+    )))))
+}
+
+fn is_coroutine(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_coroutine().is_some()))
+}
+
+/// `:suspended`, `:running` or `:dead`.
+fn coroutine_status(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_coroutine() {
+        Some(coroutine) => {
+            let status = coroutine.borrow().status().name();
+            Ok(Value::Obj(Box::new(Object::Keyword(intern(status)))))
+        }
+        None => type_error("coroutine-status", "a coroutine", &args[0]),
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::chunk::object::{ErrorObject, Object};
+use crate::chunk::value::Value;
+use crate::vm::natives::{check_arity, expect_str, text, type_error};
+use crate::vm::{VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("error", None, error);
+    vm.define_native("error?", Some(1), is_error);
+    vm.define_native("err?", Some(1), is_error);
+    vm.define_native("ok?", Some(1), is_ok);
+    vm.define_native("error-message", Some(1), error_message);
+    vm.define_native("error-kind", Some(1), error_kind);
+    vm.define_native("error-payload", Some(1), error_payload);
+    vm.define_native("assert", Some(2), assert);
+    vm.define_native("assert-eq", Some(3), assert_eq);
+}
+
+/// `(error kind message payload?)` builds an error value without raising
+/// it, so that it can be returned as data or passed to `throw`. The kind is a
+/// keyword such as `:not-found`, or a string naming one.
+fn error(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("error", args, 2, Some(3))?;
+    let kind: &str = match (args[0].get_keyword(), args[0].get_str()) {
+        (Some(kind), _) => kind,
+        (None, Some(kind)) => kind,
+        _ => return type_error("error", "a keyword or string kind", &args[0]),
+    };
+    let message = expect_str("error", &args[1])?;
+    let payload = match args.get(2) {
+        Some(payload) => match payload.get_map() {
+            Some(map) => map.clone(),
+            None => return type_error("error", "a map payload", payload),
+        },
+        None => HashMap::new(),
+    };
+    let error = ErrorObject::new(kind, message, payload);
+    Ok(Value::Obj(Box::new(Object::Error(error))))
+}
+
+fn is_error(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_error().is_some()))
+}
+
+fn is_ok(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_error().is_none()))
+}
+
+/// The message of an error, or the printed value for anything else that was
+/// thrown.
+fn error_message(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let message = match args[0].get_error() {
+        Some(error) => error.message.clone(),
+        None => text(&args[0]).into(),
+    };
+    Ok(Value::Obj(Box::new(Object::Str(message))))
+}
+
+/// The kind of an error as a keyword, nil for values that are not errors.
+fn error_kind(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(match args[0].get_error() {
+        Some(error) => Value::Obj(Box::new(Object::Keyword(error.kind.clone()))),
+        None => Value::Nil,
+    })
+}
+
+fn error_payload(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_error() {
+        Some(error) => Ok(error.payload.clone()),
+        None => type_error("error-payload", "an error", &args[0]),
+    }
+}
+
+/// Backs the `assert` form, the compiler passes the source of the form after
+/// the asserted value.
+fn assert(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0] {
+        Value::Bool(true) => Ok(Value::Nil),
+        _ => Err(assertion_failed(
+            format!("Assertion failed: {}", text(&args[1])),
+            HashMap::from([(Value::from("value"), args[0].clone())]),
+        )),
+    }
+}
+
+/// Backs the `assert-eq` form, the compiler passes the source of the whole
+/// form after the two values.
+fn assert_eq(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args[0] == args[1] {
+        return Ok(Value::Nil);
+    }
+    Err(assertion_failed(
+        format!(
+            "Assertion failed: {}, left: {}, right: {}",
+            text(&args[2]),
+            args[0].readable(),
+            args[1].readable()
+        ),
+        HashMap::from([
+            (Value::from("left"), args[0].clone()),
+            (Value::from("right"), args[1].clone()),
+        ]),
+    ))
+}
+
+fn assertion_failed(message: String, payload: HashMap<Value, Value>) -> VMErr {
+    let error = ErrorObject::new("assertion-failed", &message, payload);
+    VMErr::Exception(Value::Obj(Box::new(Object::Error(error))))
+}
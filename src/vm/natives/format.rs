@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::chunk::object::{intern, Object};
+use crate::chunk::value::Value;
+use crate::vm::natives::{check_arity, expect_str, text};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("format", None, format);
+    vm.define_native("display", Some(1), display);
+    vm.define_native("write", Some(1), write);
+    vm.define_native("newline", Some(0), newline);
+}
+
+/// `(format template arg ...)` fills the placeholders of `template`:
+///
+/// - `{}` takes the next argument, `{1}` the argument at that position and
+///   `{name}` the value of `"name"` or `:name` in a map passed as the last
+///   argument.
+/// - A spec after a colon sets fill, alignment, width and precision as in
+///   Rust: `{:>8}`, `{:*^10}`, `{:08.3}`. `{:?}` prints readably.
+/// - `~a` prints the next argument for display, `~s` prints it readably (so
+///   strings are quoted), `~%` is a newline and `~~` a tilde.
+/// - `{{` and `}}` are literal braces.
+fn format(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("format", args, 1, None)?;
+    let template = expect_str("format", &args[0])?;
+    let args = vm.realize_all(args)?;
+    let formatted = Formatter::new(&args[1..])
+        .format(template)
+        .map_err(|err| VMErr::RuntimeError(ErrorKind::Runtime, format!("format: {}", err)))?;
+    Ok(Value::Obj(Box::new(Object::Str(formatted.into()))))
+}
+
+fn display(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    emit(&text(&vm.realize(&args[0])?))
+}
+
+fn write(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    emit(&vm.realize(&args[0])?.readable().to_string())
+}
+
+fn newline(_vm: &mut VirtualMachine, _args: &mut [Value]) -> Result<Value, VMErr> {
+    emit("\n")
+}
+
+fn emit(s: &str) -> Result<Value, VMErr> {
+    let mut stdout = std::io::stdout();
+    stdout
+        .write_all(s.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|err| VMErr::RuntimeError(ErrorKind::Runtime, err.to_string()))?;
+    Ok(Value::Nil)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    readable: bool,
+}
+
+struct Formatter<'a> {
+    args: &'a [Value],
+    named: Option<&'a HashMap<Value, Value>>,
+    next: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(args: &'a [Value]) -> Formatter<'a> {
+        Formatter {
+            args,
+            named: args.last().and_then(Value::get_map),
+            next: 0,
+        }
+    }
+
+    fn format(&mut self, template: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    out.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    out.push('}');
+                }
+                '{' => {
+                    let placeholder = read_placeholder(&mut chars)?;
+                    out.push_str(&self.placeholder(&placeholder)?);
+                }
+                '}' => return Err("unmatched } in template".to_string()),
+                '~' => match chars.next() {
+                    Some('a') => out.push_str(&text(self.positional()?)),
+                    Some('s') => out.push_str(&self.positional()?.readable().to_string()),
+                    Some('%') => out.push('\n'),
+                    Some('~') => out.push('~'),
+                    Some(c) => return Err(format!("unknown directive ~{}", c)),
+                    None => return Err("template ends with ~".to_string()),
+                },
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    }
+
+    fn positional(&mut self) -> Result<&'a Value, String> {
+        let value = self
+            .args
+            .get(self.next)
+            .ok_or_else(|| format!("template needs more than {} argument(s)", self.args.len()))?;
+        self.next += 1;
+        Ok(value)
+    }
+
+    fn argument(&mut self, name: &str) -> Result<&'a Value, String> {
+        if name.is_empty() {
+            self.positional()
+        } else if let Ok(index) = name.parse::<usize>() {
+            self.args
+                .get(index)
+                .ok_or_else(|| format!("no argument at position {}", index))
+        } else {
+            let keyword = Value::Obj(Box::new(Object::Keyword(intern(name))));
+            self.named
+                .and_then(|named| {
+                    named
+                        .get(&Value::from(name))
+                        .or_else(|| named.get(&keyword))
+                })
+                .ok_or_else(|| format!("no argument named {}", name))
+        }
+    }
+
+    fn placeholder(&mut self, placeholder: &str) -> Result<String, String> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name, parse_spec(spec)?),
+            None => (placeholder, Spec::default()),
+        };
+        let value = self.argument(name.trim())?;
+        Ok(apply(value, &spec))
+    }
+}
+
+fn read_placeholder(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut placeholder = String::new();
+    for c in chars.by_ref() {
+        if c == '}' {
+            return Ok(placeholder);
+        }
+        placeholder.push(c);
+    }
+    Err("unterminated { in template".to_string())
+}
+
+fn parse_align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+/// The largest width or precision a placeholder may ask for.
+const MAX_WIDTH: usize = u16::MAX as usize;
+
+/// Parses `[[fill]align][0][width][.precision][?]`.
+fn parse_spec(spec: &str) -> Result<Spec, String> {
+    let mut result = Spec::default();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut pos = 0;
+
+    if let Some(align) = chars.get(1).copied().and_then(parse_align) {
+        result.fill = Some(chars[0]);
+        result.align = Some(align);
+        pos = 2;
+    } else if let Some(align) = chars.first().copied().and_then(parse_align) {
+        result.align = Some(align);
+        pos = 1;
+    }
+
+    if chars.get(pos) == Some(&'0') {
+        result.zero = true;
+        pos += 1;
+    }
+
+    // bounded so that a template can neither overflow `format!` nor pad a
+    // string into an allocation failure
+    let digits = |pos: &mut usize, what: &str| {
+        let start = *pos;
+        while chars.get(*pos).is_some_and(char::is_ascii_digit) {
+            *pos += 1;
+        }
+        if start == *pos {
+            return Ok(None);
+        }
+        match chars[start..*pos].iter().collect::<String>().parse() {
+            Ok(n) if n <= MAX_WIDTH => Ok(Some(n)),
+            _ => Err(format!("{} too large in {{:{}}}", what, spec)),
+        }
+    };
+
+    result.width = digits(&mut pos, "width")?;
+    if chars.get(pos) == Some(&'.') {
+        pos += 1;
+        result.precision = Some(
+            digits(&mut pos, "precision")?
+                .ok_or_else(|| format!("missing precision in {{:{}}}", spec))?,
+        );
+    }
+    if chars.get(pos) == Some(&'?') {
+        result.readable = true;
+        pos += 1;
+    }
+
+    if pos != chars.len() {
+        return Err(format!("invalid format spec {{:{}}}", spec));
+    }
+    Ok(result)
+}
+
+fn apply(value: &Value, spec: &Spec) -> String {
+    let s = match spec.precision {
+        Some(precision) if value.is_number() => {
+            format!("{:.*}", precision, value.get_number().unwrap())
+        }
+        Some(precision) => text(value).chars().take(precision).collect(),
+        None if spec.readable => value.readable().to_string(),
+        None => text(value),
+    };
+
+    let len = s.chars().count();
+    let width = match spec.width {
+        Some(width) if width > len => width,
+        _ => return s,
+    };
+
+    if spec.zero && spec.align.is_none() && value.is_number() {
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", s.as_str()),
+        };
+        return format!("{}{}{}", sign, "0".repeat(width - len), digits);
+    }
+
+    let fill = spec.fill.unwrap_or(' ');
+    let padding = width - len;
+    let (left, right) = match spec.align {
+        Some(Align::Left) => (0, padding),
+        Some(Align::Right) => (padding, 0),
+        Some(Align::Center) => (padding / 2, padding - padding / 2),
+        None if value.is_number() => (padding, 0),
+        None => (0, padding),
+    };
+    let pad = |n: usize| std::iter::repeat(fill).take(n).collect::<String>();
+    format!("{}{}{}", pad(left), s, pad(right))
+}
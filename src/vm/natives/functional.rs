@@ -0,0 +1,120 @@
+use std::cmp::Ordering;
+
+use crate::chunk::value::Value;
+use crate::vm::natives::{check_arity, like};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("apply", None, apply);
+    vm.define_native("fold-left", Some(3), fold_left);
+    vm.define_native("fold-right", Some(3), fold_right);
+    vm.define_native("sort", Some(1), sort);
+    vm.define_native("sort-by", Some(2), sort_by);
+}
+
+/// `(apply f a b args)` calls `f` with `a`, `b` and the items of `args`.
+fn apply(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("apply", args, 2, None)?;
+    let (f, args) = args.split_first_mut().unwrap();
+    let (last, leading) = args.split_last_mut().unwrap();
+    let mut spread = leading.to_vec();
+    spread.extend(vm.collect_seq(std::mem::take(last))?);
+    vm.call(f, &spread)
+}
+
+/// `(fold-left f init coll)` is `(f (f (f init a) b) c)`.
+fn fold_left(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let mut acc = args[1].clone();
+    for item in vm.collect_seq(std::mem::take(&mut args[2]))? {
+        acc = vm.call(&args[0], &[acc, item])?;
+    }
+    Ok(acc)
+}
+
+/// `(fold-right f init coll)` is `(f a (f b (f c init)))`.
+fn fold_right(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let mut acc = args[1].clone();
+    let items = vm.collect_seq(std::mem::take(&mut args[2]))?;
+    for item in items.into_iter().rev() {
+        acc = vm.call(&args[0], &[item, acc])?;
+    }
+    Ok(acc)
+}
+
+/// Sorts in ascending order, values that cannot be compared are an error.
+fn sort(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let items = vm.collect_seq(args[0].clone())?;
+    let sorted = merge_sort(items, &mut |x, y| {
+        let order = x
+            .compare(y)
+            .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?;
+        Ok(order == Some(Ordering::Less))
+    })?;
+    Ok(like(&args[0], sorted))
+}
+
+/// `(sort-by less? coll)` sorts with a comparator that tells whether its
+/// first argument goes before its second.
+fn sort_by(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let items = vm.collect_seq(args[1].clone())?;
+    let less = &args[0];
+    let sorted = merge_sort(items, &mut |x, y| {
+        let result = vm.call(less, &[x.clone(), y.clone()])?;
+        result.get_bool().ok_or_else(|| {
+            VMErr::RuntimeError(
+                ErrorKind::Type,
+                format!("Cannot use {} as a boolean", result),
+            )
+        })
+    })?;
+    Ok(like(&args[1], sorted))
+}
+
+/// A stable sort that stops at the first error of the comparator, and that
+/// does not mind comparators that are not a total order.
+fn merge_sort(
+    mut items: Vec<Value>,
+    less: &mut dyn FnMut(&Value, &Value) -> Result<bool, VMErr>,
+) -> Result<Vec<Value>, VMErr> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(items, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+    let mut merged = Vec::new();
+    while let (Some(x), Some(y)) = (left.peek(), right.peek()) {
+        if less(y, x)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_sort_is_stable() {
+        let pairs = [(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd')]
+            .iter()
+            .map(|(n, c)| (Value::Int(*n), Value::Char(*c)))
+            .collect::<Vec<_>>();
+        let items = (0..pairs.len()).map(|i| Value::Int(i as i64)).collect();
+        let sorted = merge_sort(items, &mut |x, y| {
+            let key = |v: &Value| pairs[v.get_int().unwrap() as usize].0.clone();
+            Ok(key(x).compare(&key(y)).unwrap() == Some(Ordering::Less))
+        })
+        .unwrap();
+        let order = sorted
+            .iter()
+            .map(|v| pairs[v.get_int().unwrap() as usize].1.get_char().unwrap())
+            .collect::<String>();
+        assert_eq!(order, "bdac");
+    }
+}
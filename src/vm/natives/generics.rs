@@ -0,0 +1,58 @@
+use std::rc::Rc;
+
+use crate::chunk::generic::{is_specializer, Generic, Method};
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::vm::natives::{expect_index, expect_str, type_error};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("make-generic", Some(2), make_generic);
+    vm.define_native("add-method", Some(3), add_method);
+    vm.define_native("generic?", Some(1), is_generic);
+}
+
+/// Backs `defgeneric`: `(make-generic "area" 1)` is a generic function of
+/// one argument without methods.
+fn make_generic(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let name = expect_str("make-generic", &args[0])?;
+    let arity = expect_index("make-generic", &args[1])?;
+    let generic = Generic::new(name, arity);
+    Ok(Value::Obj(Box::new(Object::Generic(Rc::new(generic)))))
+}
+
+/// Backs `defmethod`: `(add-method area [circle] f)` adds the method `f` for
+/// the types of its arguments. `f` takes the `next-method` first.
+fn add_method(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let generic = match args[0].get_generic() {
+        Some(generic) => generic,
+        None => return type_error("add-method", "a generic function", &args[0]),
+    };
+    let specializers = match args[1].get_vector() {
+        Some(specializers) => specializers.clone(),
+        None => return type_error("add-method", "a vector of types", &args[1]),
+    };
+    if let Some(other) = specializers.iter().find(|s| !is_specializer(s)) {
+        return type_error("add-method", "a type", other);
+    }
+    if specializers.len() != generic.arity {
+        return Err(VMErr::RuntimeError(
+            ErrorKind::Arity,
+            format!(
+                "{} takes {} arguments, the method takes {}",
+                generic.name,
+                generic.arity,
+                specializers.len()
+            ),
+        ));
+    }
+    generic.add(Method {
+        specializers,
+        function: args[2].clone(),
+    });
+    Ok(args[0].clone())
+}
+
+fn is_generic(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_generic().is_some()))
+}
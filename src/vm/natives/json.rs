@@ -0,0 +1,53 @@
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::vm::natives::expect_str;
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("json-parse", Some(1), json_parse);
+    vm.define_native("json-stringify", Some(1), json_stringify);
+}
+
+fn json_parse(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let source = expect_str("json-parse", &args[0])?;
+    serde_json::from_str(source)
+        .map_err(|err| VMErr::RuntimeError(ErrorKind::Runtime, format!("json-parse: {}", err)))
+}
+
+/// `(json-stringify value)` writes a value as JSON, realizing the lazy
+/// sequences in it as arrays. JSON has no NaN or infinities, so a value
+/// containing one is an error rather than written as `null`.
+fn json_stringify(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let value = vm.realize(&args[0])?;
+    if let Some(number) = non_finite(&value) {
+        return Err(VMErr::RuntimeError(
+            ErrorKind::Runtime,
+            format!("json-stringify: {} has no JSON representation", number),
+        ));
+    }
+    let json = serde_json::to_string(&value).map_err(|err| {
+        VMErr::RuntimeError(ErrorKind::Runtime, format!("json-stringify: {}", err))
+    })?;
+    Ok(Value::Obj(Box::new(Object::Str(json.into()))))
+}
+
+/// The first NaN or infinite number in a value, also in the collections
+/// nested in it.
+fn non_finite(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Float(f) if !f.is_finite() => Some(value),
+        Value::Obj(obj) => match &**obj {
+            // rationals are written as floats, which can overflow
+            Object::Ratio(_) => value
+                .get_number()
+                .filter(|number| !number.is_finite())
+                .map(|_| value),
+            Object::List(items) | Object::Vector(items) => items.iter().find_map(non_finite),
+            Object::Map(map) => map
+                .iter()
+                .find_map(|(key, value)| non_finite(key).or_else(|| non_finite(value))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,31 @@
+use crate::chunk::object::{intern, Object};
+use crate::chunk::value::Value;
+use crate::vm::natives::{expect_str, type_error};
+use crate::vm::{VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("keyword", Some(1), keyword);
+    vm.define_native("keyword?", Some(1), is_keyword);
+    vm.define_native("name", Some(1), name);
+}
+
+/// `(keyword "port")` is `:port`.
+fn keyword(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args[0].get_keyword().is_some() {
+        return Ok(args[0].clone());
+    }
+    let name = expect_str("keyword", &args[0])?;
+    Ok(Value::Obj(Box::new(Object::Keyword(intern(name)))))
+}
+
+fn is_keyword(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_keyword().is_some()))
+}
+
+/// `(name :port)` is `"port"`.
+fn name(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_keyword() {
+        Some(keyword) => Ok(Value::Obj(Box::new(Object::Str(keyword.clone())))),
+        None => type_error("name", "a keyword", &args[0]),
+    }
+}
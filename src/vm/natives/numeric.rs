@@ -0,0 +1,30 @@
+use crate::chunk::value::Value;
+use crate::vm::natives::type_error;
+use crate::vm::{VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("numerator", Some(1), numerator);
+    vm.define_native("denominator", Some(1), denominator);
+    vm.define_native("exact->inexact", Some(1), exact_to_inexact);
+}
+
+fn numerator(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_ratio() {
+        Some(r) => Ok(Value::from_bigint(r.numer().clone())),
+        None => type_error("numerator", "an exact number", &args[0]),
+    }
+}
+
+fn denominator(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_ratio() {
+        Some(r) => Ok(Value::from_bigint(r.denom().clone())),
+        None => type_error("denominator", "an exact number", &args[0]),
+    }
+}
+
+fn exact_to_inexact(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    match args[0].get_number() {
+        Some(f) => Ok(Value::Float(f)),
+        None => type_error("exact->inexact", "a number", &args[0]),
+    }
+}
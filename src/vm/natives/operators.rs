@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+
+use crate::chunk::value::Value;
+use crate::vm::natives::check_arity;
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+/// The operators as functions, so that they can be passed around as in
+/// `(reduce + 0 xs)` or `(apply + xs)`. In call position the compiler emits
+/// their opcodes instead.
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("+", None, add);
+    vm.define_native("-", None, sub);
+    vm.define_native("*", None, mul);
+    vm.define_native("/", None, div);
+    vm.define_native("quot", Some(2), quot);
+    vm.define_native("rem", Some(2), rem);
+    vm.define_native("mod", Some(2), modulo);
+    vm.define_native("=", None, eq);
+    vm.define_native("!=", Some(2), ne);
+    vm.define_native("<", None, lt);
+    vm.define_native("<=", None, le);
+    vm.define_native(">", None, gt);
+    vm.define_native(">=", None, ge);
+    vm.define_native("not", Some(1), not);
+    vm.define_native("and", Some(2), and);
+    vm.define_native("nand", Some(2), nand);
+    vm.define_native("or", Some(2), or);
+    vm.define_native("nor", Some(2), nor);
+    vm.define_native("xor", Some(2), xor);
+    vm.define_native("xnor", Some(2), xnor);
+}
+
+type Op = fn(&Value, &Value) -> Result<Value, String>;
+
+fn apply(op: Op, x: &Value, y: &Value) -> Result<Value, VMErr> {
+    op(x, y).map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))
+}
+
+/// Like `apply`, but an exact zero divisor is a `division-by-zero` error.
+fn divide(op: Op, x: &Value, y: &Value) -> Result<Value, VMErr> {
+    op(x, y).map_err(|message| {
+        let kind = if x.is_exact_division_by_zero(y) {
+            ErrorKind::DivisionByZero
+        } else {
+            ErrorKind::Type
+        };
+        VMErr::RuntimeError(kind, message)
+    })
+}
+
+/// Folds the arguments from the left, `(- 10 2 3)` is `(- (- 10 2) 3)`. A
+/// single argument is combined with `unit` first when there is one, so that
+/// `(- x)` negates and `(/ x)` inverts.
+fn fold(
+    name: &str,
+    args: &[Value],
+    unit: Option<Value>,
+    apply: fn(Op, &Value, &Value) -> Result<Value, VMErr>,
+    op: Op,
+) -> Result<Value, VMErr> {
+    check_arity(name, args, 1, None)?;
+    match (args, unit) {
+        ([x], Some(unit)) => apply(op, &unit, x),
+        ([first, rest @ ..], _) => rest
+            .iter()
+            .try_fold(first.clone(), |acc, y| apply(op, &acc, y)),
+        ([], _) => unreachable!(),
+    }
+}
+
+fn add(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args.is_empty() {
+        return Ok(Value::Int(0));
+    }
+    fold("+", args, None, apply, Value::checked_add)
+}
+
+fn sub(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    fold("-", args, Some(Value::Int(0)), apply, Value::checked_sub)
+}
+
+fn mul(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if args.is_empty() {
+        return Ok(Value::Int(1));
+    }
+    fold("*", args, None, apply, Value::checked_mul)
+}
+
+fn div(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    fold("/", args, Some(Value::Int(1)), divide, Value::checked_div)
+}
+
+fn quot(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    divide(Value::checked_quot, &args[0], &args[1])
+}
+
+fn rem(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    divide(Value::checked_rem, &args[0], &args[1])
+}
+
+fn modulo(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    divide(Value::checked_mod, &args[0], &args[1])
+}
+
+/// Whether every pair of neighbouring arguments passes `test`, so that
+/// `(< 1 2 3)` checks that the arguments increase.
+fn chain(
+    name: &str,
+    args: &[Value],
+    test: impl Fn(&Value, &Value) -> Result<bool, String>,
+) -> Result<Value, VMErr> {
+    check_arity(name, args, 1, None)?;
+    for pair in args.windows(2) {
+        let passed = test(&pair[0], &pair[1])
+            .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?;
+        if !passed {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+fn compare(x: &Value, y: &Value, accept: fn(Ordering) -> bool) -> Result<bool, String> {
+    Ok(x.compare(y)?.is_some_and(accept))
+}
+
+fn eq(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    chain("=", args, |x, y| Ok(x == y))
+}
+
+fn ne(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0] != args[1]))
+}
+
+fn lt(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    chain("<", args, |x, y| compare(x, y, Ordering::is_lt))
+}
+
+fn le(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    chain("<=", args, |x, y| compare(x, y, Ordering::is_le))
+}
+
+fn gt(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    chain(">", args, |x, y| compare(x, y, Ordering::is_gt))
+}
+
+fn ge(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    chain(">=", args, |x, y| compare(x, y, Ordering::is_ge))
+}
+
+fn not(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    args[0]
+        .checked_not()
+        .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))
+}
+
+fn and(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(Value::checked_and, &args[0], &args[1])
+}
+
+fn nand(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(|x, y| x.checked_and(y)?.checked_not(), &args[0], &args[1])
+}
+
+fn or(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(Value::checked_or, &args[0], &args[1])
+}
+
+fn nor(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(|x, y| x.checked_or(y)?.checked_not(), &args[0], &args[1])
+}
+
+fn xor(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(Value::checked_xor, &args[0], &args[1])
+}
+
+fn xnor(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    apply(|x, y| x.checked_xor(y)?.checked_not(), &args[0], &args[1])
+}
@@ -0,0 +1,179 @@
+use std::rc::Rc;
+
+use crate::chunk::lazy::{LazySeq, Step};
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::vm::natives::{check_arity, expect_index, like, type_error};
+use crate::vm::seq::lazy;
+use crate::vm::{VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("lazy-seq", Some(1), lazy_seq);
+    vm.define_native("iterate", Some(2), iterate);
+    vm.define_native("repeat", None, repeat);
+    vm.define_native("take", Some(2), take);
+    vm.define_native("drop", Some(2), drop);
+    vm.define_native("cons", Some(2), cons);
+    vm.define_native("first", Some(1), first);
+    vm.define_native("rest", Some(1), rest);
+    vm.define_native("empty?", Some(1), is_empty);
+    vm.define_native("lazy-seq?", Some(1), is_lazy_seq);
+    vm.define_native("vec", Some(1), vec);
+    vm.define_native("for-each", Some(2), for_each);
+}
+
+/// Registers the sequence functions of the prelude.
+pub fn register_prelude(vm: &mut VirtualMachine) {
+    vm.define_native("range", None, range);
+    vm.define_native("map", Some(2), map);
+    vm.define_native("filter", Some(2), filter);
+    vm.define_native("reduce", None, reduce);
+}
+
+fn expect_number<'a>(name: &str, value: &'a Value) -> Result<&'a Value, VMErr> {
+    if value.is_number() {
+        Ok(value)
+    } else {
+        type_error(name, "a number", value)
+    }
+}
+
+/// Backs the `lazy-seq` form, which passes its body as a function.
+fn lazy_seq(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(lazy(Step::Thunk(args[0].clone())))
+}
+
+/// `(range)` counts up from 0 forever, `(range end)`, `(range start end)`
+/// and `(range start end step)` stop before `end`.
+fn range(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("range", args, 0, Some(3))?;
+    for arg in args.iter() {
+        expect_number("range", arg)?;
+    }
+    let (start, end) = match args {
+        [] => (Value::Int(0), None),
+        [end] => (Value::Int(0), Some(end.clone())),
+        [start, end, ..] => (start.clone(), Some(end.clone())),
+    };
+    let step = args.get(2).cloned().unwrap_or(Value::Int(1));
+    Ok(lazy(Step::Range { start, end, step }))
+}
+
+/// `(iterate f x)` is the infinite sequence `x`, `(f x)`, `(f (f x))`...
+fn iterate(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(lazy(Step::Iterate {
+        f: args[0].clone(),
+        x: args[1].clone(),
+        applied: false,
+    }))
+}
+
+/// `(repeat x)` repeats `x` forever, `(repeat n x)` `n` times.
+fn repeat(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("repeat", args, 1, Some(2))?;
+    Ok(match args {
+        [x] => lazy(Step::Repeat(x.clone(), None)),
+        [n, x, ..] => lazy(Step::Repeat(x.clone(), Some(expect_index("repeat", n)?))),
+        [] => unreachable!(),
+    })
+}
+
+fn take(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let n = expect_index("take", &args[0])?;
+    Ok(lazy(Step::Take(n, vm.seq(&args[1])?)))
+}
+
+fn drop(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let n = expect_index("drop", &args[0])?;
+    Ok(lazy(Step::Drop(n, vm.seq(&args[1])?)))
+}
+
+/// `(cons x coll)` is the sequence of `x` followed by the items of `coll`,
+/// which is not realized. With `lazy-seq` it builds infinite sequences.
+fn cons(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(lazy(Step::Cons(args[0].clone(), vm.seq(&args[1])?)))
+}
+
+fn first(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let seq = vm.seq(&args[0])?;
+    Ok(vm.next_item(&seq)?.map_or(Value::Nil, |(item, _)| item))
+}
+
+fn rest(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let seq = vm.seq(&args[0])?;
+    let rest = match vm.next_item(&seq)? {
+        Some((_, rest)) => rest,
+        None => LazySeq::new(Step::Empty),
+    };
+    Ok(Value::Obj(Box::new(Object::LazySeq(rest))))
+}
+
+fn is_empty(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let seq = vm.seq(&args[0])?;
+    Ok(Value::Bool(vm.next_item(&seq)?.is_none()))
+}
+
+fn is_lazy_seq(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(Value::Bool(args[0].get_lazy_seq().is_some()))
+}
+
+/// `(vec coll)` realizes a sequence into a vector.
+fn vec(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let items = vm.collect_seq(std::mem::take(&mut args[0]))?;
+    Ok(Value::Obj(Box::new(Object::Vector(Rc::new(items)))))
+}
+
+/// Builds the result of `map` or `filter`, which is lazy when `coll` is.
+fn transform(vm: &mut VirtualMachine, coll: &Value, step: Step) -> Result<Value, VMErr> {
+    let result = lazy(step);
+    if coll.get_lazy_seq().is_some() {
+        return Ok(result);
+    }
+    let items = vm.collect_seq(result)?;
+    Ok(like(coll, items))
+}
+
+fn map(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let seq = vm.seq(&args[1])?;
+    transform(vm, &args[1], Step::Map(args[0].clone(), seq))
+}
+
+fn filter(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let seq = vm.seq(&args[1])?;
+    transform(vm, &args[1], Step::Filter(args[0].clone(), seq))
+}
+
+/// `(reduce f init coll)` folds `coll` from the left, `(reduce f coll)`
+/// starts with its first item.
+fn reduce(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("reduce", args, 2, Some(3))?;
+    let last = args.len() - 1;
+    let mut seq = vm.seq(&std::mem::take(&mut args[last]))?;
+    let f = &args[0];
+    let mut acc = if args.len() == 3 {
+        args[1].clone()
+    } else {
+        match vm.next_item(&seq)? {
+            Some((item, rest)) => {
+                seq = rest;
+                item
+            }
+            None => return Ok(Value::Nil),
+        }
+    };
+    while let Some((item, rest)) = vm.next_item(&seq)? {
+        acc = vm.call(f, &[acc, item])?;
+        seq = rest;
+    }
+    Ok(acc)
+}
+
+/// `(for-each f coll)` calls `f` on every item for its side effects.
+fn for_each(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let mut seq = vm.seq(&std::mem::take(&mut args[1]))?;
+    while let Some((item, rest)) = vm.next_item(&seq)? {
+        vm.call(&args[0], &[item])?;
+        seq = rest;
+    }
+    Ok(Value::Nil)
+}
@@ -0,0 +1,159 @@
+use std::rc::Rc;
+
+use crate::chunk::object::Object;
+use crate::chunk::value::Value;
+use crate::compiler::parse_number;
+use crate::vm::natives::{check_arity, expect_index, expect_str, text, type_error};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+pub fn register(vm: &mut VirtualMachine) {
+    vm.define_native("str", None, str_values);
+    vm.define_native("str-len", Some(1), str_len);
+    vm.define_native("substr", None, substr);
+    vm.define_native("str-concat", None, str_concat);
+    vm.define_native("str-split", Some(2), str_split);
+    vm.define_native("str-join", None, str_join);
+    vm.define_native("str-upper", Some(1), str_upper);
+    vm.define_native("str-lower", Some(1), str_lower);
+    vm.define_native("str-trim", Some(1), str_trim);
+    vm.define_native("str-index-of", None, str_index_of);
+    vm.define_native("str-replace", Some(3), str_replace);
+    vm.define_native("str->number", Some(1), str_to_number);
+    vm.define_native("number->str", Some(1), number_to_str);
+}
+
+fn string(s: impl Into<Rc<str>>) -> Value {
+    Value::Obj(Box::new(Object::Str(s.into())))
+}
+
+/// `(str value ...)` concatenates the printed form of its arguments.
+fn str_values(vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if let [value] = args {
+        if value.get_str().is_some() {
+            return Ok(value.clone());
+        }
+    }
+    let args = vm.realize_all(args)?;
+    Ok(string(args.iter().map(text).collect::<String>()))
+}
+
+fn str_len(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let s = expect_str("str-len", &args[0])?;
+    Ok(Value::Int(s.chars().count() as i64))
+}
+
+/// `(substr s start end?)` takes the characters from `start` up to, but not
+/// including, `end` (the end of the string by default).
+fn substr(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("substr", args, 2, Some(3))?;
+    let s = expect_str("substr", &args[0])?;
+    let len = s.chars().count();
+    let start = expect_index("substr", &args[1])?;
+    let end = match args.get(2) {
+        Some(end) => expect_index("substr", end)?,
+        None => len,
+    };
+    if start > end || end > len {
+        return Err(VMErr::RuntimeError(
+            ErrorKind::Runtime,
+            format!(
+                "substr: range {}..{} out of bounds for length {}",
+                start, end, len
+            ),
+        ));
+    }
+    Ok(string(
+        s.chars().skip(start).take(end - start).collect::<String>(),
+    ))
+}
+
+fn str_concat(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let mut result = String::new();
+    for arg in args {
+        result.push_str(expect_str("str-concat", arg)?);
+    }
+    Ok(string(result))
+}
+
+/// `(str-split s sep)` returns a vector of the pieces of `s` between each
+/// `sep`. An empty separator splits `s` into its characters.
+fn str_split(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let s = expect_str("str-split", &args[0])?;
+    let sep = expect_str("str-split", &args[1])?;
+    let pieces = if sep.is_empty() {
+        s.chars().map(|c| string(c.to_string())).collect()
+    } else {
+        s.split(sep).map(string).collect()
+    };
+    Ok(Value::Obj(Box::new(Object::Vector(Rc::new(pieces)))))
+}
+
+/// `(str-join coll sep?)` concatenates the printed form of the elements of
+/// `coll`, separated by `sep`.
+fn str_join(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("str-join", args, 1, Some(2))?;
+    let coll = match args[0].get_vector().or_else(|| args[0].get_list()) {
+        Some(coll) => coll,
+        None => return type_error("str-join", "a sequence", &args[0]),
+    };
+    let sep = match args.get(1) {
+        Some(sep) => expect_str("str-join", sep)?,
+        None => "",
+    };
+    let pieces: Vec<String> = coll.iter().map(text).collect();
+    Ok(string(pieces.join(sep)))
+}
+
+fn str_upper(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(string(expect_str("str-upper", &args[0])?.to_uppercase()))
+}
+
+fn str_lower(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(string(expect_str("str-lower", &args[0])?.to_lowercase()))
+}
+
+fn str_trim(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    Ok(string(expect_str("str-trim", &args[0])?.trim()))
+}
+
+/// `(str-index-of s sub from?)` returns the character index of the first
+/// occurrence of `sub` at or after `from`, or nil when there is none.
+fn str_index_of(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    check_arity("str-index-of", args, 2, Some(3))?;
+    let s = expect_str("str-index-of", &args[0])?;
+    let sub = expect_str("str-index-of", &args[1])?;
+    let from = match args.get(2) {
+        Some(from) => expect_index("str-index-of", from)?,
+        None => 0,
+    };
+    let offset = match s.char_indices().nth(from) {
+        Some((offset, _)) => offset,
+        None if from == s.chars().count() => s.len(),
+        None => return Ok(Value::Nil),
+    };
+    Ok(match s[offset..].find(sub) {
+        Some(found) => Value::Int((from + s[offset..offset + found].chars().count()) as i64),
+        None => Value::Nil,
+    })
+}
+
+fn str_replace(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let s = expect_str("str-replace", &args[0])?;
+    let from = expect_str("str-replace", &args[1])?;
+    let to = expect_str("str-replace", &args[2])?;
+    Ok(string(s.replace(from, to)))
+}
+
+/// `(str->number s)` parses `s` with the syntax of number literals, returning
+/// nil when it is not a number.
+fn str_to_number(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    let s = expect_str("str->number", &args[0])?;
+    Ok(parse_number(s.trim()).unwrap_or(Value::Nil))
+}
+
+fn number_to_str(_vm: &mut VirtualMachine, args: &mut [Value]) -> Result<Value, VMErr> {
+    if !args[0].is_number() {
+        return type_error("number->str", "a number", &args[0]);
+    }
+    Ok(string(args[0].to_string()))
+}
@@ -0,0 +1,36 @@
+use crate::chunk::Chunk;
+use crate::compiler::{compile, Compiler};
+use crate::vm::{natives, VirtualMachine};
+
+const SOURCE: &str = include_str!("prelude.flox");
+
+/// Defines the functions every program can use without requiring anything.
+/// `VirtualMachine::without_prelude` skips this for embedders that want to
+/// provide their own globals.
+pub fn load(vm: &mut VirtualMachine) {
+    natives::register_prelude(vm);
+    let mut chunk = Chunk::new("prelude");
+    compile(SOURCE, &mut chunk, &mut Compiler::new(None)).expect("the prelude compiles");
+    let debug = std::mem::replace(&mut vm.debug, false);
+    vm.run(&chunk).expect("the prelude runs");
+    vm.debug = debug;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_prelude() {
+        let vm = VirtualMachine::without_prelude(false);
+        assert!(vm.get_global("apply").is_none());
+        assert!(vm.get_global("identity").is_none());
+        assert!(vm.get_global("map").is_none());
+        assert!(vm.get_global("for-each").is_some());
+
+        let vm = VirtualMachine::new(false);
+        assert!(vm.get_global("apply").is_some());
+        assert!(vm.get_global("identity").is_some());
+        assert!(vm.get_global("map").is_some());
+    }
+}
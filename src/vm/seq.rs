@@ -0,0 +1,301 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::lazy::{LazySeq, Step};
+use crate::chunk::object::Object;
+use crate::chunk::value::{sorted_entries, Value};
+use crate::vm::{ErrorKind, VMErr, VirtualMachine};
+
+/// Wraps a step in a lazy sequence value.
+pub fn lazy(step: Step) -> Value {
+    Value::Obj(Box::new(Object::LazySeq(LazySeq::new(step))))
+}
+
+/// The iteration protocol: every collection can be walked as a sequence. Nil
+/// is the empty sequence, maps are sequences of `[key value]` vectors in key
+/// order and strings are sequences of characters.
+impl VirtualMachine {
+    pub fn seq(&self, value: &Value) -> Result<Rc<LazySeq>, VMErr> {
+        // lists and vectors are walked in place, without copying their items
+        let items = match value {
+            Value::Nil => Rc::new(Vec::new()),
+            Value::Obj(obj) => match &**obj {
+                Object::LazySeq(seq) => return Ok(seq.clone()),
+                Object::List(items) | Object::Vector(items) => items.clone(),
+                Object::Str(s) => Rc::new(s.chars().map(Value::Char).collect()),
+                Object::Map(map) => Rc::new(
+                    sorted_entries(map)
+                        .map(|(k, v)| {
+                            Value::Obj(Box::new(Object::Vector(Rc::new(vec![
+                                k.clone(),
+                                v.clone(),
+                            ]))))
+                        })
+                        .collect(),
+                ),
+                _ => return Err(cannot_iterate(value)),
+            },
+            _ => return Err(cannot_iterate(value)),
+        };
+        Ok(LazySeq::new(Step::Items(items, 0)))
+    }
+
+    /// Realizes the first item of a sequence, returning it with the sequence
+    /// of the rest, or `None` when the sequence is empty.
+    pub fn next_item(&mut self, seq: &Rc<LazySeq>) -> Result<Option<(Value, Rc<LazySeq>)>, VMErr> {
+        let step = seq.step.borrow().clone();
+        let next = match step {
+            Step::Empty => return Ok(None),
+            Step::Cons(first, rest) => return Ok(Some((first, rest))),
+            Step::Thunk(body) => {
+                let value = self.call(&body, &[])?;
+                let inner = self.seq(&value)?;
+                self.next_item(&inner)?
+            }
+            Step::Items(items, index) => items.get(index).map(|item| {
+                (
+                    item.clone(),
+                    LazySeq::new(Step::Items(items.clone(), index + 1)),
+                )
+            }),
+            Step::Range { start, end, step } => {
+                let done = match &end {
+                    Some(end) => {
+                        let direction = step
+                            .compare(&Value::Int(0))
+                            .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?;
+                        let position = start
+                            .compare(end)
+                            .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?;
+                        match (direction, position) {
+                            (Some(Ordering::Greater), Some(Ordering::Less)) => false,
+                            (Some(Ordering::Less), Some(Ordering::Greater)) => false,
+                            // a zero step repeats the start forever
+                            (Some(Ordering::Equal), Some(position)) => position.is_eq(),
+                            _ => true,
+                        }
+                    }
+                    None => false,
+                };
+                if done {
+                    None
+                } else {
+                    let next = start
+                        .checked_add(&step)
+                        .map_err(|message| VMErr::RuntimeError(ErrorKind::Type, message))?;
+                    let rest = Step::Range {
+                        start: next,
+                        end,
+                        step,
+                    };
+                    Some((start, LazySeq::new(rest)))
+                }
+            }
+            Step::Iterate { f, x, applied } => {
+                let item = if applied { self.call(&f, &[x])? } else { x };
+                let rest = Step::Iterate {
+                    f,
+                    x: item.clone(),
+                    applied: true,
+                };
+                Some((item, LazySeq::new(rest)))
+            }
+            Step::Repeat(_, Some(0)) => None,
+            Step::Repeat(x, n) => {
+                let rest = Step::Repeat(x.clone(), n.map(|n| n - 1));
+                Some((x, LazySeq::new(rest)))
+            }
+            Step::Take(0, _) => None,
+            Step::Take(n, inner) => self
+                .next_item(&inner)?
+                .map(|(item, rest)| (item, LazySeq::new(Step::Take(n - 1, rest)))),
+            Step::Drop(n, inner) => {
+                let mut inner = inner;
+                for _ in 0..n {
+                    match self.next_item(&inner)? {
+                        Some((_, rest)) => inner = rest,
+                        None => break,
+                    }
+                }
+                self.next_item(&inner)?
+            }
+            Step::Map(f, inner) => match self.next_item(&inner)? {
+                Some((item, rest)) => {
+                    let mapped = self.call(&f, &[item])?;
+                    Some((mapped, LazySeq::new(Step::Map(f, rest))))
+                }
+                None => None,
+            },
+            Step::Filter(f, inner) => {
+                let mut inner = inner;
+                loop {
+                    match self.next_item(&inner)? {
+                        Some((item, rest)) => {
+                            if self.test(&f, item.clone())? {
+                                break Some((item, LazySeq::new(Step::Filter(f, rest))));
+                            }
+                            inner = rest;
+                        }
+                        None => break None,
+                    }
+                }
+            }
+        };
+        *seq.step.borrow_mut() = match &next {
+            Some((first, rest)) => Step::Cons(first.clone(), rest.clone()),
+            None => Step::Empty,
+        };
+        Ok(next)
+    }
+
+    /// Realizes a whole sequence. Does not return for infinite ones. Takes
+    /// the value so that the items already collected can be freed when
+    /// nothing else holds the start of the sequence.
+    pub fn collect_seq(&mut self, value: Value) -> Result<Vec<Value>, VMErr> {
+        let mut seq = self.seq(&value)?;
+        drop(value);
+        let mut items = Vec::new();
+        while let Some((item, rest)) = self.next_item(&seq)? {
+            items.push(item);
+            seq = rest;
+        }
+        Ok(items)
+    }
+
+    /// Replaces the lazy sequences in a value, also those nested in lists,
+    /// vectors and maps, with lists of their items so that the value can be
+    /// printed. Does not return for infinite sequences.
+    pub fn realize(&mut self, value: &Value) -> Result<Value, VMErr> {
+        let realized = match value {
+            Value::Obj(obj) => match &**obj {
+                Object::LazySeq(_) => {
+                    let items = self.collect_seq(value.clone())?;
+                    Object::List(self.realize_items(&items)?)
+                }
+                Object::List(items) => Object::List(self.realize_items(items)?),
+                Object::Vector(items) => Object::Vector(self.realize_items(items)?),
+                Object::Map(map) => {
+                    let mut entries = HashMap::with_capacity(map.len());
+                    for (k, v) in map.iter() {
+                        entries.insert(self.realize(k)?, self.realize(v)?);
+                    }
+                    Object::Map(Rc::new(entries))
+                }
+                _ => return Ok(value.clone()),
+            },
+            _ => return Ok(value.clone()),
+        };
+        Ok(Value::Obj(Box::new(realized)))
+    }
+
+    pub fn realize_all(&mut self, values: &[Value]) -> Result<Vec<Value>, VMErr> {
+        values.iter().map(|value| self.realize(value)).collect()
+    }
+
+    fn realize_items(&mut self, items: &[Value]) -> Result<Rc<Vec<Value>>, VMErr> {
+        Ok(Rc::new(self.realize_all(items)?))
+    }
+
+    /// The first `count` items of a sequence, nil past its end, and the
+    /// sequence of the other items. Lists and vectors keep their kind.
+    pub fn unpack(&mut self, value: &Value, count: usize) -> Result<(Vec<Value>, Value), VMErr> {
+        if let Some(items) = value.get_list().or(value.get_vector()) {
+            let mut head: Vec<Value> = items.iter().take(count).cloned().collect();
+            head.resize(count, Value::Nil);
+            let rest = Rc::new(items[count.min(items.len())..].to_vec());
+            let rest = if value.get_list().is_some() {
+                Object::List(rest)
+            } else {
+                Object::Vector(rest)
+            };
+            return Ok((head, Value::Obj(Box::new(rest))));
+        }
+        let mut seq = self.seq(value).map_err(|_| {
+            VMErr::RuntimeError(
+                ErrorKind::Type,
+                format!("Cannot destructure {} as a sequence", value),
+            )
+        })?;
+        let mut head = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.next_item(&seq)? {
+                Some((item, rest)) => {
+                    head.push(item);
+                    seq = rest;
+                }
+                None => head.push(Value::Nil),
+            }
+        }
+        Ok((head, Value::Obj(Box::new(Object::LazySeq(seq)))))
+    }
+
+    /// Calls a predicate, which has to return a boolean.
+    pub fn test(&mut self, predicate: &Value, item: Value) -> Result<bool, VMErr> {
+        let result = self.call(predicate, &[item])?;
+        result.get_bool().ok_or_else(|| {
+            VMErr::RuntimeError(
+                ErrorKind::Type,
+                format!("Cannot use {} as a boolean", result),
+            )
+        })
+    }
+}
+
+fn cannot_iterate(value: &Value) -> VMErr {
+    VMErr::RuntimeError(ErrorKind::Type, format!("Cannot iterate over {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collections_are_seqs() {
+        let mut vm = VirtualMachine::new(false);
+        let vector = Value::Obj(Box::new(Object::Vector(Rc::new(vec![
+            Value::Int(1),
+            Value::Int(2),
+        ]))));
+        assert_eq!(vm.collect_seq(vector).unwrap().len(), 2);
+        assert_eq!(
+            vm.collect_seq(Value::Obj(Box::new(Object::Str("ab".into()))))
+                .unwrap(),
+            vec![Value::Char('a'), Value::Char('b')]
+        );
+        assert!(vm.collect_seq(Value::Nil).unwrap().is_empty());
+        assert!(vm.seq(&Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn test_unpack() {
+        let mut vm = VirtualMachine::new(false);
+        let list = Value::Obj(Box::new(Object::List(Rc::new(vec![
+            Value::Int(1),
+            Value::Int(2),
+        ]))));
+        let (head, rest) = vm.unpack(&list, 1).unwrap();
+        assert_eq!(head, vec![Value::Int(1)]);
+        assert_eq!(rest.get_list(), Some(&vec![Value::Int(2)]));
+        let (head, _) = vm.unpack(&list, 3).unwrap();
+        assert_eq!(head, vec![Value::Int(1), Value::Int(2), Value::Nil]);
+        assert_eq!(vm.unpack(&Value::Nil, 1).unwrap().0, vec![Value::Nil]);
+        assert!(vm.unpack(&Value::Int(1), 1).is_err());
+    }
+
+    #[test]
+    fn test_realized_once() {
+        let mut vm = VirtualMachine::new(false);
+        let seq = LazySeq::new(Step::Range {
+            start: Value::Int(0),
+            end: None,
+            step: Value::Int(1),
+        });
+        let (first, rest) = vm.next_item(&seq).unwrap().unwrap();
+        assert_eq!(first, Value::Int(0));
+        assert!(matches!(*seq.step.borrow(), Step::Cons(_, _)));
+        let (again, _) = vm.next_item(&seq).unwrap().unwrap();
+        assert_eq!(again, Value::Int(0));
+        assert_eq!(vm.next_item(&rest).unwrap().unwrap().0, Value::Int(1));
+    }
+}